@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+
+declare_id!("DFTSgagmqyEagXgegxpTgLw3Y57ZZKwrCULnzWK3gHQP");
+
+pub const GUARD_SEED: &[u8] = b"profit_guard";
+
+// Wrap an arbitrage transaction: record_start_balance as first instruction, check_profit as last one
+#[program]
+pub mod profit_guard {
+    use super::*;
+
+    // Save the balance of the base token account of the payer before the swaps
+    pub fn record_start_balance(ctx: Context<RecordStartBalance>) -> Result<()> {
+        let guard = &mut ctx.accounts.guard;
+        guard.payer = ctx.accounts.payer.key();
+        guard.token_account = ctx.accounts.token_account.key();
+        guard.start_balance = ctx.accounts.token_account.amount;
+        guard.active = true;
+        guard.bump = ctx.bumps.guard;
+        Ok(())
+    }
+
+    // Fail the whole transaction if the base token balance didn't increase of at least min_profit
+    pub fn check_profit(ctx: Context<CheckProfit>, min_profit: u64) -> Result<()> {
+        let guard = &mut ctx.accounts.guard;
+        require!(guard.active, ProfitGuardError::StartBalanceNotRecorded);
+        let end_balance = ctx.accounts.token_account.amount;
+        msg!("Profit guard: start {} end {} min profit {}", guard.start_balance, end_balance, min_profit);
+        require_min_profit(guard.start_balance, end_balance, min_profit)?;
+        guard.active = false;
+        Ok(())
+    }
+}
+
+// end_balance >= start_balance + min_profit
+pub fn require_min_profit(start_balance: u64, end_balance: u64, min_profit: u64) -> Result<()> {
+    let min_end_balance = start_balance.checked_add(min_profit).ok_or(ProfitGuardError::MathOverflow)?;
+    require!(end_balance >= min_end_balance, ProfitGuardError::NotProfitable);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RecordStartBalance<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Guard::INIT_SPACE,
+        seeds = [GUARD_SEED, payer.key().as_ref()],
+        bump
+    )]
+    pub guard: Account<'info, Guard>,
+    #[account(token::authority = payer)]
+    pub token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CheckProfit<'info> {
+    #[account(
+        mut,
+        seeds = [GUARD_SEED, payer.key().as_ref()],
+        bump = guard.bump,
+        has_one = payer,
+        has_one = token_account @ ProfitGuardError::WrongTokenAccount
+    )]
+    pub guard: Account<'info, Guard>,
+    pub token_account: Account<'info, TokenAccount>,
+    pub payer: Signer<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Guard {
+    pub payer: Pubkey,
+    pub token_account: Pubkey,
+    pub start_balance: u64,
+    // Set by record_start_balance, reset by check_profit
+    pub active: bool,
+    pub bump: u8,
+}
+
+#[error_code]
+#[derive(PartialEq)]
+pub enum ProfitGuardError {
+    #[msg("End balance lower than start balance + min profit")]
+    NotProfitable,
+
+    #[msg("record_start_balance must be called before check_profit")]
+    StartBalanceNotRecorded,
+
+    #[msg("Token account different from the recorded one")]
+    WrongTokenAccount,
+
+    #[msg("Math operation overflow")]
+    MathOverflow,
+}
@@ -1,10 +1,15 @@
 use std::collections::{HashMap, HashSet};
 use log::{debug, info};
+use crate::common::prices::get_market_liquidity_usd;
 use crate::markets::types::{Dex, DexLabel, Market};
 use crate::arbitrage::types::{TokenInArb, Route, SwapPath};
+use crate::strategies::pool_discovery::{get_discovered_pools, DiscoveredPools};
 use crate::strategies::pools::get_fresh_pools;
 
-pub async fn get_markets_arb(get_fresh_pools_bool: bool, restrict_sol_usdc: bool, dexs: Vec<Dex>, tokens: Vec<TokenInArb>) -> HashMap<String, Market> {
+// Min. liquidity of the markets kept in the paths
+pub const MIN_LIQUIDITY_USD: f64 = 2000.0;
+
+pub async fn get_markets_arb(get_fresh_pools_bool: bool, restrict_sol_usdc: bool, dexs: Vec<Dex>, tokens: Vec<TokenInArb>, discovered_pools: Option<DiscoveredPools>) -> HashMap<String, Market> {
 
     let sol_addr = format!("So11111111111111111111111111111111111111112");
     let usdc_addr = format!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
@@ -60,6 +65,17 @@ pub async fn get_markets_arb(get_fresh_pools_bool: bool, restrict_sol_usdc: bool
         }
         info!("👀 {} new markets founded !", count_new_pools);
     }
+    // Pools found by the programSubscribe discovery service
+    if let Some(discovered_pools) = discovered_pools {
+        let mut count_discovered_pools = 0;
+        for (key, market) in get_discovered_pools(&discovered_pools) {
+            if token_addresses.contains(&market.tokenMintA) && token_addresses.contains(&market.tokenMintB) && !markets_arb.contains_key(&key) {
+                markets_arb.insert(key, market);
+                count_discovered_pools += 1;
+            }
+        }
+        info!("🔭 {} discovered markets added !", count_discovered_pools);
+    }
 
     return markets_arb;
 }
@@ -83,7 +99,7 @@ pub fn calculate_arb(include_1hop: bool, include_2hop: bool, markets_arb: HashMa
                 excluded_markets_arb.push(key);
             },
             DexLabel::ORCA_WHIRLPOOLS => {
-                if market.liquidity.unwrap() >= 2000000000 { // 2000$ with 6 decimals, not sure 
+                if has_enough_liquidity(&market) {
                     sorted_markets_arb.insert(key, market);
                 } else {
                     excluded_markets_arb.push(key);
@@ -92,15 +108,8 @@ pub fn calculate_arb(include_1hop: bool, include_2hop: bool, markets_arb: HashMa
             DexLabel::RAYDIUM_CLMM => {
                 excluded_markets_arb.push(key);
             },
-            DexLabel::RAYDIUM => {
-                if market.liquidity.unwrap() >= 2000 { //If liquidity more than 2000$
-                    sorted_markets_arb.insert(key, market);
-                } else {
-                    excluded_markets_arb.push(key);
-                }
-            },
-            DexLabel::METEORA => {
-                if market.liquidity.unwrap() >= 2000 { //If liquidity more than 2000$
+            DexLabel::RAYDIUM | DexLabel::METEORA => {
+                if has_enough_liquidity(&market) {
                     sorted_markets_arb.insert(key, market);
                 } else {
                     excluded_markets_arb.push(key);
@@ -137,6 +146,33 @@ pub fn calculate_arb(include_1hop: bool, include_2hop: bool, markets_arb: HashMa
     return (sorted_markets_arb, all_paths);
 }
 
+// Liquidity over MIN_LIQUIDITY_USD, a market without known liquidity is excluded
+pub fn has_enough_liquidity(market: &Market) -> bool {
+    match market.dexLabel {
+        DexLabel::ORCA_WHIRLPOOLS => {
+            // USD value of the virtual reserves when prices and pool state are known
+            match get_market_liquidity_usd(market) {
+                Some(liquidity_usd) => liquidity_usd >= MIN_LIQUIDITY_USD,
+                None => market.liquidity.map_or(false, |liquidity| liquidity >= 2000000000), // 2000$ with 6 decimals, not sure 
+            }
+        }
+        //If liquidity more than 2000$
+        DexLabel::RAYDIUM | DexLabel::METEORA => market.liquidity.map_or(false, |liquidity| liquidity as f64 >= MIN_LIQUIDITY_USD),
+        DexLabel::ORCA | DexLabel::RAYDIUM_CLMM => false,
+    }
+}
+
+// Paths of `markets` going through `pool`, for a pool added while the strategies run
+// Route ids start at `first_route_id` to not collide with the ids of the paths already simulated
+pub fn get_paths_through_pool(include_1hop: bool, include_2hop: bool, markets: HashMap<String, Market>, pool: &String, tokens: Vec<TokenInArb>, first_route_id: u32) -> Vec<SwapPath> {
+    let all_routes: Vec<Route> = compute_routes(markets).into_iter()
+        .map(|route| Route { id: route.id + first_route_id, ..route })
+        .collect();
+    generate_swap_paths(include_1hop, include_2hop, all_routes, tokens).into_iter()
+        .filter(|path| path.paths.iter().any(|route| &route.pool_address == pool))
+        .collect()
+}
+
 //Compute routes 
 pub fn compute_routes(markets_arb: HashMap<String, Market>) -> Vec<Route> {
     let mut all_routes: Vec<Route> = Vec::new();
@@ -1,5 +1,6 @@
 pub mod calc_arb;
 pub mod types;
 pub mod streams;
+pub mod subscriptions;
 pub mod strategies;
 pub mod simulate;
\ No newline at end of file
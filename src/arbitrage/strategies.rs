@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs::{File, OpenOptions}, thread::sleep, time::{self, SystemTime}};
+use std::{collections::HashMap, fs::{File, OpenOptions}, time::{Duration, Instant, SystemTime}};
 use borsh::error;
 use chrono::{Datelike, Utc};
 use indicatif::{ProgressBar, ProgressStyle};
@@ -8,9 +8,15 @@ use rust_socketio::{asynchronous::{Client}};
 use solana_sdk::pubkey::Pubkey;
 use std::io::{BufWriter, Write};
 use crate::{arbitrage::{
-    calc_arb::{calculate_arb, get_markets_arb}, simulate::simulate_path, streams::get_fresh_accounts_states, types::{SwapPathResult, SwapPathSelected, SwapRouteSimulation, VecSwapPathResult, VecSwapPathSelected}
-}, common::{database::{insert_vec_swap_path_selected_collection, insert_swap_path_result_collection}, utils::{from_str, write_file_swap_path_result}}, transactions::create_transaction::{self, create_and_send_swap_transaction, create_ata_extendlut_transaction, ChainType, SendOrSimulate}};
-use crate::markets::types::{Dex,Market};
+    calc_arb::{calculate_arb, get_markets_arb, get_paths_through_pool, has_enough_liquidity}, simulate::simulate_path, streams::{get_fresh_accounts_states, get_slot_range, is_slot_consistent, is_stale}, types::{SwapPathResult, SwapPathSelected, SwapRouteSimulation, VecSwapPathResult, VecSwapPathSelected}
+}, common::{database::{insert_vec_swap_path_selected_collection, insert_swap_path_result_collection}, prices::{get_usd_value, refresh_pool_prices}, types::InputVec, utils::{from_str, get_tokens_infos, write_file_swap_path_result}}, markets::swap_events::{get_price_move, subscribe_swap_events, SwapEvent}, transactions::{blockhash::get_current_slot_cached, broadcaster::Broadcaster, create_transaction::{self, create_and_send_swap_transaction, create_ata_extendlut_transaction, ChainType, SendOrSimulate}, jito::BundleSender, leader_sender::LeaderSender, priority_fees::PriorityFeePolicy, signer::SignerService, slippage::SlippagePolicy}};
+use crate::common::constants::Env;
+use crate::data::candles::{get_market_price, CandleBuilder, CANDLES_PATH, CANDLES_SAVE_INTERVAL};
+use crate::markets::meteora::{get_oracle_dislocation_cached, run_oracles_service};
+use crate::markets::types::{Dex, DexLabel, Market};
+use crate::strategies::pool_discovery::{get_discovered_pools, subscribe_discovered_pools, DiscoveredPools};
+use tokio::sync::broadcast;
+use super::subscriptions::SubscriptionManager;
 use super::{simulate::simulate_path_precision, types::{SwapPath, TokenInArb, TokenInfos}};
 use log::{debug, error, info};
 use anyhow::Result;
@@ -18,10 +24,10 @@ use anyhow::Result;
 use tokio::net::TcpStream;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-pub async fn run_arbitrage_strategy(simulation_amount: u64, get_fresh_pools_bool: bool, restrict_sol_usdc: bool, include_1hop: bool, include_2hop: bool, numbers_of_best_paths: usize, dexs: Vec<Dex>, tokens: Vec<TokenInArb>, tokens_infos: HashMap<String, TokenInfos>) -> Result<(String, VecSwapPathSelected)> {
+pub async fn run_arbitrage_strategy(simulation_amount: u64, get_fresh_pools_bool: bool, restrict_sol_usdc: bool, include_1hop: bool, include_2hop: bool, numbers_of_best_paths: usize, dexs: Vec<Dex>, tokens: Vec<TokenInArb>, tokens_infos: HashMap<String, TokenInfos>, discovered_pools: Option<DiscoveredPools>, max_slot_window: u64) -> Result<(String, VecSwapPathSelected)> {
     info!("👀 Run Arbitrage Strategies...");
 
-    let markets_arb = get_markets_arb(get_fresh_pools_bool, restrict_sol_usdc, dexs, tokens.clone()).await;
+    let markets_arb = get_markets_arb(get_fresh_pools_bool, restrict_sol_usdc, dexs, tokens.clone(), discovered_pools).await;
 
     // println!("DEBUG {:?}", fresh_markets_arb);
     // debug!("DEBUG {:?}", markets_arb.get(&"3s3CzbFzkqLvXYA93M3uHCes2nc4SiuZ11emtpDJwCht".to_string()));
@@ -31,7 +37,11 @@ pub async fn run_arbitrage_strategy(simulation_amount: u64, get_fresh_pools_bool
     let (sorted_markets_arb, all_paths) = calculate_arb(include_1hop, include_2hop, markets_arb.clone(), tokens.clone());
 
     //Get fresh account state
-    let fresh_markets_arb = get_fresh_accounts_states(sorted_markets_arb.clone()).await;  
+    let mut fresh_markets_arb = get_fresh_accounts_states(sorted_markets_arb.clone(), None).await;  
+
+    // USD prices of the long-tail tokens from their pools
+    let counter_pool_prices = refresh_pool_prices(&fresh_markets_arb.values().cloned().collect(), &tokens_infos);
+    info!("💵 {} tokens priced from pools", counter_pool_prices);
     
     // We keep route simulation result for RPC optimization
     let mut route_simulation: HashMap<Vec<u32>, Vec<SwapRouteSimulation>> = HashMap::new();
@@ -80,6 +90,15 @@ pub async fn run_arbitrage_strategy(simulation_amount: u64, get_fresh_pools_bool
         let pubkeys: Vec<String> = path.paths.clone().iter().map(|route| route.clone().pool_address).collect();
         let markets: Vec<Market> = pubkeys.iter().filter_map(|key| fresh_markets_arb.get(key)).cloned().collect();
 
+        // Only simulate paths where all the accounts are in the same slot window
+        if !is_slot_consistent(&markets, max_slot_window) {
+            error!("🔴⏭️  Skip the {:?} path because accounts are not in a {} slots window: {:?}", path.id_paths, max_slot_window, get_slot_range(&markets));
+            bar.inc(1);
+            counter_failed_paths += 1;
+            bar.set_message(format!("❌ Failed routes: {}/{} 💸 Positive routes: {}/{}", counter_failed_paths, bar.position(), counter_positive_paths, bar.position()));
+            continue;
+        }
+
         let (new_route_simulation, swap_simulation_result, result_difference) = simulate_path(simulation_amount, path.clone(), markets.clone(), tokens_infos.clone(), route_simulation.clone()).await;
         
         //If no error in swap path
@@ -101,31 +120,57 @@ pub async fn run_arbitrage_strategy(simulation_amount: u64, get_fresh_pools_bool
                 amount_in: swap_simulation_result[0].amount_in.clone(), 
                 estimated_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_amount_out.clone(), 
                 estimated_min_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_min_amount_out.clone(), 
-                result: result_difference
+                result: result_difference,
+                slot: get_slot_range(&markets).map(|(oldest, _)| oldest),
+                result_usd: get_usd_value(&tokens[0].address, result_difference)
             };
             swap_paths_results.result.push(sp_result.clone());
 
+            // Opportunities on accounts older than max_slot_window are simulated again on refetched accounts
+            let mut sp_result_to_send: Option<SwapPathResult> = None;
             if result_difference > 20000000.0 {
+                // Slot of the blockhash service, no RPC call
+                let stale_data = match get_current_slot_cached() {
+                    Some(current_slot) => is_stale(&markets, current_slot, max_slot_window),
+                    None => true,
+                };
+                if !stale_data {
+                    sp_result_to_send = Some(sp_result.clone());
+                } else {
+                    info!("🔄 Refetch the accounts of the {:?} opportunity, older than {} slots", path.id_paths, max_slot_window);
+                    match simulate_path_on_fresh_accounts(simulation_amount, path, &markets, &tokens_infos).await {
+                        Some((fresh_markets, fresh_simulation_result, fresh_result_difference)) => {
+                            for market in fresh_markets.iter() {
+                                fresh_markets_arb.insert(market.id.clone(), market.clone());
+                            }
+                            if fresh_result_difference > 20000000.0 {
+                                sp_result_to_send = Some(SwapPathResult{
+                                    route_simulations: fresh_simulation_result.clone(),
+                                    amount_in: fresh_simulation_result[0].amount_in.clone(),
+                                    estimated_amount_out: fresh_simulation_result[fresh_simulation_result.len() - 1].estimated_amount_out.clone(),
+                                    estimated_min_amount_out: fresh_simulation_result[fresh_simulation_result.len() - 1].estimated_min_amount_out.clone(),
+                                    result: fresh_result_difference,
+                                    slot: get_slot_range(&fresh_markets).map(|(oldest, _)| oldest),
+                                    result_usd: get_usd_value(&tokens[0].address, fresh_result_difference),
+                                    ..sp_result.clone()
+                                });
+                            } else {
+                                info!("🔴⏭️  The {:?} opportunity is gone on fresh accounts (result: {})", path.id_paths, fresh_result_difference);
+                            }
+                        }
+                        None => {
+                            error!("🔴⏭️  Drop the {:?} opportunity, accounts not refetched", path.id_paths);
+                        }
+                    }
+                }
+            }
+            if let Some(sp_result) = sp_result_to_send {
                 println!("💸💸💸💸💸💸💸💸💸 Begin Execute the tx 💸💸💸💸💸💸💸💸💸");
                 info!("💸💸💸💸💸💸💸💸💸 Send transaction execution... 💸💸💸💸💸💸💸💸💸");
                 
-                let now = Utc::now();
-                let date = format!("{}-{}-{}", now.day(), now.month(), now.year());
-
-                let path = format!("optimism_transactions/{}-{}-{}.json", date, tokens_path.clone(), counter_sp_result);
                 let _ = insert_swap_path_result_collection("optimism_transactions", sp_result.clone()).await;  
-                let _ = write_file_swap_path_result(path.clone(), sp_result);
+                send_to_executor(sp_result, tokens_path.clone(), counter_sp_result).await?;
                 counter_sp_result += 1;
-                
-                //Send message to Rust execution program
-                let mut stream = TcpStream::connect("127.0.0.1:8080").await?;
-
-                let message = path.as_bytes();
-                stream.write_all(message).await?;
-                info!("🛜  Sent: {} tx to executor", String::from_utf8_lossy(message));
-                // let mut buffer = [0; 512];
-                // let n = stream.read(&mut buffer).await?;
-                // info!("Received: {}", String::from_utf8_lossy(&buffer[0..n]));
             }
 
             // Reset errors if one path is good to only skip paths on 3 consecutives errors
@@ -235,6 +280,243 @@ pub async fn run_arbitrage_strategy(simulation_amount: u64, get_fresh_pools_bool
     return Ok((return_path, VecSwapPathSelected{ value: best_paths_for_strat}));
 }
 
+// Write the SwapPathResult in optimism_transactions/ and send its path to the Rust execution program
+pub async fn send_to_executor(sp_result: SwapPathResult, tokens_path: String, counter_sp_result: usize) -> Result<String> {
+    let now = Utc::now();
+    let date = format!("{}-{}-{}", now.day(), now.month(), now.year());
+
+    let path = format!("optimism_transactions/{}-{}-{}.json", date, tokens_path, counter_sp_result);
+    let _ = write_file_swap_path_result(path.clone(), sp_result);
+
+    //Send message to Rust execution program
+    let mut stream = TcpStream::connect("127.0.0.1:8080").await?;
+
+    let message = path.as_bytes();
+    stream.write_all(message).await?;
+    info!("🛜  Sent: {} tx to executor", String::from_utf8_lossy(message));
+    // let mut buffer = [0; 512];
+    // let n = stream.read(&mut buffer).await?;
+    // info!("Received: {}", String::from_utf8_lossy(&buffer[0..n]));
+    Ok(path)
+}
+
+// Simulate again a path on its accounts refetched at a slot not older than `markets`
+// None if the accounts couldn't be refetched or the simulation failed
+async fn simulate_path_on_fresh_accounts(simulation_amount: u64, path: &SwapPath, markets: &Vec<Market>, tokens_infos: &HashMap<String, TokenInfos>) -> Option<(Vec<Market>, Vec<SwapRouteSimulation>, f64)> {
+    let oldest_slot = get_slot_range(markets).map(|(oldest, _)| oldest);
+    let accounts: HashMap<String, Market> = markets.iter().map(|market| (market.id.clone(), market.clone())).collect();
+    let fresh_accounts = get_fresh_accounts_states(accounts, oldest_slot).await;
+    let fresh_markets: Vec<Market> = markets.iter().filter_map(|market| fresh_accounts.get(&market.id)).cloned().collect();
+    // get_fresh_accounts_states() keeps the previous state of the accounts when the RPC fails
+    if fresh_markets.len() < markets.len() || fresh_markets.iter().zip(markets.iter()).any(|(fresh, previous)| fresh.slot <= previous.slot) {
+        return None;
+    }
+    // No cache of the route simulations, they were done on the previous state
+    let (_, swap_simulation_result, result_difference) = simulate_path(simulation_amount, path.clone(), fresh_markets.clone(), tokens_infos.clone(), HashMap::new()).await;
+    if swap_simulation_result.len() < path.hops as usize {
+        return None;
+    }
+    Some((fresh_markets, swap_simulation_result, result_difference))
+}
+
+// Event driven strategy: listen swaps on the pools of the paths of run_arbitrage_strategy(),
+// when a swap moves the price of a pool more than `price_move_threshold`, simulate all the paths through this pool
+// with the post-swap state and send the best one to the executor
+pub async fn run_backrun_strategy(simulation_amount: u64, restrict_sol_usdc: bool, dexs: Vec<Dex>, inputs_vec: Vec<InputVec>, discovered_pools: Option<DiscoveredPools>, max_slot_window: u64, price_move_threshold: f64, candles_interval_secs: i64, oracle_window_secs: i64) -> Result<()> {
+    info!("🎯 Run Backrun Strategy...");
+
+    // Pools discovered from now, the ones already discovered are in the markets of get_markets_arb()
+    let mut discovered_receiver = subscribe_discovered_pools();
+
+    // Same path set as run_arbitrage_strategy(), for each input
+    let mut paths_by_input: Vec<(Vec<TokenInArb>, HashMap<String, TokenInfos>, Vec<SwapPath>)> = Vec::new();
+    let mut markets_backrun: HashMap<String, Market> = HashMap::new();
+    let hops_by_input: Vec<(bool, bool)> = inputs_vec.iter().map(|input_iter| (input_iter.include_1hop, input_iter.include_2hop)).collect();
+    for input_iter in inputs_vec {
+        let markets_arb = get_markets_arb(input_iter.get_fresh_pools_bool, restrict_sol_usdc, dexs.clone(), input_iter.tokens_to_arb.clone(), discovered_pools.clone()).await;
+        let (sorted_markets_arb, all_paths) = calculate_arb(input_iter.include_1hop, input_iter.include_2hop, markets_arb, input_iter.tokens_to_arb.clone());
+        let tokens_infos: HashMap<String, TokenInfos> = get_tokens_infos(input_iter.tokens_to_arb.clone()).await;
+        markets_backrun.extend(sorted_markets_arb);
+        paths_by_input.push((input_iter.tokens_to_arb, tokens_infos, all_paths));
+    }
+    let mut markets_backrun = get_fresh_accounts_states(markets_backrun, None).await;
+    let tokens_infos_backrun: HashMap<String, TokenInfos> = paths_by_input.iter().flat_map(|(_, tokens_infos, _)| tokens_infos.clone()).collect();
+
+    // Dislocation of the Meteora pools from their oracle TWAP, a dislocated pool is searched even on small swaps
+    let meteora_markets: Vec<Market> = markets_backrun.values().filter(|market| market.dexLabel == DexLabel::METEORA).cloned().collect();
+    tokio::spawn(run_oracles_service(Env::new().rpc_url, meteora_markets, oracle_window_secs, Duration::from_secs(30)));
+
+    // Prices of the pools after each swap and volumes of the swap events, saved every CANDLES_SAVE_INTERVAL
+    let mut candle_builder = CandleBuilder::load(CANDLES_PATH).unwrap_or(CandleBuilder::new(candles_interval_secs, 1440));
+    let mut candles_saved_at = Instant::now();
+
+    // Paths through each pool: (input index, path index)
+    let mut paths_by_pool: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+    for (input_index, (_, _, all_paths)) in paths_by_input.iter().enumerate() {
+        for (path_index, path) in all_paths.iter().enumerate() {
+            for route in path.paths.iter() {
+                paths_by_pool.entry(route.pool_address.clone()).or_default().push((input_index, path_index));
+            }
+        }
+    }
+
+    // Only Raydium and Meteora swaps are decoded
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<(SwapEvent, Instant)>();
+    let (new_pools_sender, new_pools_receiver) = tokio::sync::mpsc::unbounded_channel::<(DexLabel, String)>();
+    let is_decoded = |market: &Market| market.dexLabel == DexLabel::RAYDIUM || market.dexLabel == DexLabel::METEORA;
+    let pools: Vec<(DexLabel, String)> = paths_by_pool.keys()
+        .filter_map(|pool| markets_backrun.get(pool))
+        .filter(|market| is_decoded(market))
+        .map(|market| (market.dexLabel.clone(), market.id.clone()))
+        .collect();
+    tokio::spawn(subscribe_swap_events(pools, new_pools_receiver, sender));
+
+    let mut counter_sp_result = 0;
+    loop {
+        let (swap_event, received_at) = tokio::select! {
+            swap_event = receiver.recv() => match swap_event {
+                Some(swap_event) => swap_event,
+                None => break,
+            },
+            discovered = discovered_receiver.recv() => {
+                match discovered {
+                    Ok(pool) => {
+                        // New paths through the pool for the inputs with its two tokens
+                        if markets_backrun.contains_key(&pool.id) || !has_enough_liquidity(&pool) {
+                            continue;
+                        }
+                        let fresh_pool = get_fresh_accounts_states(HashMap::from([(pool.id.clone(), pool.clone())]), None).await;
+                        let pool = fresh_pool.get(&pool.id).cloned().unwrap_or(pool);
+                        markets_backrun.insert(pool.id.clone(), pool.clone());
+                        for (input_index, (tokens, _, all_paths)) in paths_by_input.iter_mut().enumerate() {
+                            let token_addresses: Vec<String> = tokens.iter().map(|token| token.address.clone()).collect();
+                            if !token_addresses.contains(&pool.tokenMintA) || !token_addresses.contains(&pool.tokenMintB) {
+                                continue;
+                            }
+                            let input_markets: HashMap<String, Market> = markets_backrun.iter()
+                                .filter(|(_, market)| token_addresses.contains(&market.tokenMintA) && token_addresses.contains(&market.tokenMintB))
+                                .map(|(key, market)| (key.clone(), market.clone()))
+                                .collect();
+                            let (include_1hop, include_2hop) = hops_by_input[input_index];
+                            // No route cache in the backrun, the route ids don't matter
+                            for path in get_paths_through_pool(include_1hop, include_2hop, input_markets, &pool.id, tokens.clone(), 0) {
+                                for route in path.paths.iter() {
+                                    paths_by_pool.entry(route.pool_address.clone()).or_default().push((input_index, all_paths.len()));
+                                }
+                                all_paths.push(path);
+                            }
+                        }
+                        info!("🎯 Discovered pool {} added to the backrun, {} paths through it", pool.id, paths_by_pool.get(&pool.id).map_or(0, |paths| paths.len()));
+                        if is_decoded(&pool) {
+                            let _ = new_pools_sender.send((pool.dexLabel.clone(), pool.id.clone()));
+                        }
+                    }
+                    Err(e) => error!("❌ Discovered pools missed by the backrun: {:?}", e),
+                }
+                continue;
+            }
+        };
+        let pool = match swap_event.pool.clone() {
+            Some(pool) => pool,
+            None => continue,
+        };
+        let market = match markets_backrun.get(&pool) {
+            Some(market) => market,
+            None => continue,
+        };
+        // The price before the swap opens the bar if the pool has none, the post-swap price is only read on big moves
+        if let Some(token_infos) = tokens_infos_backrun.get(&market.tokenMintA) {
+            candle_builder.add_swap(&swap_event, Utc::now().timestamp(), token_infos.decimals, get_market_price(market, &tokens_infos_backrun));
+        }
+        if candles_saved_at.elapsed() > CANDLES_SAVE_INTERVAL {
+            if let Err(e) = candle_builder.save(CANDLES_PATH) {
+                error!("❌ Candles not saved: {:?}", e);
+            }
+            candles_saved_at = Instant::now();
+        }
+        let price_move = match get_price_move(&swap_event, market) {
+            Some(price_move) => price_move,
+            None => continue,
+        };
+        let dislocation = get_oracle_dislocation_cached(&pool).map_or(0.0, |dislocation| dislocation.dislocation.abs());
+        if price_move < price_move_threshold && dislocation < price_move_threshold {
+            continue;
+        }
+        info!("🎯 Swap of {} moved the {:?} pool {} by {:.3}%, slot {}", swap_event.signature, swap_event.dex_label, pool, price_move * 100.0, swap_event.slot);
+
+        // Post-swap state of all the markets of the concerned paths
+        let concerned_paths = paths_by_pool.get(&pool).cloned().unwrap_or_default();
+        let mut concerned_markets: HashMap<String, Market> = HashMap::new();
+        for (input_index, path_index) in concerned_paths.iter() {
+            for route in paths_by_input[*input_index].2[*path_index].paths.iter() {
+                if let Some(market) = markets_backrun.get(&route.pool_address) {
+                    concerned_markets.insert(route.pool_address.clone(), market.clone());
+                }
+            }
+        }
+        let fresh_markets = get_fresh_accounts_states(concerned_markets, Some(swap_event.slot)).await;
+        if let Some(price) = fresh_markets.get(&pool).and_then(|market| get_market_price(market, &tokens_infos_backrun)) {
+            candle_builder.add_price(&fresh_markets[&pool], Utc::now().timestamp(), price);
+        }
+
+        let mut best_path: Option<(SwapPathResult, String)> = None;
+        for (input_index, path_index) in concerned_paths.iter() {
+            let (tokens, tokens_infos, all_paths) = &paths_by_input[*input_index];
+            let path = &all_paths[*path_index];
+            let pubkeys: Vec<String> = path.paths.iter().map(|route| route.pool_address.clone()).collect();
+            let markets: Vec<Market> = pubkeys.iter().filter_map(|key| fresh_markets.get(key)).cloned().collect();
+            if !is_slot_consistent(&markets, max_slot_window) {
+                continue;
+            }
+
+            let (_, swap_simulation_result, result_difference) = simulate_path(simulation_amount, path.clone(), markets.clone(), tokens_infos.clone(), HashMap::new()).await;
+            if swap_simulation_result.len() < path.hops as usize {
+                continue;
+            }
+            if best_path.as_ref().map_or(false, |(best, _)| best.result >= result_difference) {
+                continue;
+            }
+
+            let mut tokens_path = swap_simulation_result.iter().map(|swap_sim| tokens_infos.get(&swap_sim.token_in).unwrap().symbol.clone()).collect::<Vec<String>>().join("-");
+            tokens_path = format!("{}-{}",tokens_path, tokens[0].symbol.clone());
+
+            let sp_result: SwapPathResult = SwapPathResult{ 
+                path_id: *path_index as u32, 
+                hops: path.hops,
+                tokens_path: tokens_path.clone(), 
+                route_simulations: swap_simulation_result.clone(), 
+                token_in: tokens[0].address.clone(), 
+                token_in_symbol: tokens[0].symbol.clone(), 
+                token_out: tokens[0].address.clone(), 
+                token_out_symbol: tokens[0].symbol.clone(), 
+                amount_in: swap_simulation_result[0].amount_in.clone(), 
+                estimated_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_amount_out.clone(), 
+                estimated_min_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_min_amount_out.clone(), 
+                result: result_difference,
+                slot: get_slot_range(&markets).map(|(oldest, _)| oldest),
+                result_usd: get_usd_value(&tokens[0].address, result_difference)
+            };
+            best_path = Some((sp_result, tokens_path));
+        }
+
+        match best_path {
+            Some((sp_result, tokens_path)) if sp_result.result > 20000000.0 => {
+                info!("💸💸💸 Backrun {} with path {} (result: {})", swap_event.signature, tokens_path, sp_result.result);
+                let _ = insert_swap_path_result_collection("backrun_transactions", sp_result.clone()).await;
+                match send_to_executor(sp_result, tokens_path, counter_sp_result).await {
+                    Ok(_) => info!("⏱️  Backrun latency from swap event to submission: {:?}", received_at.elapsed()),
+                    Err(e) => error!("❌ Backrun not sent to executor: {:?}", e),
+                }
+                counter_sp_result += 1;
+            }
+            _ => {
+                info!("🎯 No profitable backrun for {} ({:?} after the event)", swap_event.signature, received_at.elapsed());
+            }
+        }
+    }
+    Ok(())
+}
+
 pub async fn precision_strategy(socket: Client, path: SwapPath, markets: Vec<Market>, tokens: Vec<TokenInArb>, tokens_infos: HashMap<String, TokenInfos>) {
 
     info!("🔎🔎 Run a Precision SImulation on Path Id: {:?}", path.id_paths);
@@ -274,7 +556,9 @@ pub async fn precision_strategy(socket: Client, path: SwapPath, markets: Vec<Mar
                 amount_in: swap_simulation_result[0].amount_in.clone(), 
                 estimated_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_amount_out.clone(), 
                 estimated_min_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_min_amount_out.clone(), 
-                result: result_difference
+                result: result_difference,
+                slot: None,
+                result_usd: get_usd_value(&tokens[0].address, result_difference)
             };
             swap_paths_results.result.push(sp_result.clone());
             
@@ -294,18 +578,77 @@ pub async fn precision_strategy(socket: Client, path: SwapPath, markets: Vec<Mar
     }
 }   
 
-pub async fn sorted_interesting_path_strategy(simulation_amount: u64, path:String, tokens: Vec<TokenInArb>, tokens_infos: HashMap<String, TokenInfos>) -> Result<()>{
+// With `subscriptions`, the markets of the paths are kept up to date by websocket and the strategy pauses while the data is stale
+// With `discovered_pools`, the paths through the new pools are added while the strategy runs
+pub async fn sorted_interesting_path_strategy(simulation_amount: u64, path:String, tokens: Vec<TokenInArb>, tokens_infos: HashMap<String, TokenInfos>, subscriptions: Option<(u64, Duration)>, discovered_pools: Option<DiscoveredPools>) -> Result<()>{
 
     let file_read = OpenOptions::new().read(true).write(true).open(path)?;
     let mut paths_vec: VecSwapPathSelected = serde_json::from_reader(&file_read).unwrap();
     let mut counter_sp_result = 0;
 
-    let paths: Vec<SwapPathSelected> = paths_vec.value;
+    let mut paths: Vec<SwapPathSelected> = paths_vec.value;
     let mut route_simulation: HashMap<Vec<u32>, Vec<SwapRouteSimulation>> = HashMap::new();
     let tokens_for_tx: Vec<Pubkey> = tokens.iter().map(|tk| from_str(&tk.address).unwrap()).collect();
+
+    let subscription_manager = subscriptions.map(|(max_slot_gap, max_staleness)| {
+        let markets: HashMap<String, Market> = paths.iter().flat_map(|path| path.markets.clone()).map(|market| (market.id.clone(), market)).collect();
+        let manager = SubscriptionManager::new(markets, max_slot_gap, max_staleness);
+        manager.start();
+        manager
+    });
+
+    // The route ids of the new paths follow the ones of the file, they key the route simulations
+    let mut discovered_receiver = subscribe_discovered_pools();
+    let mut new_pools: Vec<Market> = discovered_pools.as_ref().map(|discovered| get_discovered_pools(discovered).into_values().collect()).unwrap_or_default();
+    let mut next_route_id: u32 = paths.iter().flat_map(|path| path.path.paths.iter().map(|route| route.id + 1)).max().unwrap_or(0);
     loop {
+        if discovered_pools.is_some() {
+            loop {
+                match discovered_receiver.try_recv() {
+                    Ok(pool) => new_pools.push(pool),
+                    Err(broadcast::error::TryRecvError::Lagged(skipped)) => error!("❌ {} discovered pools missed by the sorted strategy", skipped),
+                    Err(_) => break,
+                }
+            }
+        }
+        for pool in new_pools.drain(..) {
+            let mut markets: HashMap<String, Market> = paths.iter().flat_map(|path| path.markets.clone()).map(|market| (market.id.clone(), market)).collect();
+            if markets.contains_key(&pool.id) || !has_enough_liquidity(&pool) {
+                continue;
+            }
+            let fresh_pool = get_fresh_accounts_states(HashMap::from([(pool.id.clone(), pool.clone())]), None).await;
+            let pool = fresh_pool.get(&pool.id).cloned().unwrap_or(pool);
+            markets.insert(pool.id.clone(), pool.clone());
+            let new_paths = get_paths_through_pool(true, true, markets.clone(), &pool.id, tokens.clone(), next_route_id);
+            next_route_id += 2 * markets.len() as u32;
+            info!("🎯 Discovered pool {} added to the sorted strategy, {} paths through it", pool.id, new_paths.len());
+            for new_path in new_paths {
+                let path_markets: Vec<Market> = new_path.paths.iter().filter_map(|route| markets.get(&route.pool_address)).cloned().collect();
+                paths.push(SwapPathSelected { result: 0.0, path: new_path, markets: path_markets });
+            }
+            if let Some(manager) = &subscription_manager {
+                manager.add_markets(HashMap::from([(pool.id.clone(), pool)]));
+            }
+        }
+
+        // Pause all the paths until the subscriptions are healthy
+        if let Some(manager) = &subscription_manager {
+            if !manager.is_healthy() {
+                error!("⏸️  Pause, subscriptions not healthy: {:?}", manager.health());
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                continue;
+            }
+        }
         for (index, path) in paths.iter().enumerate() {
-            let (new_route_simulation, swap_simulation_result, result_difference) = simulate_path(simulation_amount, path.path.clone(), path.markets.clone(), tokens_infos.clone(), route_simulation.clone()).await;
+            let mut markets = path.markets.clone();
+            if let Some(manager) = &subscription_manager {
+                if !manager.is_healthy() {
+                    break;
+                }
+                let live_markets = manager.get_markets();
+                markets = markets.iter().map(|market| live_markets.get(&market.id).cloned().unwrap_or(market.clone())).collect();
+            }
+            let (new_route_simulation, swap_simulation_result, result_difference) = simulate_path(simulation_amount, path.path.clone(), markets, tokens_infos.clone(), route_simulation.clone()).await;
             //If no error in swap path
             if swap_simulation_result.len() >= path.path.hops as usize {
                 // tokens.iter().map(|token| &token.symbol).cloned().collect::<Vec<String>>().join("-");
@@ -325,7 +668,9 @@ pub async fn sorted_interesting_path_strategy(simulation_amount: u64, path:Strin
                     amount_in: swap_simulation_result[0].amount_in.clone(), 
                     estimated_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_amount_out.clone(), 
                     estimated_min_amount_out: swap_simulation_result[swap_simulation_result.len() - 1].estimated_min_amount_out.clone(), 
-                    result: result_difference
+                    result: result_difference,
+                    slot: None,
+                    result_usd: get_usd_value(&tokens[0].address, result_difference)
                 };
                 
                 if result_difference > 20000000.0 {
@@ -344,32 +689,18 @@ pub async fn sorted_interesting_path_strategy(simulation_amount: u64, path:Strin
                     //     sp_result.clone()
                     // ).await;
                     
-                    let now = Utc::now();
-                    let date = format!("{}-{}-{}", now.day(), now.month(), now.year());
-
-                    let path = format!("optimism_transactions/{}-{}-{}.json", date, tokens_path, counter_sp_result);
-                    let _ = write_file_swap_path_result(path.clone(), sp_result);
+                    send_to_executor(sp_result, tokens_path, counter_sp_result).await?;
                     counter_sp_result += 1;
-                    
-                    //Send message to Rust execution program
-                    let mut stream = TcpStream::connect("127.0.0.1:8080").await?;
-    
-                    let message = path.as_bytes();
-                    stream.write_all(message).await?;
-                    info!("🛜  Sent: {} tx to executor", String::from_utf8_lossy(message));
-                    // let mut buffer = [0; 512];
-                    // let n = stream.read(&mut buffer).await?;
-                    // info!("Received: {}", String::from_utf8_lossy(&buffer[0..n]));
                 }
             }
-            sleep(time::Duration::from_millis(200))
+            tokio::time::sleep(Duration::from_millis(200)).await;
         }
     }
     // Ok(())
 
 }
 
-pub async fn optimism_tx_strategy(path:String) -> Result<()>{
+pub async fn optimism_tx_strategy(path:String, signer: SignerService, leader_sender: Option<LeaderSender>, bundle_sender: Option<BundleSender>, broadcaster: Option<Broadcaster>, priority_fee_policy: PriorityFeePolicy, slippage_policy: SlippagePolicy, wrap_sol: bool) -> Result<()>{
 
     let file_read = OpenOptions::new().read(true).write(true).open(path)?;
     let mut spr: SwapPathResult = serde_json::from_reader(&file_read).unwrap();
@@ -386,7 +717,14 @@ pub async fn optimism_tx_strategy(path:String) -> Result<()>{
     let _ = create_and_send_swap_transaction(
         SendOrSimulate::Send,
         ChainType::Mainnet, 
-        spr.clone()
+        signer,
+        spr.clone(),
+        leader_sender,
+        bundle_sender,
+        broadcaster,
+        priority_fee_policy,
+        slippage_policy,
+        wrap_sol
     ).await;
 
     Ok(())
@@ -1,51 +1,146 @@
-
 use std::collections::HashMap;
-use anyhow::Result;
-use log::info;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::pubkey::Pubkey;
+use log::{error, info};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
 use crate::{
     common::{
         constants::Env,
         utils::from_str,
-    }, 
-    markets::types::{DexLabel, Market}
+    },
+    markets::{raydium::{get_raydium_reserves_keys, record_raydium_reserves_account}, snapshot::record_market_states, types::{DexLabel, Market}},
 };
 
 //Get fresh data on all acounts with getMultipleAccounts
-pub async fn get_fresh_accounts_states(mut accounts: HashMap<String, Market>) -> HashMap<String, Market> {
+// Each market keep the slot of the RPC context where it was read, `min_context_slot` avoid to read older state than a previous batch
+pub async fn get_fresh_accounts_states(accounts: HashMap<String, Market>, min_context_slot: Option<u64>) -> HashMap<String, Market> {
     let env = Env::new();
     let rpc_client = RpcClient::new(env.rpc_url);
+    fetch_accounts_states(&rpc_client, accounts, min_context_slot).await
+}
+
+pub async fn fetch_accounts_states(rpc_client: &RpcClient, mut accounts: HashMap<String, Market>, min_context_slot: Option<u64>) -> HashMap<String, Market> {
     let mut counter_fresh_markets = 0;
+    let mut min_context_slot = min_context_slot;
 
-    let mut markets_vec: Vec<Market> = Vec::new();
-    let mut key_vec: Vec<String> = Vec::new();
-    let mut pubkeys_vec: Vec<Pubkey> = Vec::new();
-    for (key, market) in accounts.clone().iter() {
-        markets_vec.push(market.clone());
-        key_vec.push(key.clone());
-        pubkeys_vec.push(from_str(market.clone().id.as_str()).unwrap());
+    // First the pools
+    let keys: Vec<String> = accounts.keys().cloned().collect();
+    let groups: Vec<Vec<Pubkey>> = keys.iter().map(|key| vec![from_str(accounts[key].id.as_str()).unwrap()]).collect();
+    let mut raydium_pools: Vec<String> = Vec::new();
+    for (key, fetched) in keys.iter().zip(get_multiple_accounts_by_group(rpc_client, &groups, &mut min_context_slot).await) {
+        let (account, slot) = match fetched {
+            Some((mut fetched_accounts, slot)) => match fetched_accounts.remove(0) {
+                Some(account) => (account, slot),
+                None => continue,
+            },
+            None => continue,
+        };
+        let market = accounts.get_mut(key).unwrap();
+        market.account_data = Some(account.data);
+        market.id = key.clone();
+        market.slot = Some(slot);
+        counter_fresh_markets += 1;
+        if market.dexLabel == DexLabel::RAYDIUM {
+            raydium_pools.push(key.clone());
+        }
     }
 
-    for i in (0..pubkeys_vec.len()).step_by(100) {
-        let maxLength = std::cmp::min(i + 100, pubkeys_vec.len());
-        let batch = &pubkeys_vec[(i..maxLength)];
-        
-        let batch_results = rpc_client.get_multiple_accounts(&batch).unwrap();
-        // println!("BatchResult {:?}", batch_results);
-        for (j, account) in batch_results.iter().enumerate() {
-            let account = account.clone().unwrap();
-            // println!("WhirpoolAccount: {:?}", data);
-            let account_data = account.data;
-
-            markets_vec[j].account_data = Some(account_data);
-            markets_vec[j].id = key_vec[j].clone();
-            counter_fresh_markets += 1;
-            accounts.insert(key_vec[j].clone(), markets_vec[j].clone());
+    // Then the accounts to quote the Raydium pools, found in their fresh AmmInfo and not older than the pools
+    // The event queue of an OpenBook market is known once the market is read: these pools are read again with it
+    let mut fetched_keys_len: HashMap<String, usize> = HashMap::new();
+    for _ in 0..2 {
+        let mut pools: Vec<(String, Vec<Pubkey>)> = Vec::new();
+        for key in raydium_pools.iter() {
+            let reserves_keys = get_raydium_reserves_keys(&accounts[key]);
+            if reserves_keys.len() > fetched_keys_len.get(key).cloned().unwrap_or(0) {
+                fetched_keys_len.insert(key.clone(), reserves_keys.len());
+                pools.push((key.clone(), reserves_keys));
+            }
+        }
+        if pools.is_empty() {
+            break;
+        }
+        let groups: Vec<Vec<Pubkey>> = pools.iter().map(|(_, reserves_keys)| reserves_keys.clone()).collect();
+        for ((_, reserves_keys), fetched) in pools.iter().zip(get_multiple_accounts_by_group(rpc_client, &groups, &mut min_context_slot).await) {
+            let (fetched_accounts, slot) = match fetched {
+                Some(fetched) => fetched,
+                None => continue,
+            };
+            for (pubkey, account) in reserves_keys.iter().zip(fetched_accounts) {
+                if let Some(account) = account {
+                    record_raydium_reserves_account(*pubkey, account, slot);
+                }
+            }
         }
     }
 
     info!("💦💦 Fresh data for {:?} markets", counter_fresh_markets);
+    record_market_states(&accounts);
     return accounts;
 }
 
+// The accounts of a group are read in the same getMultipleAccounts, the groups are packed in batches of at most 100 accounts
+// Return the accounts and the slot of each group, None if its batch failed. `min_context_slot` follows the slot of the last batch
+async fn get_multiple_accounts_by_group(rpc_client: &RpcClient, groups: &Vec<Vec<Pubkey>>, min_context_slot: &mut Option<u64>) -> Vec<Option<(Vec<Option<Account>>, u64)>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut batch_len = 0;
+    for (index, keys) in groups.iter().enumerate() {
+        if batches.is_empty() || batch_len + keys.len() > 100 {
+            batches.push(Vec::new());
+            batch_len = 0;
+        }
+        batches.last_mut().unwrap().push(index);
+        batch_len += keys.len();
+    }
+
+    let mut fetched_groups: Vec<Option<(Vec<Option<Account>>, u64)>> = vec![None; groups.len()];
+    for batch in batches {
+        let pubkeys: Vec<Pubkey> = batch.iter().flat_map(|index| groups[*index].clone()).collect();
+        let batch_response = match rpc_client.get_multiple_accounts_with_config(&pubkeys, RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            data_slice: None,
+            commitment: Some(CommitmentConfig::confirmed()),
+            min_context_slot: *min_context_slot,
+        }).await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("❌ Error in get fresh accounts states: {:?}", e);
+                continue;
+            }
+        };
+        let slot = batch_response.context.slot;
+        // Next batches can't be older than this one
+        *min_context_slot = Some(slot);
+        let mut fetched_accounts = batch_response.value.into_iter();
+        for index in batch {
+            let group_accounts: Vec<Option<Account>> = groups[index].iter().map(|_| fetched_accounts.next().flatten()).collect();
+            fetched_groups[index] = Some((group_accounts, slot));
+        }
+    }
+    fetched_groups
+}
+
+// (oldest, newest) slot of the markets, None if one of them have no slot
+pub fn get_slot_range(markets: &Vec<Market>) -> Option<(u64, u64)> {
+    let mut slots: Vec<u64> = Vec::new();
+    for market in markets {
+        slots.push(market.slot?);
+    }
+    Some((*slots.iter().min()?, *slots.iter().max()?))
+}
+
+// All the accounts of a path are observed in the same slot window
+pub fn is_slot_consistent(markets: &Vec<Market>, max_slot_window: u64) -> bool {
+    match get_slot_range(markets) {
+        Some((oldest, newest)) => newest - oldest <= max_slot_window,
+        None => false,
+    }
+}
+
+// The oldest account of a path is not older than max_slot_window compared to the current slot
+pub fn is_stale(markets: &Vec<Market>, current_slot: u64, max_slot_window: u64) -> bool {
+    match get_slot_range(markets) {
+        Some((oldest, _)) => current_slot.saturating_sub(oldest) > max_slot_window,
+        None => true,
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use futures::stream::{select_all, BoxStream, StreamExt};
+use log::{error, info};
+use solana_account_decoder::{UiAccount, UiAccountData, UiAccountEncoding};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_client::rpc_response::Response;
+use solana_pubsub_client::nonblocking::pubsub_client::{PubsubClient, PubsubClientResult};
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::sync::Notify;
+
+use crate::arbitrage::streams::fetch_accounts_states;
+use crate::common::constants::Env;
+use crate::common::utils::from_str;
+use crate::markets::types::Market;
+
+pub const MIN_BACKOFF: Duration = Duration::from_millis(500);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// Keep a blocking websocket subscription alive with the same backoff, for the subscriptions outside the SubscriptionManager
+// `subscribe` returns Ok when its subscription is disconnected and Err when it can't subscribe
+pub fn subscribe_with_reconnect(name: &str, mut subscribe: impl FnMut() -> anyhow::Result<()>, is_stopped: impl Fn() -> bool) {
+    let mut backoff = MIN_BACKOFF;
+    while !is_stopped() {
+        match subscribe() {
+            Ok(()) => {
+                // It was connected, restart from the min backoff
+                backoff = MIN_BACKOFF;
+                if !is_stopped() {
+                    error!("❌ {} disconnected, reconnect in {:?}", name, backoff);
+                    std::thread::sleep(backoff);
+                }
+            }
+            Err(e) => {
+                error!("❌ {} failed, retry in {:?}: {:?}", name, backoff, e);
+                std::thread::sleep(backoff);
+                backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionHealth {
+    pub subscribed_accounts: usize,
+    pub connected_accounts: usize,
+    pub slot_connected: bool,
+    // Last slot received by slotSubscribe
+    pub last_slot: u64,
+    pub last_slot_update: Option<Instant>,
+    pub reconnections: u64,
+    pub pending_backfill: bool,
+}
+
+// Own all the websocket subscriptions of the markets: one accountSubscribe per market and one slotSubscribe,
+// multiplexed on one websocket connection to the endpoint
+// The connection reconnects with an exponential backoff, a reconnection or a gap in the slots
+// triggers a backfill of all the markets with getMultipleAccounts
+#[derive(Clone)]
+pub struct SubscriptionManager {
+    markets: Arc<RwLock<HashMap<String, Market>>>,
+    connected_accounts: Arc<AtomicUsize>,
+    slot_connected: Arc<AtomicBool>,
+    last_slot: Arc<AtomicU64>,
+    last_slot_update: Arc<RwLock<Option<Instant>>>,
+    reconnections: Arc<AtomicU64>,
+    pending_backfill: Arc<AtomicBool>,
+    // Notified by add_markets() to subscribe the new markets on the current connection
+    markets_added: Arc<Notify>,
+    max_slot_gap: u64,
+    max_staleness: Duration,
+}
+
+impl SubscriptionManager {
+    pub fn new(markets: HashMap<String, Market>, max_slot_gap: u64, max_staleness: Duration) -> Self {
+        SubscriptionManager {
+            markets: Arc::new(RwLock::new(markets)),
+            connected_accounts: Arc::new(AtomicUsize::new(0)),
+            slot_connected: Arc::new(AtomicBool::new(false)),
+            last_slot: Arc::new(AtomicU64::new(0)),
+            last_slot_update: Arc::new(RwLock::new(None)),
+            reconnections: Arc::new(AtomicU64::new(0)),
+            // Markets are fetched once at startup
+            pending_backfill: Arc::new(AtomicBool::new(true)),
+            markets_added: Arc::new(Notify::new()),
+            max_slot_gap,
+            max_staleness,
+        }
+    }
+
+    // Launch the subscriptions and the backfill task, must be called inside a tokio runtime
+    pub fn start(&self) {
+        info!("📡 Launch {} account subscriptions...", self.markets.read().unwrap().len());
+        let manager = self.clone();
+        tokio::spawn(async move { manager.supervise().await });
+        let manager = self.clone();
+        tokio::spawn(async move { manager.run_backfill().await });
+    }
+
+    // Markets added while running (discovered pools) with a fresh state, unhealthy until they are subscribed
+    pub fn add_markets(&self, new_markets: HashMap<String, Market>) {
+        {
+            let mut markets = self.markets.write().unwrap();
+            for (key, market) in new_markets {
+                markets.entry(key).or_insert(market);
+            }
+        }
+        self.markets_added.notify_one();
+    }
+
+    pub fn get_markets(&self) -> HashMap<String, Market> {
+        self.markets.read().unwrap().clone()
+    }
+
+    pub fn health(&self) -> SubscriptionHealth {
+        SubscriptionHealth {
+            subscribed_accounts: self.markets.read().unwrap().len(),
+            connected_accounts: self.connected_accounts.load(Ordering::SeqCst),
+            slot_connected: self.slot_connected.load(Ordering::SeqCst),
+            last_slot: self.last_slot.load(Ordering::SeqCst),
+            last_slot_update: *self.last_slot_update.read().unwrap(),
+            reconnections: self.reconnections.load(Ordering::SeqCst),
+            pending_backfill: self.pending_backfill.load(Ordering::SeqCst),
+        }
+    }
+
+    // Strategies must pause when the data is not healthy
+    pub fn is_healthy(&self) -> bool {
+        let health = self.health();
+        health.slot_connected
+            && health.connected_accounts == health.subscribed_accounts
+            && !health.pending_backfill
+            && health.last_slot_update.map_or(false, |update| update.elapsed() <= self.max_staleness)
+    }
+
+    async fn subscribe_account<'a>(pubsub_client: &'a PubsubClient, key: &String) -> PubsubClientResult<BoxStream<'a, (String, Response<UiAccount>)>> {
+        let (account_stream, _account_unsubscribe) = pubsub_client.account_subscribe(
+            &from_str(key.as_str()).unwrap(),
+            Some(RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                data_slice: None,
+                commitment: Some(CommitmentConfig::confirmed()),
+                min_context_slot: None,
+            }),
+        ).await?;
+        let key = key.clone();
+        Ok(account_stream.map(move |response| (key.clone(), response)).boxed())
+    }
+
+    async fn supervise(&self) {
+        let env = Env::new();
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let pubsub_client = match PubsubClient::new(env.wss_rpc_url.as_str()).await {
+                Ok(pubsub_client) => pubsub_client,
+                Err(e) => {
+                    error!("❌ Websocket connection failed, retry in {:?}: {:?}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                    continue;
+                }
+            };
+            let (mut slot_updates, _slot_unsubscribe) = match pubsub_client.slot_subscribe().await {
+                Ok(subscription) => subscription,
+                Err(e) => {
+                    error!("❌ Slot subscription failed, retry in {:?}: {:?}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                    continue;
+                }
+            };
+            let keys: Vec<String> = self.markets.read().unwrap().keys().cloned().collect();
+            let mut subscribed_keys: HashSet<String> = HashSet::new();
+            let mut account_streams = Vec::new();
+            for key in keys.iter() {
+                match Self::subscribe_account(&pubsub_client, key).await {
+                    Ok(account_stream) => {
+                        subscribed_keys.insert(key.clone());
+                        account_streams.push(account_stream);
+                    }
+                    Err(e) => {
+                        error!("❌ Account subscription on {} failed: {:?}", key, e);
+                        break;
+                    }
+                }
+            }
+            if account_streams.len() < keys.len() {
+                error!("❌ Account subscriptions failed, retry in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                continue;
+            }
+            backoff = MIN_BACKOFF;
+            self.connected_accounts.store(keys.len(), Ordering::SeqCst);
+            self.slot_connected.store(true, Ordering::SeqCst);
+
+            // The streams end when the connection drops
+            let mut account_updates = select_all(account_streams);
+            loop {
+                tokio::select! {
+                    _ = self.markets_added.notified() => {
+                        let new_keys: Vec<String> = self.markets.read().unwrap().keys().filter(|key| !subscribed_keys.contains(*key)).cloned().collect();
+                        let mut subscribed = true;
+                        for key in new_keys {
+                            match Self::subscribe_account(&pubsub_client, &key).await {
+                                Ok(account_stream) => {
+                                    account_updates.push(account_stream);
+                                    subscribed_keys.insert(key);
+                                }
+                                Err(e) => {
+                                    error!("❌ Account subscription on new market {} failed: {:?}", key, e);
+                                    subscribed = false;
+                                    break;
+                                }
+                            }
+                        }
+                        if !subscribed {
+                            break;
+                        }
+                        info!("📡 {} new markets subscribed", subscribed_keys.len() - self.connected_accounts.load(Ordering::SeqCst));
+                        self.connected_accounts.store(subscribed_keys.len(), Ordering::SeqCst);
+                    },
+                    update = account_updates.next() => match update {
+                        Some((key, response)) => self.update_market(&key, response),
+                        None => break,
+                    },
+                    slot_info = slot_updates.next() => match slot_info {
+                        Some(slot_info) => self.update_slot(slot_info.slot),
+                        None => break,
+                    },
+                }
+            }
+            error!("❌ Websocket subscriptions disconnected, reconnect...");
+
+            // Updates were missed while disconnected
+            self.connected_accounts.store(0, Ordering::SeqCst);
+            self.slot_connected.store(false, Ordering::SeqCst);
+            self.reconnections.fetch_add(1, Ordering::SeqCst);
+            self.pending_backfill.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn update_market(&self, key: &String, response: Response<UiAccount>) {
+        let data = match UiAccountData::decode(&response.value.data) {
+            Some(data) => data,
+            None => return,
+        };
+        let mut markets = self.markets.write().unwrap();
+        if let Some(market) = markets.get_mut(key) {
+            // Never replace a state with an older one (backfill can be newer)
+            if market.slot.map_or(true, |slot| slot <= response.context.slot) {
+                market.account_data = Some(data);
+                market.slot = Some(response.context.slot);
+            }
+        }
+    }
+
+    fn update_slot(&self, slot: u64) {
+        let last_slot = self.last_slot.swap(slot, Ordering::SeqCst);
+        if last_slot != 0 && slot > last_slot + self.max_slot_gap {
+            error!("⚠️ Gap in slots: {} -> {}, backfill markets", last_slot, slot);
+            self.pending_backfill.store(true, Ordering::SeqCst);
+        }
+        *self.last_slot_update.write().unwrap() = Some(Instant::now());
+    }
+
+    async fn run_backfill(&self) {
+        let rpc_client = RpcClient::new(Env::new().rpc_url);
+        loop {
+            if self.pending_backfill.load(Ordering::SeqCst) {
+                self.backfill(&rpc_client).await;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    // The markets stay unhealthy until the fetched states are merged
+    pub async fn backfill(&self, rpc_client: &RpcClient) {
+        let markets = self.get_markets();
+        let fresh_markets = fetch_accounts_states(rpc_client, markets, None).await;
+        {
+            let mut markets = self.markets.write().unwrap();
+            for (key, fresh_market) in fresh_markets {
+                if let Some(market) = markets.get_mut(&key) {
+                    if market.slot <= fresh_market.slot {
+                        *market = fresh_market;
+                    }
+                }
+            }
+        }
+        self.pending_backfill.store(false, Ordering::SeqCst);
+        info!("📡 Markets backfilled, health: {:?}", self.health());
+    }
+}
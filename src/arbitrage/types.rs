@@ -60,6 +60,12 @@ pub struct SwapPathResult {
     pub estimated_amount_out: String,
     pub estimated_min_amount_out: String,
     pub result: f64,
+    // Oldest slot of the accounts used to simulate the path, used as min_context_slot to build the transaction
+    #[serde(default)]
+    pub slot: Option<u64>,
+    // Value of `result` in USD, None if token_in has no known price
+    #[serde(default)]
+    pub result_usd: Option<f64>,
 }
 #[derive(Debug, Clone, Serialize)]
 pub struct VecSwapPathResult {
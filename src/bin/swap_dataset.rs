@@ -0,0 +1,35 @@
+use anyhow::Result;
+use clap::{Arg, Command};
+use log::info;
+use MEV_Bot_Solana::common::constants::Env;
+use MEV_Bot_Solana::common::utils::setup_logger;
+use MEV_Bot_Solana::data::swap_dataset::{build_swap_dataset, build_swap_dataset_from_fixtures};
+
+// Build the swaps dataset from RPC blocks or from recorded getBlock JSON files:
+// cargo run --bin swap_dataset -- --start-slot 270000000 --end-slot 270000100
+// cargo run --bin swap_dataset -- --fixtures blocks/270000000.json blocks/270000001.json
+fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+    setup_logger().unwrap();
+
+    let matches = Command::new("swap_dataset")
+        .about("Extract Raydium, Whirlpools and Meteora DLMM swaps from blocks")
+        .arg(Arg::new("start-slot").long("start-slot").value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("end-slot").long("end-slot").value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("fixtures").long("fixtures").num_args(1..))
+        .arg(Arg::new("output").long("output").default_value("src/data/cache/swaps.jsonl"))
+        .get_matches();
+
+    let output_path = matches.get_one::<String>("output").unwrap();
+
+    let counter_records = if let Some(fixtures) = matches.get_many::<String>("fixtures") {
+        build_swap_dataset_from_fixtures(fixtures.cloned().collect(), output_path)?
+    } else {
+        let start_slot = *matches.get_one::<u64>("start-slot").expect("--start-slot or --fixtures is required");
+        let end_slot = matches.get_one::<u64>("end-slot").cloned().unwrap_or(start_slot);
+        build_swap_dataset(Env::new().rpc_url, start_slot, end_slot, output_path)?
+    };
+
+    info!("📚 Dataset done: {} swaps in {}", counter_records, output_path);
+    Ok(())
+}
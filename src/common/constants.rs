@@ -9,6 +9,8 @@ pub struct Env {
     pub block_engine_url: String,
     pub mainnet_rpc_url: String,
     pub rpc_url_tx: String,
+    // Comma separated RPC URLs which also receive the swap transactions
+    pub rpc_urls_broadcast: String,
     pub devnet_rpc_url: String,
     pub rpc_url: String,
     pub wss_rpc_url: String,
@@ -17,6 +19,14 @@ pub struct Env {
     pub simulator_url: String,
     pub ws_simulator_url: String,
     pub payer_keypair_path: String,
+    // file, keystore or remote
+    pub payer_signer: String,
+    pub payer_keystore_path: String,
+    pub payer_remote_signer_socket: String,
+    // Separate account paying the fees, the payer when empty
+    pub fee_payer_keypair_path: String,
+    // Deployed programs/profit_guard, the arbitrages are not wrapped by the guard when empty
+    pub profit_guard_program: String,
     pub database_name: String,
 
 }
@@ -28,6 +38,7 @@ impl Env {
             rpc_url: get_env("RPC_URL"),
             mainnet_rpc_url: get_env("MAINNET_RPC_URL"),
             rpc_url_tx: get_env("RPC_URL_TX"),
+            rpc_urls_broadcast: get_env("RPC_URLS_BROADCAST"),
             devnet_rpc_url: get_env("DEVNET_RPC_URL"),
             wss_rpc_url: get_env("WSS_RPC_URL"),
             geyser_url: get_env("GEYSER_URL"),
@@ -35,6 +46,11 @@ impl Env {
             simulator_url: get_env("SIMULATOR_URL"),
             ws_simulator_url: get_env("WS_SIMULATOR_URL"),
             payer_keypair_path: get_env("PAYER_KEYPAIR_PATH"),
+            payer_signer: get_env("PAYER_SIGNER"),
+            payer_keystore_path: get_env("PAYER_KEYSTORE_PATH"),
+            payer_remote_signer_socket: get_env("PAYER_REMOTE_SIGNER_SOCKET"),
+            fee_payer_keypair_path: get_env("FEE_PAYER_KEYPAIR_PATH"),
+            profit_guard_program: get_env("PROFIT_GUARD_PROGRAM"),
             database_name: get_env("DATABASE_NAME")
         }
     }
@@ -5,6 +5,7 @@ use mongodb::{Client as MongoDbCLient, options::ClientOptions};
 use anyhow::Result;
 
 use crate::arbitrage::types::{SwapPathResult, VecSwapPathSelected};
+use crate::transactions::confirmation::TradeResult;
 
 pub async fn insert_swap_path_result_collection(collection_name: &str, sp_result: SwapPathResult) -> Result<()> {
     let db_name = "MEV_Bot";
@@ -35,4 +36,17 @@ pub async fn insert_vec_swap_path_selected_collection(collection_name: &str, bes
     info!("📊 {} writed in DB", collection_name);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+pub async fn insert_trade_result_collection(collection_name: &str, trade_result: TradeResult) -> Result<()> {
+    let db_name = "MEV_Bot";
+    let client_options = ClientOptions::parse("mongodb://localhost:27017").await?;
+    let client = MongoDbCLient::with_options(client_options)?;
+
+    let db = client.database(db_name);
+    let coll: Collection<TradeResult> = db.collection::<TradeResult>(collection_name);
+
+    coll.insert_one(trade_result, None).await?;
+    info!("📊 {} writed in DB", collection_name);
+    Ok(())
+}
@@ -2,6 +2,7 @@ pub mod config;
 pub mod constants;
 pub mod utils;
 pub mod maths;
+pub mod prices;
 pub mod debug;
 pub mod types;
 pub mod database;
\ No newline at end of file
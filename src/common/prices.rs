@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+
+use crate::arbitrage::types::TokenInfos;
+use crate::common::utils::from_str;
+use crate::data::candles::get_market_price;
+use crate::markets::orca_whirpools::unpack_from_slice;
+use crate::markets::types::{DexLabel, Market};
+
+// Pyth legacy price account (pyth-client v2)
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+const PYTH_PRICE_ACCOUNT_MIN_LEN: usize = 240;
+// A price published more than this number of slots ago is stale (~1 min)
+pub const PYTH_MAX_AGE_SLOTS: u64 = 150;
+// Max confidence interval relative to the price
+pub const PYTH_MAX_CONF_RATIO: f64 = 0.02;
+
+// (mint, decimals, Pyth price account on mainnet)
+pub const PYTH_FEEDS: [(&str, u8, &str); 5] = [
+    ("So11111111111111111111111111111111111111112", 9, "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"), // SOL/USD
+    ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", 6, "Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD"), // USDC/USD
+    ("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", 6, "3vxLXJqLqF3JG5TCbYycbKWRBbCJQLxQmBGCkyqEEefL"), // USDT/USD
+    ("3NZ9JMVBmGAqocybic2c7LQCJScmgsAZ6vQqTDzcqmJh", 8, "GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU"), // WBTC/USD
+    ("7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs", 8, "JBu1AL4obBcCMqKBBxhpWCNUt136ijcuMZLFvTP7iWdB"), // WETH/USD
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythPrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub status: u32,
+    // Slot of the aggregate price
+    pub publish_slot: u64,
+    pub timestamp: i64,
+}
+
+impl PythPrice {
+    pub fn price_f64(&self) -> f64 {
+        self.price as f64 * 10f64.powi(self.expo)
+    }
+
+    pub fn conf_f64(&self) -> f64 {
+        self.conf as f64 * 10f64.powi(self.expo)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PriceSource {
+    Pyth,
+    // Derived from the price of the pool and the USD price of the other token
+    Pool(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct UsdPrice {
+    pub price: f64,
+    pub decimals: u8,
+    pub source: PriceSource,
+    pub updated_at: Instant,
+}
+
+fn usd_prices() -> &'static RwLock<HashMap<String, UsdPrice>> {
+    static USD_PRICES: OnceLock<RwLock<HashMap<String, UsdPrice>>> = OnceLock::new();
+    USD_PRICES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+pub fn decode_pyth_price(data: &[u8]) -> Result<PythPrice> {
+    if data.len() < PYTH_PRICE_ACCOUNT_MIN_LEN {
+        return Err(anyhow::format_err!("Pyth price account too small: {} bytes", data.len()));
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let read_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    if read_u32(0) != PYTH_MAGIC || read_u32(8) != PYTH_PRICE_ACCOUNT_TYPE {
+        return Err(anyhow::format_err!("Not a Pyth price account"));
+    }
+    Ok(PythPrice {
+        expo: read_u32(20) as i32,
+        timestamp: read_u64(96) as i64,
+        // Aggregate price info
+        price: read_u64(208) as i64,
+        conf: read_u64(216),
+        status: read_u32(224),
+        publish_slot: read_u64(232),
+    })
+}
+
+// USD price if the feed is trading, recent and precise enough
+pub fn check_pyth_price(pyth_price: &PythPrice, current_slot: u64, max_age_slots: u64, max_conf_ratio: f64) -> Result<f64> {
+    if pyth_price.status != PYTH_STATUS_TRADING {
+        return Err(anyhow::format_err!("Pyth price not trading (status {})", pyth_price.status));
+    }
+    if current_slot.saturating_sub(pyth_price.publish_slot) > max_age_slots {
+        return Err(anyhow::format_err!("Pyth price stale: published at slot {}, current slot {}", pyth_price.publish_slot, current_slot));
+    }
+    let price = pyth_price.price_f64();
+    if price <= 0.0 || pyth_price.conf_f64() / price > max_conf_ratio {
+        return Err(anyhow::format_err!("Pyth price not precise enough: {} +/- {}", price, pyth_price.conf_f64()));
+    }
+    Ok(price)
+}
+
+pub fn refresh_pyth_prices(rpc_client: &RpcClient) -> Result<usize> {
+    let price_accounts = PYTH_FEEDS.iter().map(|(_, _, account)| from_str(account)).collect::<Result<Vec<_>, _>>()?;
+    let response = rpc_client.get_multiple_accounts_with_commitment(&price_accounts, CommitmentConfig::confirmed())?;
+    let current_slot = response.context.slot;
+
+    let mut counter_prices = 0;
+    let mut prices = usd_prices().write().unwrap();
+    for ((mint, decimals, price_account), account) in PYTH_FEEDS.iter().zip(response.value) {
+        let price = account.ok_or(anyhow::format_err!("Pyth price account {} not found", price_account))
+            .and_then(|account| decode_pyth_price(&account.data))
+            .and_then(|pyth_price| check_pyth_price(&pyth_price, current_slot, PYTH_MAX_AGE_SLOTS, PYTH_MAX_CONF_RATIO));
+        match price {
+            Ok(price) => {
+                prices.insert(mint.to_string(), UsdPrice { price, decimals: *decimals, source: PriceSource::Pyth, updated_at: Instant::now() });
+                counter_prices += 1;
+            }
+            Err(e) => error!("❌ Pyth price of {} not updated: {:?}", mint, e),
+        }
+    }
+    Ok(counter_prices)
+}
+
+// Long-tail tokens: USD price from a pool with a token already priced (Pyth prices first, then the pools ones)
+pub fn refresh_pool_prices(markets: &Vec<Market>, tokens_infos: &HashMap<String, TokenInfos>) -> usize {
+    let mut priced_tokens: HashSet<String> = HashSet::new();
+    for market in markets.iter() {
+        let (priced, unpriced) = {
+            let prices = usd_prices().read().unwrap();
+            let is_pyth = |mint: &String| prices.get(mint).map_or(false, |price| price.source == PriceSource::Pyth);
+            match (is_pyth(&market.tokenMintA), is_pyth(&market.tokenMintB)) {
+                (true, false) => (market.tokenMintA.clone(), market.tokenMintB.clone()),
+                (false, true) => (market.tokenMintB.clone(), market.tokenMintA.clone()),
+                _ => continue,
+            }
+        };
+        // One pool per token
+        if priced_tokens.contains(&unpriced) {
+            continue;
+        }
+        let decimals = match tokens_infos.get(&unpriced) {
+            Some(token_infos) => token_infos.decimals,
+            None => continue,
+        };
+        // Price of A in B
+        let market_price = match get_market_price(market, tokens_infos) {
+            Some(market_price) if market_price > 0.0 => market_price,
+            _ => continue,
+        };
+        let priced_usd = match get_usd_price(&priced) {
+            Some(price) => price,
+            None => continue,
+        };
+        let price = if unpriced == market.tokenMintA { market_price * priced_usd } else { priced_usd / market_price };
+        usd_prices().write().unwrap().insert(unpriced.clone(), UsdPrice { price, decimals, source: PriceSource::Pool(market.id.clone()), updated_at: Instant::now() });
+        priced_tokens.insert(unpriced);
+    }
+    priced_tokens.len()
+}
+
+pub async fn run_prices_service(rpc_url: String, refresh_interval: Duration) {
+    info!("💵 Launch USD prices service...");
+    let rpc_client = RpcClient::new(rpc_url);
+    loop {
+        if let Err(e) = refresh_pyth_prices(&rpc_client) {
+            error!("❌ Pyth prices refresh error: {:?}", e);
+        }
+        tokio::time::sleep(refresh_interval).await;
+    }
+}
+
+pub fn get_usd_price(mint: &String) -> Option<f64> {
+    usd_prices().read().unwrap().get(mint).map(|price| price.price)
+}
+
+// Mints already reported without USD price, to warn once per mint
+fn unpriced_mints() -> &'static RwLock<HashSet<String>> {
+    static UNPRICED_MINTS: OnceLock<RwLock<HashSet<String>>> = OnceLock::new();
+    UNPRICED_MINTS.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+// USD value of a raw amount of `mint`
+pub fn get_usd_value(mint: &String, amount: f64) -> Option<f64> {
+    let prices = usd_prices().read().unwrap();
+    let price = match prices.get(mint) {
+        Some(price) => price,
+        None => {
+            if unpriced_mints().write().unwrap().insert(mint.clone()) {
+                warn!("⚠️ No USD price for {}: no Pyth feed in PYTH_FEEDS and no pool price yet", mint);
+            }
+            return None;
+        }
+    };
+    Some(amount / 10f64.powi(price.decimals as i32) * price.price)
+}
+
+// USD liquidity of the pool from the raw balances of its vaults, the side without price is valued like the other one
+pub fn get_vaults_liquidity_usd(market: &Market, amount_a: u64, amount_b: u64) -> Option<f64> {
+    match (get_usd_value(&market.tokenMintA, amount_a as f64), get_usd_value(&market.tokenMintB, amount_b as f64)) {
+        (Some(value_a), Some(value_b)) => Some(value_a + value_b),
+        (Some(value_a), None) => Some(value_a * 2.0),
+        (None, Some(value_b)) => Some(value_b * 2.0),
+        (None, None) => None,
+    }
+}
+
+// USD liquidity of the pool from its state, None if no token of the pool is priced
+// Raydium and Meteora liquidity comes from the APIs already in USD
+pub fn get_market_liquidity_usd(market: &Market) -> Option<f64> {
+    match market.dexLabel {
+        DexLabel::ORCA_WHIRLPOOLS => {
+            let whirlpool = unpack_from_slice(market.account_data.as_ref()?).ok()?;
+            let sqrt_price = whirlpool.sqrt_price as f64 / 2f64.powi(64);
+            if sqrt_price == 0.0 {
+                return None;
+            }
+            // Virtual reserves at the current price, both sides have the same value
+            let liquidity = whirlpool.liquidity as f64;
+            get_usd_value(&market.tokenMintA, liquidity / sqrt_price)
+                .or_else(|| get_usd_value(&market.tokenMintB, liquidity * sqrt_price))
+                .map(|value| value * 2.0)
+        }
+        DexLabel::RAYDIUM | DexLabel::METEORA => market.liquidity.map(|liquidity| liquidity as f64),
+        _ => None,
+    }
+}
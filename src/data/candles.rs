@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use lb_clmm::math::price_math::get_price_from_id;
+use serde::{Deserialize, Serialize};
+
+use crate::arbitrage::types::TokenInfos;
+use crate::markets::meteora::AccountData;
+use crate::markets::orca_whirpools::unpack_from_slice;
+use crate::markets::raydium::get_raydium_reserves;
+use crate::markets::swap_events::SwapEvent;
+use crate::markets::types::{DexLabel, Market};
+
+pub const CANDLES_PATH: &str = "src/data/cache/candles.json";
+pub const CANDLES_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    // Unix timestamp of the beginning of the bar
+    pub start_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    // Volume in token A (UI amount)
+    pub volume: f64,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(start_time: i64, price: f64) -> Self {
+        Candle { start_time, open: price, high: price, low: price, close: price, volume: 0.0, trades: 0 }
+    }
+
+    fn update_price(&mut self, price: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+    }
+}
+
+// Price of token A in token B (UI amounts) from the decoded state of the pool
+// Raydium pools need the vaults and open orders fetched with the pool by get_fresh_accounts_states()
+pub fn get_market_price(market: &Market, tokens_infos: &HashMap<String, TokenInfos>) -> Option<f64> {
+    let decimals_a = tokens_infos.get(&market.tokenMintA)?.decimals as i32;
+    let decimals_b = tokens_infos.get(&market.tokenMintB)?.decimals as i32;
+    let decimals_factor = 10f64.powi(decimals_a - decimals_b);
+    match market.dexLabel {
+        DexLabel::ORCA_WHIRLPOOLS => {
+            let whirlpool = unpack_from_slice(market.account_data.as_ref()?).ok()?;
+            let sqrt_price = whirlpool.sqrt_price as f64 / 2f64.powi(64);
+            Some(sqrt_price * sqrt_price * decimals_factor)
+        }
+        DexLabel::METEORA => {
+            let lb_pair = AccountData::try_from_slice(market.account_data.as_ref()?).ok()?;
+            let price_x64 = get_price_from_id(lb_pair.active_id, lb_pair.bin_step).ok()?;
+            Some(price_x64 as f64 / 2f64.powi(64) * decimals_factor)
+        }
+        DexLabel::RAYDIUM => {
+            // Coin is token A
+            let reserves = get_raydium_reserves(market).ok()?;
+            if reserves.total_coin_without_take_pnl == 0 {
+                return None;
+            }
+            Some(reserves.total_pc_without_take_pnl as f64 / reserves.total_coin_without_take_pnl as f64 * decimals_factor)
+        }
+        _ => None,
+    }
+}
+
+// OHLCV bars per pool, prices from pool states and volume from swap events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandleBuilder {
+    pub interval_secs: i64,
+    // Max bars kept in memory per pool
+    pub max_candles: usize,
+    pub candles: HashMap<String, Vec<Candle>>,
+    // (tokenMintA, tokenMintB) of each pool for token series
+    pub pool_tokens: HashMap<String, (String, String)>,
+}
+
+impl CandleBuilder {
+    pub fn new(interval_secs: i64, max_candles: usize) -> Self {
+        CandleBuilder { interval_secs, max_candles, candles: HashMap::new(), pool_tokens: HashMap::new() }
+    }
+
+    // Bar of `timestamp`, created with the close of the previous bar (or `price`) as open
+    fn get_candle(&mut self, pool: &String, timestamp: i64, price: Option<f64>) -> Option<&mut Candle> {
+        let start_time = timestamp - timestamp.rem_euclid(self.interval_secs);
+        let candles = self.candles.entry(pool.clone()).or_default();
+        if candles.last().map_or(true, |candle| candle.start_time < start_time) {
+            let open = candles.last().map(|candle| candle.close).or(price)?;
+            candles.push(Candle::new(start_time, open));
+            if candles.len() > self.max_candles {
+                candles.remove(0);
+            }
+        }
+        // Late observations of an older bar are ignored
+        candles.last_mut().filter(|candle| candle.start_time == start_time)
+    }
+
+    pub fn add_price(&mut self, market: &Market, timestamp: i64, price: f64) {
+        self.pool_tokens.insert(market.id.clone(), (market.tokenMintA.clone(), market.tokenMintB.clone()));
+        if let Some(candle) = self.get_candle(&market.id, timestamp, Some(price)) {
+            candle.update_price(price);
+        }
+    }
+
+    // `decimals_a` of the token A of the pool to get the volume in UI amount
+    // `price` opens the bar when the pool has no bar yet, the volume would be lost without it
+    pub fn add_swap(&mut self, swap_event: &SwapEvent, timestamp: i64, decimals_a: u8, price: Option<f64>) {
+        let pool = match &swap_event.pool {
+            Some(pool) => pool.clone(),
+            None => return,
+        };
+        let amount_a = if swap_event.token_0to1 { swap_event.amount_in } else { swap_event.amount_out };
+        if let Some(candle) = self.get_candle(&pool, timestamp, price) {
+            candle.volume += amount_a as f64 / 10f64.powi(decimals_a as i32);
+            candle.trades += 1;
+        }
+    }
+
+    pub fn get_candles(&self, pool: &String) -> Vec<Candle> {
+        self.candles.get(pool).cloned().unwrap_or_default()
+    }
+
+    // Standard deviation of the log returns of the last `periods` closes
+    pub fn volatility(&self, pool: &String, periods: usize) -> Option<f64> {
+        let candles = self.candles.get(pool)?;
+        let closes: Vec<f64> = candles.iter().rev().take(periods + 1).rev().map(|candle| candle.close).collect();
+        if closes.len() < 3 {
+            return None;
+        }
+        let returns: Vec<f64> = closes.windows(2).map(|window| (window[1] / window[0]).ln()).collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+        Some(variance.sqrt())
+    }
+
+    // Relative change of the close over the last `periods` bars
+    pub fn trend(&self, pool: &String, periods: usize) -> Option<f64> {
+        let candles = self.candles.get(pool)?;
+        let last = candles.last()?;
+        let first = candles.iter().rev().nth(periods.min(candles.len() - 1))?;
+        Some(last.close / first.close - 1.0)
+    }
+
+    // Mean volatility of the pools of the token
+    pub fn token_volatility(&self, token: &String, periods: usize) -> Option<f64> {
+        let volatilities: Vec<f64> = self.pool_tokens.iter()
+            .filter(|(_, (token_a, token_b))| token_a == token || token_b == token)
+            .filter_map(|(pool, _)| self.volatility(pool, periods))
+            .collect();
+        if volatilities.is_empty() {
+            return None;
+        }
+        Some(volatilities.iter().sum::<f64>() / volatilities.len() as f64)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(&file);
+        writer.write_all(serde_json::to_string(&self)?.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path)?;
+        let candle_builder: CandleBuilder = serde_json::from_reader(&file)?;
+        Ok(candle_builder)
+    }
+}
@@ -1 +1,3 @@
-pub mod graphs;
\ No newline at end of file
+pub mod candles;
+pub mod graphs;
+pub mod swap_dataset;
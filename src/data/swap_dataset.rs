@@ -0,0 +1,201 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use anyhow::Result;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcBlockConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_transaction_status::{EncodedTransactionWithStatusMeta, TransactionDetails, UiConfirmedBlock, UiTransactionEncoding};
+
+use crate::markets::swap_events::{decode_swap_events_from_encoded_transaction, get_executed_instructions};
+use crate::markets::types::DexLabel;
+
+const COMPUTE_BUDGET_PROGRAM: &str = "ComputeBudget111111111111111111111111111111";
+// Default compute unit limit of an instruction when the transaction doesn't set it
+const DEFAULT_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+const MAX_COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+
+// One swap of the dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub signature: String,
+    pub signer: String,
+    pub dex_label: DexLabel,
+    pub pool: String,
+    pub token_0to1: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    // Swap fee of the pool, in token in
+    // 0 for Raydium and Whirlpools, their fee rate is in the pool account and not in the transaction
+    pub fee: u64,
+    // Micro-lamports per compute unit
+    pub compute_unit_price: u64,
+    // Priority fee of the transaction in lamports
+    pub priority_fee: u64,
+}
+
+// (compute unit price, priority fee in lamports) of the ComputeBudget instructions of the transaction
+// Same rule as the runtime: the fee is charged on the requested limit, not on meta.compute_units_consumed,
+// without SetComputeUnitLimit the limit is 200k per non ComputeBudget instruction, capped at 1.4M
+fn get_priority_fee(account_keys: &Vec<String>, instructions: &[CompiledInstruction]) -> (u64, u64) {
+    let mut compute_unit_price: u64 = 0;
+    let mut compute_unit_limit: Option<u64> = None;
+    let mut counter_instructions: u64 = 0;
+    for instruction in instructions.iter() {
+        if account_keys[instruction.program_id_index as usize] != COMPUTE_BUDGET_PROGRAM {
+            counter_instructions += 1;
+            continue;
+        }
+        let data = &instruction.data;
+        match data.first() {
+            // SetComputeUnitLimit(u32)
+            Some(2) if data.len() >= 5 => compute_unit_limit = Some(u32::from_le_bytes(data[1..5].try_into().unwrap()) as u64),
+            // SetComputeUnitPrice(u64)
+            Some(3) if data.len() >= 9 => compute_unit_price = u64::from_le_bytes(data[1..9].try_into().unwrap()),
+            _ => {}
+        }
+    }
+    let compute_unit_limit = compute_unit_limit.unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT * counter_instructions).min(MAX_COMPUTE_UNIT_LIMIT);
+    // Micro-lamports rounded up to the lamport
+    let priority_fee = (compute_unit_price as u128 * compute_unit_limit as u128).div_ceil(1_000_000) as u64;
+    (compute_unit_price, priority_fee)
+}
+
+pub fn trade_records_from_transaction(slot: u64, block_time: Option<i64>, transaction: &EncodedTransactionWithStatusMeta) -> Result<Vec<TradeRecord>> {
+    let versioned_transaction = transaction.transaction.decode().ok_or(anyhow::format_err!("Can't decode transaction at slot {}", slot))?;
+    let signature = versioned_transaction.signatures[0].to_string();
+    // Failed transactions have no swap
+    if transaction.meta.as_ref().map_or(true, |meta| meta.err.is_some()) {
+        return Ok(Vec::new());
+    }
+
+    let swap_events = decode_swap_events_from_encoded_transaction(signature.clone(), slot, transaction)?;
+    if swap_events.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (account_keys, _) = get_executed_instructions(&signature, transaction)?;
+    let (compute_unit_price, priority_fee) = get_priority_fee(&account_keys, versioned_transaction.message.instructions());
+
+    let trade_records = swap_events.into_iter()
+        .filter_map(|swap_event| Some(TradeRecord {
+            slot,
+            block_time,
+            signature: signature.clone(),
+            signer: account_keys[0].clone(),
+            dex_label: swap_event.dex_label,
+            pool: swap_event.pool?,
+            token_0to1: swap_event.token_0to1,
+            amount_in: swap_event.amount_in,
+            amount_out: swap_event.amount_out,
+            fee: swap_event.fee,
+            compute_unit_price,
+            priority_fee,
+        }))
+        .collect();
+    Ok(trade_records)
+}
+
+pub fn trade_records_from_block(slot: u64, block: &UiConfirmedBlock) -> Vec<TradeRecord> {
+    let mut trade_records: Vec<TradeRecord> = Vec::new();
+    for transaction in block.transactions.clone().unwrap_or_default().iter() {
+        match trade_records_from_transaction(slot, block.block_time, transaction) {
+            Ok(records) => trade_records.extend(records),
+            Err(e) => error!("❌ Transaction not decoded at slot {}: {:?}", slot, e),
+        }
+    }
+    trade_records
+}
+
+pub fn fetch_block(rpc_client: &RpcClient, slot: u64) -> Result<UiConfirmedBlock> {
+    let block = rpc_client.get_block_with_config(
+        slot,
+        RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            transaction_details: Some(TransactionDetails::Full),
+            rewards: Some(false),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        },
+    )?;
+    Ok(block)
+}
+
+// Block recorded as the JSON result of getBlock (base64 encoding, full transaction details)
+pub fn load_block_fixture(path: &str) -> Result<UiConfirmedBlock> {
+    let data = fs::read_to_string(path)?;
+    let block: UiConfirmedBlock = serde_json::from_str(&data)?;
+    Ok(block)
+}
+
+// Dataset stored as JSON lines, one TradeRecord per line
+pub fn write_trade_records(path: &str, trade_records: &Vec<TradeRecord>) -> Result<()> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(&file);
+    for trade_record in trade_records.iter() {
+        writer.write_all(serde_json::to_string(trade_record)?.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_trade_records(path: &str) -> Result<Vec<TradeRecord>> {
+    let file = OpenOptions::new().read(true).open(path)?;
+    let mut trade_records: Vec<TradeRecord> = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        trade_records.push(serde_json::from_str(&line)?);
+    }
+    Ok(trade_records)
+}
+
+// Ingest the blocks [start_slot, end_slot] from the RPC, skipped slots are ignored
+pub fn build_swap_dataset(rpc_url: String, start_slot: u64, end_slot: u64, output_path: &str) -> Result<usize> {
+    let rpc_client = RpcClient::new(rpc_url);
+    let mut counter_records = 0;
+    for slot in start_slot..=end_slot {
+        let block = match fetch_block(&rpc_client, slot) {
+            Ok(block) => block,
+            Err(e) => {
+                error!("❌ Block {} not fetched: {:?}", slot, e);
+                continue;
+            }
+        };
+        let trade_records = trade_records_from_block(slot, &block);
+        write_trade_records(output_path, &trade_records)?;
+        counter_records += trade_records.len();
+        info!("📚 Block {}: {} swaps", slot, trade_records.len());
+    }
+    info!("📚 {} swaps written in {}", counter_records, output_path);
+    Ok(counter_records)
+}
+
+// Same from recorded blocks named <slot>.json, getBlock results don't include the slot
+// (parent slot + 1 is used when the name is not a slot)
+pub fn build_swap_dataset_from_fixtures(fixtures_paths: Vec<String>, output_path: &str) -> Result<usize> {
+    let mut counter_records = 0;
+    for path in fixtures_paths {
+        let block = load_block_fixture(&path)?;
+        let slot = std::path::Path::new(&path).file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+            .unwrap_or(block.parent_slot + 1);
+        let trade_records = trade_records_from_block(slot, &block);
+        write_trade_records(output_path, &trade_records)?;
+        counter_records += trade_records.len();
+        info!("📚 Fixture {}: {} swaps", path, trade_records.len());
+    }
+    info!("📚 {} swaps written in {}", counter_records, output_path);
+    Ok(counter_records)
+}
@@ -10,7 +10,7 @@ use std::env;
 mod tests {
     use solana_sdk::pubkey::Pubkey;
 
-    use crate::{arbitrage::types::{SwapPathResult, SwapRouteSimulation, TokenInArb}, common::utils::from_str, markets::types::DexLabel, transactions::create_transaction::{create_ata_extendlut_transaction, write_lut_for_market, ChainType, SendOrSimulate}};
+    use crate::{arbitrage::types::{SwapPathResult, SwapRouteSimulation, TokenInArb}, common::utils::from_str, markets::types::DexLabel, transactions::{create_transaction::{create_ata_extendlut_transaction, write_lut_for_market, ChainType, SendOrSimulate}, signer::SignerService}};
 
     #[test]
     fn write_in_write_lut_for_market() {
@@ -75,16 +75,864 @@ mod tests {
             amount_in: 300000000,
             estimated_amount_out: "300776562".to_string(),
             estimated_min_amount_out: "297798576".to_string(),
-            result: 776562.0
+            result: 776562.0,
+            slot: None,
+            result_usd: None
         };
         
         let tokens: Vec<Pubkey> = tokens_to_arb.into_iter().map(|tok| from_str(tok.address.as_str()).unwrap()).collect();
         let _ = create_ata_extendlut_transaction(
             ChainType::Devnet,
+            SignerService::from_env().unwrap(),
             SendOrSimulate::Send,
             spr.clone(),
             from_str("6nGymM5X1djYERKZtoZ3Yz3thChMVF6jVRDzhhcmxuee").unwrap(),
             tokens
         ).await;       
     }
-}
\ No newline at end of file
+    #[test]
+    fn leader_sender_sends_to_local_leader_tpu() {
+        use std::{collections::{BTreeMap, HashMap}, net::UdpSocket, time::Duration};
+        use crate::transactions::leader_sender::{LeaderSender, LeaderState, LeaderTpu};
+
+        // Local stand-in for the TPU of the leader of the current slot
+        let tpu_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        tpu_socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let leader = Pubkey::new_unique();
+        let next_leader = Pubkey::new_unique();
+        let state = LeaderState {
+            slot: 10,
+            updated_at: None,
+            leaders_by_slot: BTreeMap::from([(10, leader), (11, leader), (12, next_leader)]),
+            tpu_by_leader: HashMap::from([(leader, LeaderTpu{ leader, tpu: Some(tpu_socket.local_addr().unwrap()), tpu_quic: None })]),
+        };
+        let sender = LeaderSender::with_schedule(state, 2, false);
+
+        assert_eq!(sender.current_leader(), Some(leader));
+        // next_leader has no known TPU, only the current leader receives the transaction
+        assert_eq!(sender.send_wire_transaction(b"wire_transaction").unwrap(), 1);
+        let mut buffer = [0u8; 64];
+        let (size, _) = tpu_socket.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer[..size], b"wire_transaction");
+    }
+    #[tokio::test]
+    async fn leader_sender_holds_until_preferred_leader() {
+        use std::{collections::{BTreeMap, HashMap, HashSet}, net::UdpSocket, time::Duration};
+        use solana_sdk::transaction::VersionedTransaction;
+        use crate::transactions::leader_sender::{LeaderSender, LeaderState, LeaderTpu};
+
+        let tpu_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        tpu_socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let leader = Pubkey::new_unique();
+        let state = LeaderState {
+            slot: 10,
+            updated_at: None,
+            leaders_by_slot: BTreeMap::from([(10, leader)]),
+            tpu_by_leader: HashMap::from([(leader, LeaderTpu{ leader, tpu: Some(tpu_socket.local_addr().unwrap()), tpu_quic: None })]),
+        };
+        let transaction = VersionedTransaction::default();
+
+        // The current leader is preferred: sent at once
+        let sender = LeaderSender::with_schedule(state.clone(), 0, false).with_preferred_leaders(HashSet::from([leader]), 100);
+        assert_eq!(sender.send_in_leader_window(&transaction).await.unwrap(), 1);
+        // No preferred leader in the next 0 slots: sent anyway to the current leader
+        let sender = LeaderSender::with_schedule(state, 0, false).with_preferred_leaders(HashSet::from([Pubkey::new_unique()]), 0);
+        assert_eq!(sender.send_in_leader_window(&transaction).await.unwrap(), 1);
+        let mut buffer = [0u8; 1232];
+        for _ in 0..2 {
+            let (size, _) = tpu_socket.recv_from(&mut buffer).unwrap();
+            assert_eq!(&buffer[..size], bincode::serialize(&transaction).unwrap().as_slice());
+        }
+    }
+    #[test]
+    fn decode_and_check_pyth_price_account() {
+        use crate::common::prices::{check_pyth_price, decode_pyth_price, PYTH_MAX_AGE_SLOTS, PYTH_MAX_CONF_RATIO};
+
+        // SOL/USD price account synthesized with the pyth-client v2 layout (not recorded on chain): 145.23 +/- 0.07 trading at slot 280000000
+        let mut data = vec![0u8; 3312];
+        data[0..4].copy_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        data[8..12].copy_from_slice(&3u32.to_le_bytes());
+        data[20..24].copy_from_slice(&(-8i32).to_le_bytes());
+        data[96..104].copy_from_slice(&1717000000i64.to_le_bytes());
+        data[208..216].copy_from_slice(&14523000000i64.to_le_bytes());
+        data[216..224].copy_from_slice(&7000000u64.to_le_bytes());
+        data[224..228].copy_from_slice(&1u32.to_le_bytes());
+        data[232..240].copy_from_slice(&280000000u64.to_le_bytes());
+
+        let pyth_price = decode_pyth_price(&data).unwrap();
+        assert_eq!(pyth_price.expo, -8);
+        assert!((pyth_price.price_f64() - 145.23).abs() < 1e-9);
+        assert!((check_pyth_price(&pyth_price, 280000010, PYTH_MAX_AGE_SLOTS, PYTH_MAX_CONF_RATIO).unwrap() - 145.23).abs() < 1e-9);
+        // Stale price
+        assert!(check_pyth_price(&pyth_price, 280000000 + PYTH_MAX_AGE_SLOTS + 1, PYTH_MAX_AGE_SLOTS, PYTH_MAX_CONF_RATIO).is_err());
+        // Not a price account
+        data[8..12].copy_from_slice(&2u32.to_le_bytes());
+        assert!(decode_pyth_price(&data).is_err());
+    }
+    // AmmInfo bytes of a Raydium pool: status, swap fee (numerator, denominator) and need_take_pnl_pc, other fields at 0
+    fn raydium_amm_info_data(status: u64, swap_fee: (u64, u64), need_take_pnl_pc: u64) -> Vec<u8> {
+        let mut data = vec![0u8; std::mem::size_of::<raydium_amm::state::AmmInfo>()];
+        data[0..8].copy_from_slice(&status.to_le_bytes());
+        data[176..184].copy_from_slice(&swap_fee.0.to_le_bytes());
+        data[184..192].copy_from_slice(&swap_fee.1.to_le_bytes());
+        data[200..208].copy_from_slice(&need_take_pnl_pc.to_le_bytes());
+        data
+    }
+    fn market_with_data(dex_label: DexLabel, account_data: Vec<u8>) -> crate::markets::types::Market {
+        crate::markets::types::Market {
+            tokenMintA: String::new(),
+            tokenVaultA: String::new(),
+            tokenMintB: String::new(),
+            tokenVaultB: String::new(),
+            dexLabel: dex_label,
+            fee: 0,
+            id: Pubkey::new_unique().to_string(),
+            account_data: Some(account_data),
+            liquidity: None,
+            slot: Some(42),
+        }
+    }
+    // Local mock of a JSON-RPC API answering `requests` requests with `respond(method)` (the result or error field)
+    fn spawn_mock_json_rpc(requests: usize, respond: fn(&str) -> serde_json::Value) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let (url, server) = spawn_mock_json_rpc_with_params(requests, move |method, _| respond(method));
+        let methods = std::thread::spawn(move || server.join().unwrap().iter().map(|body| body["method"].as_str().unwrap().to_string()).collect());
+        (url, methods)
+    }
+    // Same with `respond(method, params)`, return the bodies of the requests
+    fn spawn_mock_json_rpc_with_params(requests: usize, respond: impl Fn(&str, &serde_json::Value) -> serde_json::Value + Send + 'static) -> (String, std::thread::JoinHandle<Vec<serde_json::Value>>) {
+        use std::{io::{Read, Write}, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies: Vec<serde_json::Value> = Vec::new();
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request: Vec<u8> = Vec::new();
+                let mut buffer = [0u8; 4096];
+                // Read the headers and the body of Content-Length
+                loop {
+                    let size = stream.read(&mut buffer).unwrap();
+                    if size == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..size]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some(headers_end) = text.find("\r\n\r\n") {
+                        let content_length = text.lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if request.len() >= headers_end + 4 + content_length {
+                            break;
+                        }
+                    }
+                }
+                let text = String::from_utf8_lossy(&request).to_string();
+                let body: serde_json::Value = serde_json::from_str(&text[text.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+                let method = body["method"].as_str().unwrap().to_string();
+                let mut response = respond(method.as_str(), &body["params"]);
+                response["jsonrpc"] = serde_json::json!("2.0");
+                response["id"] = serde_json::json!(1);
+                let response = response.to_string();
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", response.len(), response).unwrap();
+                bodies.push(body);
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn bundle_sender_with_local_block_engine() {
+        use solana_sdk::{hash::Hash, message::{v0, VersionedMessage}, signature::{Keypair, Signer}, transaction::VersionedTransaction};
+        use crate::transactions::jito::{create_tip_instruction, BundleSender, BundleStatus};
+
+        let (block_engine_url, block_engine) = spawn_mock_json_rpc(2, |method| match method {
+            "sendBundle" => serde_json::json!({ "result": "bundle-id" }),
+            _ => serde_json::json!({ "result": { "context": { "slot": 42 }, "value": [{ "bundle_id": "bundle-id", "status": "Landed", "landed_slot": 42 }] } }),
+        });
+
+        let payer = Keypair::new();
+        let tip_ix = create_tip_instruction(&payer.pubkey(), 10000);
+        let transaction = VersionedTransaction::try_new(
+            VersionedMessage::V0(v0::Message::try_compile(&payer.pubkey(), &[tip_ix], &[], Hash::default()).unwrap()),
+            &[&payer],
+        ).unwrap();
+
+        let bundle_sender = BundleSender::new(block_engine_url, 10000, true);
+        let bundle_id = bundle_sender.send_bundle(&[transaction]).await.unwrap();
+        assert_eq!(bundle_id, "bundle-id");
+        let status = bundle_sender.wait_for_bundle(&bundle_id, std::time::Duration::from_secs(1)).await.unwrap();
+        assert_eq!(status, BundleStatus::Landed(42));
+        assert_eq!(block_engine.join().unwrap(), vec!["sendBundle", "getInflightBundleStatuses"]);
+    }
+    #[tokio::test]
+    async fn broadcaster_with_local_rpc_endpoints() {
+        use solana_sdk::{hash::Hash, message::{v0, VersionedMessage}, signature::{Keypair, Signer}, system_instruction, transaction::VersionedTransaction};
+        use crate::transactions::broadcaster::{BroadcastEndpoint, Broadcaster};
+
+        let (fast_url, fast_rpc) = spawn_mock_json_rpc(1, |_| serde_json::json!({ "result": "signature" }));
+        let (slow_url, slow_rpc) = spawn_mock_json_rpc(1, |_| {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            serde_json::json!({ "result": "signature" })
+        });
+        let (failing_url, failing_rpc) = spawn_mock_json_rpc(1, |_| serde_json::json!({ "error": { "code": -32002, "message": "Transaction simulation failed" } }));
+
+        let payer = Keypair::new();
+        let transfer_ix = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+        let transaction = VersionedTransaction::try_new(
+            VersionedMessage::V0(v0::Message::try_compile(&payer.pubkey(), &[transfer_ix], &[], Hash::default()).unwrap()),
+            &[&payer],
+        ).unwrap();
+
+        let broadcaster = Broadcaster::new(vec![
+            BroadcastEndpoint::Rpc(fast_url.clone()),
+            BroadcastEndpoint::Rpc(slow_url.clone()),
+            BroadcastEndpoint::Rpc(failing_url.clone()),
+        ]);
+        let signature = broadcaster.broadcast(&transaction).await.unwrap();
+        assert_eq!(signature, transaction.signatures[0]);
+        assert_eq!(broadcaster.record_landing(&signature, true), Some(fast_url.clone()));
+        for rpc in [fast_rpc, slow_rpc, failing_rpc] {
+            assert_eq!(rpc.join().unwrap(), vec!["sendTransaction"]);
+        }
+
+        let stats = broadcaster.get_stats();
+        assert_eq!(stats[&fast_url].fastest_ack, 1);
+        assert_eq!(stats[&slow_url].fastest_ack, 0);
+        assert_eq!(stats[&failing_url].drop_rate(), 1.0);
+        assert!(stats[&slow_url].average_latency_ms() > stats[&fast_url].average_latency_ms());
+        // Routing weights follow the stats
+        assert!(stats[&fast_url].weight > stats[&slow_url].weight);
+        assert_eq!(stats[&failing_url].weight, 0.0);
+    }
+    #[test]
+    fn classify_failed_arbitrages() {
+        use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+        use crate::transactions::confirmation::{classify_failure, FailureKind};
+
+        let custom_error = TransactionError::InstructionError(3, InstructionError::Custom(6000));
+        let guard_logs = vec!["Program log: AnchorError occurred. Error Code: NotProfitable. Error Number: 6000.".to_string()];
+        assert_eq!(classify_failure(&custom_error, &guard_logs), FailureKind::NotProfitable);
+        let swap_logs = vec!["Program log: Error: exceeds desired slippage limit".to_string()];
+        assert_eq!(classify_failure(&custom_error, &swap_logs), FailureKind::Slippage);
+        assert_eq!(classify_failure(&TransactionError::InsufficientFundsForFee, &vec![]), FailureKind::InsufficientFunds);
+        assert!(matches!(classify_failure(&custom_error, &vec![]), FailureKind::Other(_)));
+    }
+    #[test]
+    fn signer_service_with_keystore_and_remote_signer() {
+        use std::{io::{BufRead, BufReader, Write}, os::unix::net::UnixListener, sync::Arc};
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use solana_sdk::signature::{Keypair, Signer};
+        use crate::transactions::signer::{decrypt_keystore, encrypt_keystore, RemoteSigner};
+
+        let payer = Keypair::new();
+        let keystore = encrypt_keystore(&payer, "password").unwrap();
+        assert_eq!(decrypt_keystore(&keystore, "password").unwrap().pubkey(), payer.pubkey());
+        assert!(decrypt_keystore(&keystore, "wrong password").is_err());
+
+        // Local stand-in for the signer process, one connection per request
+        let socket_path = std::env::temp_dir().join(format!("remote-signer-{}.sock", payer.pubkey()));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let remote_keypair = Keypair::from_bytes(&payer.to_bytes()).unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                let response = match request["method"].as_str() {
+                    Some("getPubkey") => serde_json::json!({ "pubkey": remote_keypair.pubkey().to_string() }),
+                    _ => {
+                        let message = STANDARD.decode(request["message"].as_str().unwrap()).unwrap();
+                        serde_json::json!({ "signature": remote_keypair.sign_message(&message).to_string() })
+                    }
+                };
+                stream.write_all(format!("{}\n", response).as_bytes()).unwrap();
+            }
+        });
+        let remote_signer = RemoteSigner::connect(socket_path.to_string_lossy().to_string()).unwrap();
+        assert_eq!(remote_signer.pubkey(), payer.pubkey());
+
+        // Fees paid by another account: fee payer first, both sign
+        let fee_payer = Keypair::new();
+        let signer = SignerService::new(Arc::new(remote_signer), Some(Arc::new(fee_payer.insecure_clone())));
+        assert_eq!(signer.fee_payer(), fee_payer.pubkey());
+        let signers = signer.signers();
+        assert_eq!(signers.iter().map(|signer| signer.pubkey()).collect::<Vec<Pubkey>>(), vec![fee_payer.pubkey(), payer.pubkey()]);
+        let message = b"arbitrage";
+        assert!(signers[1].sign_message(message).verify(payer.pubkey().as_ref(), message));
+        let _ = std::fs::remove_file(&socket_path);
+    }
+    #[test]
+    fn nonce_accounts_acquire_release_and_cancel() {
+        use std::sync::Arc;
+        use solana_client::rpc_client::RpcClient;
+        use solana_sdk::{hash::Hash, signature::Keypair};
+        use crate::transactions::nonce::{acquire_nonce, cancel_nonce, get_nonce_account, load_nonce_account, release_nonce};
+
+        let signer = SignerService::new(Arc::new(Keypair::new()), None);
+        // Nothing listens here: the advances of the unlanded transactions fail
+        let rpc_client = RpcClient::new("http://127.0.0.1:9".to_string());
+        let (first_address, second_address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        load_nonce_account(first_address, Hash::new_unique());
+        load_nonce_account(second_address, Hash::new_unique());
+
+        let first = acquire_nonce(&rpc_client, &signer).unwrap();
+        let second = acquire_nonce(&rpc_client, &signer).unwrap();
+        assert_ne!(first.0, second.0);
+        assert_eq!(acquire_nonce(&rpc_client, &signer), None);
+        // Never sent: the same nonce is given again
+        cancel_nonce(&first.0);
+        assert_eq!(acquire_nonce(&rpc_client, &signer), Some(first));
+        // Not landed and not advanced: free again but advanced before its next use, skipped while it fails
+        release_nonce(&rpc_client, &signer, &first.0, &first.1, false);
+        let account = get_nonce_account(&first.0).unwrap();
+        assert!(account.pending_advance && !account.in_use);
+        assert_eq!(acquire_nonce(&rpc_client, &signer), None);
+        assert!(!get_nonce_account(&first.0).unwrap().in_use);
+        assert!(get_nonce_account(&second.0).unwrap().in_use);
+    }
+    #[test]
+    fn realized_profit_with_a_separate_fee_payer() {
+        use solana_transaction_status::UiTransactionStatusMeta;
+        use crate::transactions::{confirmation::get_realized_profit, wsol::WSOL_MINT};
+
+        let (fee_payer, payer, pool) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let token_balance = |amount: u64| serde_json::json!([{
+            "accountIndex": 3,
+            "mint": WSOL_MINT,
+            "uiTokenAmount": { "uiAmount": amount as f64 / 1e9, "decimals": 9, "amount": amount.to_string(), "uiAmountString": (amount as f64 / 1e9).to_string() },
+            "owner": payer.to_string(),
+            "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+        }]);
+        // The fee payer pays 10000 lamports, the payer gets 100000 lamports in SOL and 5000 in wSOL
+        let meta: UiTransactionStatusMeta = serde_json::from_value(serde_json::json!({
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 10000,
+            "preBalances": [1_000_000_000u64, 500_000_000u64, 1u64, 2_039_280u64],
+            "postBalances": [999_990_000u64, 500_100_000u64, 1u64, 2_039_280u64],
+            "preTokenBalances": token_balance(300_000_000),
+            "postTokenBalances": token_balance(300_005_000),
+        })).unwrap();
+        let account_keys: Vec<String> = vec![fee_payer, payer, pool, Pubkey::new_unique()].iter().map(|key| key.to_string()).collect();
+        assert_eq!(get_realized_profit(&meta, &account_keys, &payer, &WSOL_MINT.to_string()), 105_000);
+        // Same transaction with the payer as fee payer
+        let account_keys: Vec<String> = vec![payer, fee_payer, pool, Pubkey::new_unique()].iter().map(|key| key.to_string()).collect();
+        assert_eq!(get_realized_profit(&meta, &account_keys, &payer, &WSOL_MINT.to_string()), -5_000);
+    }
+    #[test]
+    fn profit_guard_instructions_match_the_program() {
+        use anchor_lang::{InstructionData, ToAccountMetas};
+        use anchor_spl::associated_token::get_associated_token_address;
+        use solana_sdk::system_program;
+        use crate::transactions::{profit_guard::{construct_profit_guard_instructions, get_guard_address}, wsol::WSOL_MINT};
+
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let base_token = from_str(WSOL_MINT).unwrap();
+        let guard = get_guard_address(&program_id, &payer);
+        assert_eq!(guard, Pubkey::find_program_address(&[profit_guard::GUARD_SEED, payer.as_ref()], &program_id).0);
+        let token_account = get_associated_token_address(&payer, &base_token);
+
+        let (record_start_balance, check_profit) = construct_profit_guard_instructions(program_id, payer, base_token, 5000);
+        assert_eq!(record_start_balance.instruction.program_id, program_id);
+        assert_eq!(record_start_balance.instruction.data, profit_guard::instruction::RecordStartBalance {}.data());
+        assert_eq!(record_start_balance.instruction.accounts, profit_guard::accounts::RecordStartBalance { guard, token_account, payer, system_program: system_program::id() }.to_account_metas(None));
+        assert_eq!(check_profit.instruction.data, profit_guard::instruction::CheckProfit { min_profit: 5000 }.data());
+        assert_eq!(check_profit.instruction.accounts, profit_guard::accounts::CheckProfit { guard, token_account, payer }.to_account_metas(None));
+    }
+    #[test]
+    fn profit_guard_requires_the_min_profit() {
+        use profit_guard::{require_min_profit, ProfitGuardError};
+
+        assert!(require_min_profit(1_000_000, 1_005_000, 5000).is_ok());
+        assert!(require_min_profit(1_000_000, 1_010_000, 5000).is_ok());
+        assert_eq!(require_min_profit(1_000_000, 1_004_999, 5000).unwrap_err(), ProfitGuardError::NotProfitable.into());
+        assert_eq!(require_min_profit(1_000_000, 999_000, 0).unwrap_err(), ProfitGuardError::NotProfitable.into());
+        assert_eq!(require_min_profit(u64::MAX, u64::MAX, 1).unwrap_err(), ProfitGuardError::MathOverflow.into());
+    }
+    #[test]
+    fn priority_fee_percentile_and_profit_cap() {
+        use crate::transactions::priority_fees::{cap_compute_unit_price, get_percentile};
+
+        let fees: Vec<u64> = vec![500, 0, 100, 10_000, 2_000];
+        assert_eq!(get_percentile(&fees, 0.0), 0);
+        assert_eq!(get_percentile(&fees, 50.0), 500);
+        assert_eq!(get_percentile(&fees, 75.0), 2_000);
+        assert_eq!(get_percentile(&fees, 100.0), 10_000);
+        assert_eq!(get_percentile(&fees, 150.0), 10_000);
+        assert_eq!(get_percentile(&vec![], 75.0), 0);
+        // 30% of 100000 lamports over 200000 CU: at most 150000 micro-lamports per CU
+        assert_eq!(cap_compute_unit_price(1_000_000, 200_000, 100_000, 0.3), 150_000);
+        assert_eq!(cap_compute_unit_price(50_000, 200_000, 100_000, 0.3), 50_000);
+        assert_eq!(cap_compute_unit_price(50_000, 200_000, 0, 0.3), 0);
+        assert_eq!(cap_compute_unit_price(50_000, 0, 100_000, 0.3), 50_000);
+    }
+    #[test]
+    fn plan_compute_units_with_margin_and_cap() {
+        use solana_sdk::compute_budget::ComputeBudgetInstruction;
+        use crate::transactions::{compute_units::{plan_compute_units, MAX_COMPUTE_UNITS}, create_transaction::InstructionDetails};
+
+        // Instructions without market only, the profile of an empty DEX sequence
+        let instructions: Vec<InstructionDetails> = (0..2).map(|_| InstructionDetails {
+            instruction: ComputeBudgetInstruction::set_compute_unit_price(0),
+            details: "Set priority fees".to_string(),
+            market: None,
+        }).collect();
+        assert_eq!(plan_compute_units(None, &instructions), 23_000);
+        assert_eq!(plan_compute_units(Some(100_000), &instructions), 115_000);
+        // The simulated consumption is now the profile of the path
+        assert_eq!(plan_compute_units(None, &instructions), 115_000);
+        assert_eq!(plan_compute_units(Some(1_300_000), &instructions), MAX_COMPUTE_UNITS);
+    }
+    #[test]
+    fn swap_amounts_of_the_slippage_policies() {
+        use crate::transactions::slippage::{get_swap_amounts, SlippagePolicy};
+
+        let route_simulation = |amount_in: u64, estimated_amount_out: &str, estimated_min_amount_out: &str| SwapRouteSimulation {
+            id_route: 0,
+            pool_address: Pubkey::new_unique().to_string(),
+            dex_label: DexLabel::RAYDIUM,
+            token_0to1: true,
+            token_in: Pubkey::new_unique().to_string(),
+            token_out: Pubkey::new_unique().to_string(),
+            amount_in,
+            estimated_amount_out: estimated_amount_out.to_string(),
+            estimated_min_amount_out: estimated_min_amount_out.to_string(),
+        };
+        let mut spr = SwapPathResult {
+            path_id: 1,
+            hops: 2,
+            tokens_path: "SOL-USDC-SOL".to_string(),
+            route_simulations: vec![
+                route_simulation(1_000_000_000, "150000000", "149000000"),
+                route_simulation(150_000_000, "1010000000", "1000000000"),
+            ],
+            token_in: "So11111111111111111111111111111111111111112".to_string(),
+            token_in_symbol: "SOL".to_string(),
+            token_out: "So11111111111111111111111111111111111111112".to_string(),
+            token_out_symbol: "SOL".to_string(),
+            amount_in: 1_000_000_000,
+            estimated_amount_out: "1010000000".to_string(),
+            estimated_min_amount_out: "1000000000".to_string(),
+            result: 10_000_000.0,
+            slot: None,
+            result_usd: None,
+        };
+        // The second hop spends the minimum of the first one, its estimation scaled down to it
+        assert_eq!(get_swap_amounts(&spr, &SlippagePolicy::PerHop { bps: 100 }), vec![(1_000_000_000, 148_500_000), (148_500_000, 989_901_000)]);
+        assert_eq!(get_swap_amounts(&spr, &SlippagePolicy::FinalLeg { bps: 50 }), vec![(1_000_000_000, 149_000_000), (149_000_000, 998_250_332)]);
+        assert_eq!(get_swap_amounts(&spr, &SlippagePolicy::RequiredProfit { min_profit: 5_000 }), vec![(1_000_000_000, 149_000_000), (149_000_000, 1_000_005_000)]);
+        // No min for this DEX: the intermediate hop at `bps` under its estimation
+        spr.route_simulations[0].estimated_min_amount_out = "0".to_string();
+        assert_eq!(get_swap_amounts(&spr, &SlippagePolicy::FinalLeg { bps: 50 }), vec![(1_000_000_000, 149_250_000), (149_250_000, 999_925_250)]);
+        for policy in [SlippagePolicy::PerHop { bps: 100 }, SlippagePolicy::FinalLeg { bps: 50 }, SlippagePolicy::RequiredProfit { min_profit: 5_000 }] {
+            let swap_amounts = get_swap_amounts(&spr, &policy);
+            assert!(swap_amounts.windows(2).all(|hops| hops[1].0 == hops[0].1));
+        }
+        spr.route_simulations.clear();
+        assert!(get_swap_amounts(&spr, &SlippagePolicy::default()).is_empty());
+    }
+    #[test]
+    fn lut_extend_batches_and_authority_offset() {
+        use std::borrow::Cow;
+        use solana_sdk::{address_lookup_table::{instruction::ProgramInstruction, state::{AddressLookupTable, LookupTableMeta}}, packet::PACKET_DATA_SIZE, transaction::Transaction};
+        use crate::transactions::lut_manager::{get_extend_instructions, EXTEND_BATCH_SIZE, LUT_AUTHORITY_OFFSET};
+
+        let (lut_address, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let addresses: Vec<Pubkey> = (0..45).map(|_| Pubkey::new_unique()).collect();
+        let instructions = get_extend_instructions(lut_address, authority, &addresses);
+        let batches: Vec<Vec<Pubkey>> = instructions.iter().map(|instruction| match bincode::deserialize(&instruction.data).unwrap() {
+            ProgramInstruction::ExtendLookupTable { new_addresses } => new_addresses,
+            _ => panic!("Not an extend instruction"),
+        }).collect();
+        assert_eq!(batches.iter().map(|batch| batch.len()).collect::<Vec<usize>>(), vec![EXTEND_BATCH_SIZE, EXTEND_BATCH_SIZE, 5]);
+        assert_eq!(batches.concat(), addresses);
+        // A full batch fits in one signed transaction
+        let transaction = Transaction::new_with_payer(&instructions[0..1], Some(&authority));
+        assert!(bincode::serialized_size(&transaction).unwrap() as usize <= PACKET_DATA_SIZE);
+
+        // getProgramAccounts filter of the tables of the payer
+        let table = AddressLookupTable {
+            meta: LookupTableMeta { authority: Some(authority), ..LookupTableMeta::default() },
+            addresses: Cow::Owned(addresses),
+        };
+        let data = table.serialize_for_tests().unwrap();
+        assert_eq!(&data[LUT_AUTHORITY_OFFSET..LUT_AUTHORITY_OFFSET + 32], authority.as_ref());
+    }
+    #[test]
+    fn wrap_and_unwrap_around_the_profit_guard() {
+        use solana_sdk::instruction::Instruction;
+        use crate::transactions::{create_transaction::{wrap_swap_instructions, InstructionDetails}, wsol::WSOL_MINT};
+
+        let (payer, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let swap = InstructionDetails { instruction: Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]), details: "Swap".to_string(), market: None };
+        let details = |instructions: Vec<InstructionDetails>| instructions.into_iter().map(|instruction| instruction.details).collect::<Vec<String>>();
+
+        let instructions = wrap_swap_instructions(vec![swap.clone()], payer, from_str(WSOL_MINT).unwrap(), Some((program_id, 0)), Some(1_000_000));
+        assert_eq!(details(instructions), vec![
+            "Wrap SOL: create wSOL account", "Wrap SOL: transfer", "Wrap SOL: sync native",
+            "Profit guard: record start balance", "Swap", "Profit guard: check profit", "Unwrap SOL: close wSOL account",
+        ]);
+        let instructions = wrap_swap_instructions(vec![swap.clone()], payer, from_str(WSOL_MINT).unwrap(), None, Some(1_000_000));
+        assert_eq!(details(instructions), vec!["Wrap SOL: create wSOL account", "Wrap SOL: transfer", "Wrap SOL: sync native", "Swap", "Unwrap SOL: close wSOL account"]);
+        let instructions = wrap_swap_instructions(vec![swap], payer, from_str(WSOL_MINT).unwrap(), Some((program_id, 0)), None);
+        assert_eq!(details(instructions), vec!["Profit guard: record start balance", "Swap", "Profit guard: check profit"]);
+    }
+    #[test]
+    fn raydium_quote_from_fixed_account_bytes() {
+        use anchor_spl::token::spl_token;
+        use raydium_amm::{math::{Calculator, CheckedCeilDiv, SwapDirection, U128}, state::{AmmInfo, Loadable}};
+        use solana_program::program_pack::Pack;
+        use solana_sdk::account::Account;
+        use crate::markets::raydium::compute_raydium_reserves;
+
+        // AmmInfo of a SwapOnly pool (no orderbook), swap fee 25/10000 and 50 USDC of pnl to take
+        let amm_data = raydium_amm_info_data(6, (25, 10000), 50_000_000);
+        let amm = *AmmInfo::load_from_bytes(&amm_data).unwrap();
+        let vault = |amount: u64| {
+            let mut data = vec![0u8; spl_token::state::Account::LEN];
+            spl_token::state::Account::pack(spl_token::state::Account { amount, state: spl_token::state::AccountState::Initialized, ..Default::default() }, &mut data).unwrap();
+            Account { lamports: 2_039_280, data, owner: spl_token::id(), executable: false, rent_epoch: 0 }
+        };
+
+        // 1000 SOL / 150k USDC
+        let reserves = compute_raydium_reserves(Pubkey::new_unique(), &amm, &vault(1_000_000_000_000), &vault(150_000_000_000), None, 42).unwrap();
+        assert_eq!((reserves.total_pc_without_take_pnl, reserves.total_coin_without_take_pnl), Calculator::calc_total_without_take_pnl_no_orderbook(150_000_000_000, 1_000_000_000_000, &amm).unwrap());
+        assert_eq!((reserves.total_pc_without_take_pnl, reserves.total_coin_without_take_pnl), (149_950_000_000, 1_000_000_000_000));
+        assert_eq!((reserves.swap_fee_numerator, reserves.swap_fee_denominator, reserves.orderbook_enabled, reserves.slot), (25, 10000, false, 42));
+
+        // Same steps than process_swap_base_in(): fee rounded up, then constant product
+        let swap_fee = U128::from(1_000_000_000u64).checked_mul(25u64.into()).unwrap().checked_ceil_div(10000u64.into()).unwrap().0;
+        let amount_out = Calculator::swap_token_amount_base_in(U128::from(1_000_000_000u64).checked_sub(swap_fee).unwrap(), 149_950_000_000u64.into(), 1_000_000_000_000u64.into(), SwapDirection::Coin2PC).as_u64();
+        assert_eq!(reserves.quote_swap_base_in(1_000_000_000, true), amount_out);
+        assert_eq!(amount_out, 149_426_072);
+        assert_eq!(reserves.quote_swap_base_in(150_000_000, false), 996_837_933);
+    }
+    #[test]
+    fn decode_raydium_ray_logs() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use raydium_amm::log::{SwapBaseInLog, SwapBaseOutLog};
+        use crate::markets::swap_events::{decode_raydium_swap_log, decode_swap_events_from_logs, RAYDIUM_AMM_PROGRAM};
+
+        // Encoded like encode_ray_log() of the program: bincode then base64
+        let swap_base_in = STANDARD.encode(bincode::serialize(&SwapBaseInLog { log_type: 3, amount_in: 1_000_000_000, minimum_out: 140_000_000, direction: 2, user_source: 5_000_000_000, pool_coin: 1_000_000_000_000, pool_pc: 150_000_000_000, out_amount: 149_426_072 }).unwrap());
+        let swap_base_out = STANDARD.encode(bincode::serialize(&SwapBaseOutLog { log_type: 4, max_in: 160_000_000, amount_out: 1_000_000_000, direction: 1, user_source: 500_000_000, pool_coin: 1_000_000_000_000, pool_pc: 150_000_000_000, deduct_in: 150_527_183 }).unwrap());
+
+        let swap_log = decode_raydium_swap_log(&swap_base_out).unwrap();
+        assert_eq!((swap_log.base_in, swap_log.amount_in, swap_log.amount_out, swap_log.direction), (false, 150_527_183, 1_000_000_000, 1));
+        assert!(decode_raydium_swap_log(&STANDARD.encode([0u8; 20])).is_none());
+
+        let logs: Vec<String> = vec![
+            format!("Program {} invoke [1]", RAYDIUM_AMM_PROGRAM),
+            format!("Program log: ray_log: {}", swap_base_in),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]".to_string(),
+            "Program log: Instruction: Transfer".to_string(),
+            // Not written by the Raydium program
+            format!("Program log: ray_log: {}", swap_base_out),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success".to_string(),
+            format!("Program {} consumed 31258 of 200000 compute units", RAYDIUM_AMM_PROGRAM),
+            format!("Program {} success", RAYDIUM_AMM_PROGRAM),
+        ];
+        let swap_events = decode_swap_events_from_logs("signature".to_string(), 42, &logs, Some("pool".to_string()));
+        assert_eq!(swap_events.len(), 1);
+        let swap_event = &swap_events[0];
+        assert_eq!((swap_event.pool.clone(), swap_event.slot, swap_event.token_0to1), (Some("pool".to_string()), 42, true));
+        assert_eq!((swap_event.amount_in, swap_event.amount_out, swap_event.fee), (1_000_000_000, 149_426_072, 0));
+        assert_eq!(swap_event.reserves_before, Some((1_000_000_000_000, 150_000_000_000)));
+    }
+    #[test]
+    fn decode_meteora_event_cpi_swap() {
+        use anchor_lang::Event;
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use crate::markets::swap_events::{decode_meteora_swap_event, decode_swap_events_from_logs, METEORA_DLMM_PROGRAM};
+
+        let lb_pair = Pubkey::new_unique();
+        let swap = lb_clmm::events::Swap { lb_pair, from: Pubkey::new_unique(), start_bin_id: -120, end_bin_id: -123, amount_in: 2_000_000_000, amount_out: 297_000_000, swap_for_y: true, fee: 3_000_000, protocol_fee: 150_000, fee_bps: 15, host_fee: 0 };
+        // Data of the self invoke of emit_cpi!(): EVENT_IX_TAG_LE + discriminator + borsh data
+        let event_cpi_data = [anchor_lang::event::EVENT_IX_TAG_LE, &swap.data()[..]].concat();
+        let decoded = decode_meteora_swap_event(&event_cpi_data).unwrap();
+        assert_eq!((decoded.lb_pair, decoded.start_bin_id, decoded.end_bin_id, decoded.amount_in, decoded.amount_out, decoded.swap_for_y, decoded.fee), (lb_pair, -120, -123, 2_000_000_000, 297_000_000, true, 3_000_000));
+        // Another event
+        assert!(decode_meteora_swap_event(&[anchor_lang::event::EVENT_IX_TAG_LE, &[0u8; 16][..]].concat()).is_none());
+
+        // emit!() writes the same data without the tag in a "Program data:" log
+        let logs: Vec<String> = vec![
+            format!("Program {} invoke [1]", METEORA_DLMM_PROGRAM),
+            "Program log: Instruction: Swap".to_string(),
+            format!("Program data: {}", STANDARD.encode(swap.data())),
+            format!("Program {} success", METEORA_DLMM_PROGRAM),
+        ];
+        let swap_events = decode_swap_events_from_logs("signature".to_string(), 42, &logs, None);
+        assert_eq!(swap_events.len(), 1);
+        assert_eq!((swap_events[0].pool.clone(), swap_events[0].token_0to1, swap_events[0].fee, swap_events[0].bins), (Some(lb_pair.to_string()), true, 3_000_000, Some((-120, -123))));
+    }
+    #[test]
+    fn infer_whirlpool_swaps_from_transfers() {
+        use crate::markets::swap_events::{decode_whirlpool_swaps, ExecutedInstruction, WHIRLPOOL_PROGRAM};
+
+        let token_program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".to_string();
+        let pool = Pubkey::new_unique().to_string();
+        // swap args: amount, other_amount_threshold, sqrt_price_limit, amount_specified_is_input, a_to_b
+        let mut swap_data = solana_sdk::hash::hash(b"global:swap").to_bytes()[..8].to_vec();
+        swap_data.extend(1_000_000_000u64.to_le_bytes());
+        swap_data.extend(140_000_000u64.to_le_bytes());
+        swap_data.extend(4295048016u128.to_le_bytes());
+        swap_data.extend([1u8, 1u8]);
+        let transfer = |amount: u64| -> ExecutedInstruction { (token_program.clone(), vec![], [&[3u8][..], &amount.to_le_bytes()[..]].concat()) };
+        let accounts = vec![token_program.clone(), Pubkey::new_unique().to_string(), pool.clone()];
+        let instructions: Vec<ExecutedInstruction> = vec![
+            ("ComputeBudget111111111111111111111111111111".to_string(), vec![], vec![3, 0, 0, 0, 0, 0, 0, 0, 0]),
+            (WHIRLPOOL_PROGRAM.to_string(), accounts.clone(), swap_data.clone()),
+            transfer(1_000_000_000),
+            transfer(149_512_311),
+            // Swap without its two transfers
+            (WHIRLPOOL_PROGRAM.to_string(), accounts, swap_data),
+            transfer(5),
+        ];
+        let swap_events = decode_whirlpool_swaps(&instructions, &"signature".to_string(), 42);
+        assert_eq!(swap_events.len(), 1);
+        assert_eq!((swap_events[0].pool.clone(), swap_events[0].token_0to1, swap_events[0].amount_in, swap_events[0].amount_out), (Some(pool), true, 1_000_000_000, 149_512_311));
+    }
+    #[test]
+    fn price_move_of_raydium_and_meteora_swaps() {
+        use borsh::BorshSerialize;
+        use crate::markets::{meteora::AccountData, swap_events::{get_price_move, SwapEvent}};
+
+        // 10 SOL in a 1000 SOL / 150k USDC pool, 0.25% swap fee from the AmmInfo
+        let raydium_market = market_with_data(DexLabel::RAYDIUM, raydium_amm_info_data(6, (25, 10000), 0));
+        let mut swap_event = SwapEvent { dex_label: DexLabel::RAYDIUM, pool: Some(raydium_market.id.clone()), signature: "signature".to_string(), slot: 42, token_0to1: true, amount_in: 10_000_000_000, amount_out: 0, fee: 0, reserves_before: Some((1_000_000_000_000, 150_000_000_000)), bins: None };
+        assert!((get_price_move(&swap_event, &raydium_market).unwrap() - 0.019655419283785114).abs() < 1e-12);
+        // No AmmInfo, no fee rate
+        assert!(get_price_move(&swap_event, &market_with_data(DexLabel::RAYDIUM, vec![0u8; 10])).is_none());
+
+        // 4 bins crossed with a bin step of 25 bps
+        let account_data = AccountData { bin_step: 25, ..AccountData::default() };
+        let mut meteora_data: Vec<u8> = Vec::new();
+        account_data.serialize(&mut meteora_data).unwrap();
+        let meteora_market = market_with_data(DexLabel::METEORA, meteora_data);
+        swap_event.dex_label = DexLabel::METEORA;
+        swap_event.reserves_before = None;
+        swap_event.bins = Some((104, 100));
+        assert!((get_price_move(&swap_event, &meteora_market).unwrap() - 0.010037562539062295).abs() < 1e-12);
+    }
+    #[test]
+    fn trade_records_of_a_block_fixture() {
+        use crate::data::swap_dataset::{load_block_fixture, trade_records_from_block, TradeRecord};
+
+        // getBlock result (base64 encoding, full transaction details) built for the test with the layout of the RPC:
+        // a Raydium swap_base_in, a Whirlpool swap in a v0 transaction with the pool in a lookup table,
+        // a Meteora swap with its event-cpi and a failed Raydium swap
+        let block = load_block_fixture("src/data/fixtures/270000000.json").unwrap();
+        let trade_records = trade_records_from_block(270000000, &block);
+        let summary = |trade_record: &TradeRecord| (trade_record.signer.clone(), trade_record.dex_label.clone(), trade_record.pool.clone(), trade_record.token_0to1, trade_record.amount_in, trade_record.amount_out, trade_record.fee);
+        assert_eq!(trade_records.iter().map(summary).collect::<Vec<_>>(), vec![
+            ("BTJrQAp4QNCQ981QGkqgBKok4ngvSTpXbK8KfhB4jCVT".to_string(), DexLabel::RAYDIUM, "89syStbmsXGLiuV7q6DGCKzFuTaT3WQRjXcyYDBsjMqE".to_string(), true, 1_000_000_000, 149_426_072, 0),
+            ("7oD9YBYfHnL47CR65AgDe6Z9RNzjXsNE19h7LdwVwvgC".to_string(), DexLabel::ORCA_WHIRLPOOLS, "76TnhZQoaAUkoUuwZ1ovgFPefLrfZBWh1hHFUDN2pupd".to_string(), false, 150_000_000, 996_837_933, 0),
+            ("GpDPtfKoNU5NGQmaooX4HN3eKk8Bgowq8TRe4XqvDYBy".to_string(), DexLabel::METEORA, "2Kht7AZaJNLPFnNYku756t4H7u22CXAGSrwcktz79KWw".to_string(), true, 2_000_000_000, 297_000_000, 3_000_000),
+        ]);
+        assert_eq!(trade_records[0].signature, "5M66Aig6p9evLuHE163NrH7r7czpAinuBz411KeC7jqKsKiFnUjP5DUb2LXSUNs5s3vszKCLy4oMPA1TbTqcGW6N");
+        assert!(trade_records.iter().all(|trade_record| trade_record.slot == 270000000 && trade_record.block_time == Some(1718000000)));
+        // SetComputeUnitLimit(300k), default limit of one instruction, no ComputeBudget instruction
+        assert_eq!(trade_records.iter().map(|trade_record| (trade_record.compute_unit_price, trade_record.priority_fee)).collect::<Vec<_>>(), vec![(25_000, 7_500), (1_000, 200), (0, 0)]);
+        // Same priority fee as charged in the transaction fee (5000 lamports per signature)
+        for (trade_record, transaction) in trade_records.iter().zip(block.transactions.unwrap().iter()) {
+            assert_eq!(transaction.meta.as_ref().unwrap().fee - 5000, trade_record.priority_fee);
+        }
+    }
+    #[test]
+    fn pools_snapshot_round_trip() {
+        use crate::markets::snapshot::{dexs_from_snapshot, load_pools_snapshot, record_market_states, write_pools_snapshot};
+        use crate::markets::types::Dex;
+        use crate::markets::utils::toPairString;
+
+        // A pool of the registry without state and a pool with a recorded state (slot 42)
+        let mut pool = market_with_data(DexLabel::ORCA_WHIRLPOOLS, Vec::new());
+        pool.account_data = None;
+        pool.slot = None;
+        pool.tokenMintA = Pubkey::new_unique().to_string();
+        pool.tokenMintB = Pubkey::new_unique().to_string();
+        let mut pool_with_state = market_with_data(DexLabel::RAYDIUM, vec![1, 2, 3]);
+        pool_with_state.tokenMintA = pool.tokenMintA.clone();
+        pool_with_state.tokenMintB = pool.tokenMintB.clone();
+        record_market_states(&std::collections::HashMap::from([(pool_with_state.id.clone(), pool_with_state.clone())]));
+
+        let mut dex = Dex::new(DexLabel::ORCA_WHIRLPOOLS);
+        dex.pairToMarkets.insert(toPairString(pool.tokenMintA.clone(), pool.tokenMintB.clone()), vec![pool.clone()]);
+        let path = std::env::temp_dir().join(format!("pools-snapshot-{}.bin", pool.id)).to_string_lossy().to_string();
+        write_pools_snapshot(&vec![dex], &path).unwrap();
+
+        // The temporary file is renamed over the snapshot
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        let snapshot = load_pools_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(snapshot.slot >= 42);
+        let snapshot_pool = snapshot.markets.iter().find(|market| market.id == pool.id).unwrap();
+        assert_eq!((snapshot_pool.slot, snapshot_pool.account_data.clone()), (None, None));
+        let snapshot_pool_with_state = snapshot.markets.iter().find(|market| market.id == pool_with_state.id).unwrap();
+        assert_eq!((snapshot_pool_with_state.slot, snapshot_pool_with_state.account_data.clone()), (Some(42), Some(vec![1, 2, 3])));
+
+        // Each market goes back in the Dex of its label under its pair
+        let dexs = dexs_from_snapshot(&snapshot);
+        let pair_string = toPairString(pool.tokenMintA.clone(), pool.tokenMintB.clone());
+        let markets_of = |label: DexLabel| dexs.iter().find(|dex| dex.label == label).unwrap().pairToMarkets.get(&pair_string).cloned().unwrap_or_default();
+        assert!(markets_of(DexLabel::ORCA_WHIRLPOOLS).iter().any(|market| market.id == pool.id));
+        assert!(markets_of(DexLabel::RAYDIUM).iter().any(|market| market.id == pool_with_state.id));
+    }
+    #[test]
+    fn candles_volatility_and_trend() {
+        use crate::data::candles::CandleBuilder;
+        use crate::markets::swap_events::SwapEvent;
+
+        let market = market_with_data(DexLabel::ORCA_WHIRLPOOLS, Vec::new());
+        let mut candle_builder = CandleBuilder::new(60, 3);
+        for (timestamp, price) in [(0, 100.0), (30, 110.0), (45, 90.0), (60, 99.0), (120, 108.9), (10, 50.0)] {
+            candle_builder.add_price(&market, timestamp, price);
+        }
+        let candles = candle_builder.get_candles(&market.id);
+        assert_eq!(candles.iter().map(|candle| (candle.start_time, candle.open, candle.high, candle.low, candle.close)).collect::<Vec<_>>(), vec![
+            (0, 100.0, 110.0, 90.0, 90.0),
+            // Opened at the close of the previous bar, the late price of t=10 is ignored
+            (60, 90.0, 99.0, 90.0, 99.0),
+            (120, 99.0, 108.9, 99.0, 108.9),
+        ]);
+
+        // 2 SOL sold in the pool during the last bar
+        let swap_event = SwapEvent { dex_label: DexLabel::ORCA_WHIRLPOOLS, pool: Some(market.id.clone()), signature: "signature".to_string(), slot: 42, token_0to1: true, amount_in: 2_000_000_000, amount_out: 0, fee: 0, reserves_before: None, bins: None };
+        candle_builder.add_swap(&swap_event, 130, 9, None);
+        let last_candle = candle_builder.get_candles(&market.id).last().cloned().unwrap();
+        assert_eq!((last_candle.volume, last_candle.trades), (2.0, 1));
+
+        // Max 3 bars: the first one is dropped
+        candle_builder.add_price(&market, 180, 98.01);
+        let candles = candle_builder.get_candles(&market.id);
+        assert_eq!(candles.iter().map(|candle| candle.start_time).collect::<Vec<_>>(), vec![60, 120, 180]);
+        assert!((candle_builder.trend(&market.id, 2).unwrap() - (98.01 / 99.0 - 1.0)).abs() < 1e-12);
+        // Sample standard deviation of ln(1.1) and ln(0.9)
+        let expected_volatility = (1.1f64.ln() - 0.9f64.ln()).abs() / 2f64.sqrt();
+        assert!((candle_builder.volatility(&market.id, 2).unwrap() - expected_volatility).abs() < 1e-12);
+        assert!((candle_builder.token_volatility(&market.tokenMintA, 2).unwrap() - expected_volatility).abs() < 1e-12);
+        // Not enough returns
+        assert!(candle_builder.volatility(&market.id, 1).is_none());
+
+        // A swap without bar is kept only when it can open one
+        let other_market = market_with_data(DexLabel::ORCA_WHIRLPOOLS, Vec::new());
+        let other_swap_event = SwapEvent { pool: Some(other_market.id.clone()), ..swap_event };
+        candle_builder.add_swap(&other_swap_event, 130, 9, None);
+        assert!(candle_builder.get_candles(&other_market.id).is_empty());
+        candle_builder.add_swap(&other_swap_event, 130, 9, Some(100.0));
+        let candles = candle_builder.get_candles(&other_market.id);
+        assert_eq!(candles.iter().map(|candle| (candle.start_time, candle.open, candle.close, candle.volume, candle.trades)).collect::<Vec<_>>(), vec![(120, 100.0, 100.0, 2.0, 1)]);
+    }
+    #[test]
+    fn meteora_oracle_twap_and_dislocation() {
+        use crate::markets::meteora::{decode_oracle, get_oracle_dislocation, get_twap_bin_id, AccountData};
+
+        // Oracle account with 3 samples, the latest at idx 1: active bin 100 from t=1000 to 1060, then 110 until 1120
+        let mut data = vec![0u8; 32 + 3 * 32];
+        data[8..16].copy_from_slice(&1u64.to_le_bytes());
+        data[16..24].copy_from_slice(&3u64.to_le_bytes());
+        data[24..32].copy_from_slice(&3u64.to_le_bytes());
+        for (idx, cumulative_active_bin_id, timestamp) in [(2usize, 0i128, 1000i64), (0, 6000, 1060), (1, 12600, 1120)] {
+            let offset = 32 + idx * 32;
+            data[offset..offset + 16].copy_from_slice(&cumulative_active_bin_id.to_le_bytes());
+            data[offset + 16..offset + 24].copy_from_slice(&timestamp.to_le_bytes());
+            data[offset + 24..offset + 32].copy_from_slice(&timestamp.to_le_bytes());
+        }
+        let oracle = decode_oracle(&data).unwrap();
+        assert_eq!(oracle.observations.iter().map(|observation| observation.last_updated_at).collect::<Vec<_>>(), vec![1000, 1060, 1120]);
+
+        // Active bin 120 since t=1120, TWAP over the last 120s from the sample of t=1060
+        assert_eq!(get_twap_bin_id(&oracle, 120, 1180, 120), Some((115.0, 120)));
+        // Window longer than the oracle: from the earliest sample
+        assert_eq!(get_twap_bin_id(&oracle, 120, 1180, 1000), Some((110.0, 180)));
+
+        // 5 bins of 10 bps above the TWAP
+        let lb_pair = AccountData { active_id: 120, bin_step: 10, ..AccountData::default() };
+        let dislocation = get_oracle_dislocation("pool".to_string(), &lb_pair, &oracle, 1180, 120).unwrap();
+        assert!((dislocation.dislocation - (1.001f64.powi(5) - 1.0)).abs() < 1e-9);
+        assert_eq!(dislocation.twap_duration, 120);
+    }
+    #[tokio::test]
+    async fn fresh_raydium_reserves_from_api_market() {
+        use anchor_spl::token::spl_token;
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use raydium_amm::state::{AmmInfo, Loadable};
+        use solana_client::nonblocking::rpc_client::RpcClient;
+        use solana_program::program_pack::Pack;
+        use crate::arbitrage::streams::fetch_accounts_states;
+        use crate::markets::raydium::{get_raydium_reserves, RaydiumPool};
+
+        // SwapOnly pool with its vaults: 1000 SOL / 150k USDC
+        let pool = Pubkey::new_from_array([9; 32]);
+        let mut amm = *AmmInfo::load_from_bytes(&raydium_amm_info_data(6, (25, 10000), 0)).unwrap();
+        amm.coin_vault = Pubkey::new_from_array([1; 32]);
+        amm.pc_vault = Pubkey::new_from_array([2; 32]);
+        let amm_data = safe_transmute::transmute_one_to_bytes(&amm).to_vec();
+        let vault_data = |amount: u64| {
+            let mut data = vec![0u8; spl_token::state::Account::LEN];
+            spl_token::state::Account::pack(spl_token::state::Account { amount, state: spl_token::state::AccountState::Initialized, ..Default::default() }, &mut data).unwrap();
+            data
+        };
+        let accounts_data = std::collections::HashMap::from([
+            (pool.to_string(), amm_data.clone()),
+            (amm.coin_vault.to_string(), vault_data(1_000_000_000_000)),
+            (amm.pc_vault.to_string(), vault_data(150_000_000_000)),
+        ]);
+        // Slot 100, then one slot after minContextSlot
+        let (rpc_url, rpc) = spawn_mock_json_rpc_with_params(2, move |_, params| {
+            let slot = params[1]["minContextSlot"].as_u64().map_or(100, |slot| slot + 1);
+            let value: Vec<serde_json::Value> = params[0].as_array().unwrap().iter().map(|key| {
+                let data = &accounts_data[key.as_str().unwrap()];
+                serde_json::json!({ "data": [STANDARD.encode(data), "base64"], "executable": false, "lamports": 2_039_280, "owner": spl_token::id().to_string(), "rentEpoch": 0, "space": data.len() })
+            }).collect();
+            serde_json::json!({ "result": { "context": { "slot": slot }, "value": value } })
+        });
+
+        // Market from the API cache: the API JSON in account_data, no AmmInfo yet
+        let mut market = market_with_data(DexLabel::RAYDIUM, serde_json::to_vec(&RaydiumPool::default()).unwrap());
+        market.id = pool.to_string();
+        market.slot = None;
+        let markets = fetch_accounts_states(&RpcClient::new(rpc_url), std::collections::HashMap::from([(pool.to_string(), market)]), None).await;
+
+        let market = &markets[&pool.to_string()];
+        assert_eq!((market.account_data.clone(), market.slot), (Some(amm_data), Some(100)));
+        // Vaults from the fresh AmmInfo, read in a second request not older than the pool
+        let bodies = rpc.join().unwrap();
+        assert_eq!(bodies[1]["params"][0], serde_json::json!([amm.coin_vault.to_string(), amm.pc_vault.to_string()]));
+        assert_eq!(bodies[1]["params"][1]["minContextSlot"], serde_json::json!(100));
+        let reserves = get_raydium_reserves(market).unwrap();
+        assert_eq!((reserves.total_coin_without_take_pnl, reserves.total_pc_without_take_pnl, reserves.slot), (1_000_000_000_000, 150_000_000_000, 100));
+    }
+    #[tokio::test]
+    async fn backfill_pending_during_slow_fetch() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use solana_client::nonblocking::rpc_client::RpcClient;
+        use crate::arbitrage::subscriptions::SubscriptionManager;
+
+        // getMultipleAccounts answered after 500ms
+        let (rpc_url, rpc) = spawn_mock_json_rpc_with_params(1, |_, params| {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            let value: Vec<serde_json::Value> = params[0].as_array().unwrap().iter().map(|_| {
+                serde_json::json!({ "data": [STANDARD.encode([7u8; 8]), "base64"], "executable": false, "lamports": 1, "owner": Pubkey::default().to_string(), "rentEpoch": 0, "space": 8 })
+            }).collect();
+            serde_json::json!({ "result": { "context": { "slot": 100 }, "value": value } })
+        });
+        let market = market_with_data(DexLabel::ORCA_WHIRLPOOLS, vec![0; 8]);
+        let key = market.id.clone();
+        let manager = SubscriptionManager::new(std::collections::HashMap::from([(key.clone(), market)]), 10, std::time::Duration::from_secs(5));
+        let backfilling = manager.clone();
+        let backfill = tokio::spawn(async move { backfilling.backfill(&RpcClient::new(rpc_url)).await });
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(manager.health().pending_backfill);
+        assert!(!manager.is_healthy());
+        backfill.await.unwrap();
+        rpc.join().unwrap();
+        assert!(!manager.health().pending_backfill);
+        assert_eq!((manager.get_markets()[&key].account_data.clone(), manager.get_markets()[&key].slot), (Some(vec![7; 8]), Some(100)));
+    }
+}
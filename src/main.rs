@@ -1,20 +1,35 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
 use std::path;
+use std::time::Duration;
 
 use anyhow::Result;
 use futures::FutureExt;
-use log::info;
+use log::{error, info};
 use solana_sdk::pubkey::Pubkey;
+use tokio::sync::Notify;
 use tokio::task::JoinSet;
 use solana_client::rpc_client::RpcClient;
-use MEV_Bot_Solana::arbitrage::strategies::{optimism_tx_strategy, run_arbitrage_strategy, sorted_interesting_path_strategy};
+use MEV_Bot_Solana::arbitrage::strategies::{optimism_tx_strategy, run_arbitrage_strategy, run_backrun_strategy, sorted_interesting_path_strategy};
 use MEV_Bot_Solana::common::database::insert_vec_swap_path_selected_collection;
 use MEV_Bot_Solana::common::types::InputVec;
-use MEV_Bot_Solana::markets::pools::load_all_pools;
+use MEV_Bot_Solana::markets::pools::load_all_pools_with_snapshot;
+use MEV_Bot_Solana::markets::snapshot::{run_snapshot_service, write_pools_snapshot, SNAPSHOT_PATH};
+use MEV_Bot_Solana::strategies::pool_discovery::{run_pool_discovery, DiscoveredPools};
+use MEV_Bot_Solana::transactions::blockhash::run_blockhash_service;
+use MEV_Bot_Solana::transactions::broadcaster::{BroadcastEndpoint, Broadcaster};
+use MEV_Bot_Solana::transactions::confirmation::run_confirmation_service;
+use MEV_Bot_Solana::transactions::nonce::init_nonce_accounts;
+use MEV_Bot_Solana::transactions::signer::SignerService;
+use MEV_Bot_Solana::transactions::jito::BundleSender;
+use MEV_Bot_Solana::transactions::leader_sender::LeaderSender;
+use MEV_Bot_Solana::transactions::priority_fees::PriorityFeePolicy;
+use MEV_Bot_Solana::transactions::slippage::SlippagePolicy;
 use MEV_Bot_Solana::transactions::create_transaction::{create_ata_extendlut_transaction, ChainType, SendOrSimulate};
 use MEV_Bot_Solana::{common::constants::Env, transactions::create_transaction::create_and_send_swap_transaction};
+use MEV_Bot_Solana::common::prices::{refresh_pyth_prices, run_prices_service};
 use MEV_Bot_Solana::common::utils::{from_str, get_tokens_infos, setup_logger};
 use MEV_Bot_Solana::arbitrage::types::{SwapPathResult, SwapPathSelected, SwapRouteSimulation, TokenInArb, TokenInfos, VecSwapPathSelected};
 use rust_socketio::{Payload, asynchronous::{Client, ClientBuilder},};
@@ -28,8 +43,27 @@ use mongodb::{Client as MongoDbCLient, options::ClientOptions};
 
 // use MEV_Bot_Solana::common::pools::{load_all_pools, Pool};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+
+    // Notified by the ctrl_c handler once the pools snapshot is written
+    let shutdown = Arc::new(Notify::new());
+    let result = runtime.block_on(async {
+        tokio::select! {
+            result = run(shutdown.clone()) => result,
+            _ = shutdown.notified() => {
+                info!("🛑 Shutdown requested, stopping the bot...");
+                Ok(())
+            }
+        }
+    });
+
+    // The subscriptions run on blocking threads and never return by themselves
+    runtime.shutdown_timeout(Duration::from_secs(5));
+    result
+}
+
+async fn run(shutdown: Arc<Notify>) -> Result<()> {
 
     //Options
     let simulation_amount = 3500000000; //3.5 SOL
@@ -39,13 +73,64 @@ async fn main() -> Result<()> {
     let massive_strategie: bool = true;
     let best_strategie: bool = true;
     let optimism_strategie: bool = true;
+    let backrun_strategie: bool = false;
 
     //massive_strategie options
     let fetch_new_pools = false;
             // Restrict USDC/SOL pools to 2 markets
     let restrict_sol_usdc = true;
+            // Subscribe to Raydium/Whirlpools/Meteora programs to add new pools while the bot runs
+    let discover_new_pools = true;
+            // Max slots between the accounts of a path (and with the current slot before sending)
+    let max_slot_window: u64 = 5;
+            // Load pools and their last states from the snapshot at startup, and write it periodically and at shutdown
+    let use_pools_snapshot = true;
+    let snapshot_interval_secs: u64 = 60;
+
+    //Transactions options
+            // Refresh interval of the blockhash and slot service
+    let blockhash_refresh_ms: u64 = 400;
+            // Interval of the batched status requests of the confirmation tracker
+    let confirmation_poll_ms: u64 = 500;
+            // Send the swap transactions directly to the TPU (QUIC) of the current and next leaders
+    let use_leader_sender = false;
+            // Leaders of the next slots which also receive the transaction
+    let leader_fanout_slots: u64 = 8;
+            // Validators whose slots are preferred to send the swap transactions, and the max slots to wait for them
+    let preferred_leaders: Vec<&str> = vec![];
+    let leader_max_wait_slots: u64 = 4;
+            // Send the swap transactions as Jito bundles with a tip to BLOCK_ENGINE_URL
+    let use_jito_bundles = false;
+    let jito_tip_lamports: u64 = 10000;
+            // Send again without tip by the normal path if the bundle is rejected
+    let jito_fallback_to_rpc = true;
+            // Nonce accounts of the payer for durable nonce transactions, one per opportunity in flight (0 to disable)
+    let durable_nonce_accounts: usize = 0;
+            // Send the swap transactions to RPC_URL_TX, RPC_URLS_BROADCAST and these TPU addresses at once
+    let use_broadcaster = false;
+    let broadcast_tpu_addresses: Vec<&str> = vec![];
+            // Compute unit price: percentile of the recent fees on the written pools, max share of the expected profit
+    let priority_fee_policy = PriorityFeePolicy { percentile: 75.0, max_profit_share: 0.3, ..PriorityFeePolicy::default() };
+            // minimum_amount_out of the swaps: PerHop { bps }, FinalLeg { bps } or RequiredProfit { min_profit }
+    let slippage_policy = SlippagePolicy::FinalLeg { bps: 50 };
+            // Cycles in SOL: wrap the amount in from the native balance and unwrap after the last hop
+    let wrap_sol = false;
+            // Refresh interval of the Pyth USD prices
+    let usd_prices_refresh_secs: u64 = 10;
+
+    //backrun_strategie options
+            // Min. price move of a pool (0.005 = 0.5%) caused by a swap to search a backrun
+    let backrun_price_move_threshold: f64 = 0.005;
+            // Interval of the OHLCV candles built from the swap events and the pools prices
+    let candles_interval_secs: i64 = 60;
+            // Window of the Meteora oracles TWAP, pools away from it by more than the threshold are also searched
+    let oracle_window_secs: i64 = 300;
 
     //best_strategie options
+            // Keep the markets of the best paths up to date by websocket, pause when data is stale
+    let supervised_subscriptions = true;
+            // Max slots gap before backfill and max delay without new slot before pause
+    let subscriptions_settings = (max_slot_window, Duration::from_secs(5));
     // let mut path_best_strategie: String = format!("best_paths_selected/SOL-SOLLY.json");
     let mut path_best_strategie: String = format!("best_paths_selected/ultra_strategies/0-SOL-SOLLY-1-SOL-SPIKE-2-SOL-AMC-GME.json");
     
@@ -136,7 +221,68 @@ async fn main() -> Result<()> {
     info!("⚠️⚠️ New fresh pools fetched on METEORA and RAYDIUM are excluded because a lot of time there have very low liquidity, potentially can be used on subscribe log strategy");
     info!("⚠️⚠️ Liquidity is fetch to API and can be outdated on Radyium Pool");
 
+    // Payer and fee payer of the transactions: PAYER_SIGNER (file, keystore or remote), FEE_PAYER_KEYPAIR_PATH
+    let signer = SignerService::from_env()?;
+    info!("💳 Wallet {} (fee payer {})", signer.payer(), signer.fee_payer());
+
     let mut set: JoinSet<()> = JoinSet::new();
+
+    // Keep a fresh blockhash and the current slot for the transactions builders
+    let rpc_url_tx = Env::new().rpc_url_tx;
+    set.spawn(async move {
+        run_blockhash_service(rpc_url_tx, Duration::from_millis(blockhash_refresh_ms)).await;
+    });
+
+    if durable_nonce_accounts > 0 {
+        if let Err(e) = init_nonce_accounts(&RpcClient::new(Env::new().rpc_url_tx), &signer, durable_nonce_accounts) {
+            error!("❌ Nonce accounts not loaded, transactions with recent blockhashes only: {:?}", e);
+        }
+    }
+
+    // Follow the sent transactions until they land, realized PnL to the DB and the strategy statistics
+    let rpc_url_confirmation = Env::new().rpc_url_tx;
+    set.spawn(async move {
+        run_confirmation_service(rpc_url_confirmation, Duration::from_millis(confirmation_poll_ms)).await;
+    });
+
+    // USD prices for the liquidity checks and the paths results, needed before the first calculate_arb
+    let rpc_url_prices = Env::new().rpc_url;
+    if let Err(e) = refresh_pyth_prices(&RpcClient::new(rpc_url_prices.clone())) {
+        error!("❌ Pyth prices not loaded: {:?}", e);
+    }
+    set.spawn(async move {
+        run_prices_service(rpc_url_prices, Duration::from_secs(usd_prices_refresh_secs)).await;
+    });
+
+    let leader_sender = if use_leader_sender {
+        let preferred_leaders: HashSet<Pubkey> = preferred_leaders.iter().map(|leader| from_str(leader)).collect::<Result<_, _>>()?;
+        let leader_sender = LeaderSender::new(Env::new().rpc_url_tx, leader_fanout_slots, true).with_preferred_leaders(preferred_leaders, leader_max_wait_slots);
+        let leader_service = leader_sender.clone();
+        set.spawn(async move {
+            leader_service.run_refresh_service(Duration::from_secs(10)).await;
+        });
+        Some(leader_sender)
+    } else {
+        None
+    };
+    
+    let block_engine_url = Env::new().block_engine_url;
+    let bundle_sender = if use_jito_bundles && !block_engine_url.is_empty() {
+        Some(BundleSender::new(block_engine_url, jito_tip_lamports, jito_fallback_to_rpc))
+    } else {
+        None
+    };
+
+    let broadcaster = if use_broadcaster {
+        let env = Env::new();
+        let mut broadcaster = Broadcaster::from_rpc_urls(&format!("{},{}", env.rpc_url_tx, env.rpc_urls_broadcast));
+        for tpu_address in broadcast_tpu_addresses.iter() {
+            broadcaster.add_endpoint(BroadcastEndpoint::Tpu(tpu_address.parse()?));
+        }
+        Some(broadcaster)
+    } else {
+        None
+    };
     
     // // The first token is the base token (here SOL)
     let tokens_to_arb: Vec<TokenInArb> = inputs_vec.clone().into_iter().flat_map(|input| input.tokens_to_arb).collect();
@@ -170,8 +316,35 @@ async fn main() -> Result<()> {
 
     if massive_strategie {
         info!("🏊 Launch pools fetching infos...");
-        let dexs = load_all_pools(fetch_new_pools).await;
+        let dexs = load_all_pools_with_snapshot(fetch_new_pools, use_pools_snapshot).await;
         info!("🏊 {} Dexs are loaded", dexs.len());
+
+        if use_pools_snapshot {
+            let dexs_snapshot = dexs.clone();
+            set.spawn(async move {
+                run_snapshot_service(dexs_snapshot, Duration::from_secs(snapshot_interval_secs)).await;
+            });
+            let dexs_shutdown = dexs.clone();
+            let shutdown_snapshot = shutdown.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    if let Err(e) = write_pools_snapshot(&dexs_shutdown, SNAPSHOT_PATH) {
+                        error!("❌ Pools snapshot not written at shutdown: {:?}", e);
+                    }
+                    shutdown_snapshot.notify_one();
+                }
+            });
+        }
+
+        let discovered_pools: DiscoveredPools = Arc::new(RwLock::new(HashMap::new()));
+        if discover_new_pools {
+            let known_pools: HashSet<String> = dexs.iter().flat_map(|dex| dex.pairToMarkets.values().flatten().map(|market| market.id.clone())).collect();
+            let discovered = discovered_pools.clone();
+            let tokens = tokens_to_arb.clone();
+            set.spawn(async move {
+                let _ = run_pool_discovery(tokens, known_pools, discovered).await;
+            });
+        }
         
         
         info!("🪙🪙 Tokens Infos: {:?}", tokens_to_arb);
@@ -180,7 +353,7 @@ async fn main() -> Result<()> {
         for input_iter in inputs_vec.clone() {
             let tokens_infos: HashMap<String, TokenInfos> = get_tokens_infos(input_iter.tokens_to_arb.clone()).await;
 
-            let result = run_arbitrage_strategy(simulation_amount, input_iter.get_fresh_pools_bool, restrict_sol_usdc, input_iter.include_1hop, input_iter.include_2hop, input_iter.numbers_of_best_paths, dexs.clone(), input_iter.tokens_to_arb.clone(), tokens_infos.clone()).await;
+            let result = run_arbitrage_strategy(simulation_amount, input_iter.get_fresh_pools_bool, restrict_sol_usdc, input_iter.include_1hop, input_iter.include_2hop, input_iter.numbers_of_best_paths, dexs.clone(), input_iter.tokens_to_arb.clone(), tokens_infos.clone(), Some(discovered_pools.clone()), max_slot_window).await;
             let (path_for_best_strategie, swap_path_selected) = result.unwrap();
             vec_best_paths.push(path_for_best_strategie);
         }
@@ -218,21 +391,31 @@ async fn main() -> Result<()> {
             path_best_strategie = path;
         }
 
+        // Runs until the end of the process, the other strategies go on
+        if backrun_strategie {
+            let (dexs_backrun, inputs_backrun, discovered_backrun) = (dexs.clone(), inputs_vec.clone(), discovered_pools.clone());
+            set.spawn(async move {
+                if let Err(e) = run_backrun_strategy(simulation_amount, restrict_sol_usdc, dexs_backrun, inputs_backrun, Some(discovered_backrun), max_slot_window, backrun_price_move_threshold, candles_interval_secs, oracle_window_secs).await {
+                    error!("❌ Backrun strategy stopped: {:?}", e);
+                }
+            });
+        }
+
         if best_strategie {
             let tokens_infos: HashMap<String, TokenInfos> = get_tokens_infos(tokens_to_arb.clone()).await;
 
-            let _ = sorted_interesting_path_strategy(simulation_amount, path_best_strategie.clone(), tokens_to_arb.clone(), tokens_infos.clone()).await;
+            let _ = sorted_interesting_path_strategy(simulation_amount, path_best_strategie.clone(), tokens_to_arb.clone(), tokens_infos.clone(), if supervised_subscriptions { Some(subscriptions_settings) } else { None }, Some(discovered_pools.clone())).await;
         }
     }
     
     if best_strategie {
         let tokens_infos: HashMap<String, TokenInfos> = get_tokens_infos(tokens_to_arb.clone()).await;
 
-        let _ = sorted_interesting_path_strategy(simulation_amount, path_best_strategie.clone(), tokens_to_arb.clone(), tokens_infos.clone()).await;
+        let _ = sorted_interesting_path_strategy(simulation_amount, path_best_strategie.clone(), tokens_to_arb.clone(), tokens_infos.clone(), if supervised_subscriptions { Some(subscriptions_settings) } else { None }, None).await;
     }
     
     if optimism_strategie {
-        let _ = optimism_tx_strategy(optimism_path);
+        let _ = optimism_tx_strategy(optimism_path, signer.clone(), leader_sender.clone(), bundle_sender.clone(), broadcaster.clone(), priority_fee_policy.clone(), slippage_policy.clone(), wrap_sol);
     }
     
     while let Some(res) = set.join_next().await {
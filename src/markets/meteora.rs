@@ -6,6 +6,8 @@ use crate::common::utils::{from_Pubkey, from_str, make_request};
 use crate::common::constants::Env;
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use lb_clmm::math::price_math::get_price_from_id;
+use lb_clmm::state::oracle::{Observation, Oracle};
 use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
 use std::collections::HashMap;
 use std::fs::File;
@@ -14,11 +16,14 @@ use serde::{Deserialize, Deserializer, de, Serialize};
 use serde_json::Value;
 use reqwest::get;
 use std::io::{BufWriter, Write};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use log::{info, error};
 use solana_account_decoder::UiAccountEncoding;
 use solana_program::pubkey::Pubkey;
 use anyhow::Result;
 use solana_client::rpc_client::RpcClient;
+use solana_client::nonblocking::rpc_client::RpcClient as NonblockingRpcClient;
 use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
 
 #[derive(Debug)]
@@ -60,6 +65,7 @@ impl MeteoraDEX {
                 id: pool.address.clone(),
                 account_data: Some(serialized_data),
                 liquidity: Some(liquidity as u64),
+                slot: None,
             };
 
             let pair_string = toPairString(pool.mint_x, pool.mint_y);
@@ -145,21 +151,7 @@ pub async fn fetch_new_meteora_pools(rpc_client: &RpcClient, token: String, on_t
     ).unwrap();
 
     for account in accounts.clone() {
-        // println!("Address: {:?}", &account.0);
-        // println!("account data: {:?}", &account.1.data);
-        let meteora_market = AccountData::try_from_slice(&account.1.data).unwrap();
-        // println!("meteora_market: {:?}", meteora_market);
-        let market: Market = Market {
-            tokenMintA: from_Pubkey(meteora_market.token_xmint.clone()),
-            tokenVaultA: from_Pubkey(meteora_market.reserve_x.clone()),
-            tokenMintB: from_Pubkey(meteora_market.token_ymint.clone()),
-            tokenVaultB: from_Pubkey(meteora_market.reserve_y.clone()),
-            dexLabel: DexLabel::METEORA,
-            fee: 0 as u64,        
-            id: from_Pubkey(account.0).clone(),
-            account_data: Some(account.1.data),
-            liquidity: Some(666 as u64),
-        };
+        let market = meteora_market_from_account(account.0, account.1.data).unwrap();
         new_markets.push((account.0, market));
     }
     // println!("Accounts: {:?}", accounts);
@@ -168,6 +160,25 @@ pub async fn fetch_new_meteora_pools(rpc_client: &RpcClient, token: String, on_t
 }
 
 
+// Build a Market from raw LbPair account data (getProgramAccounts or programSubscribe)
+pub fn meteora_market_from_account(address: Pubkey, data: Vec<u8>) -> Result<Market> {
+    let meteora_market = AccountData::try_from_slice(&data)?;
+    let market: Market = Market {
+        tokenMintA: from_Pubkey(meteora_market.token_xmint.clone()),
+        tokenVaultA: from_Pubkey(meteora_market.reserve_x.clone()),
+        tokenMintB: from_Pubkey(meteora_market.token_ymint.clone()),
+        tokenVaultB: from_Pubkey(meteora_market.reserve_y.clone()),
+        dexLabel: DexLabel::METEORA,
+        fee: 0 as u64,        
+        id: from_Pubkey(address),
+        account_data: Some(data),
+        // USD liquidity from the vaults balances, set by the caller
+        liquidity: None,
+        slot: None,
+    };
+    Ok(market)
+}
+
 // Simulate one route 
 // I want to get the data of the market i'm interested in this route
 pub async fn simulate_route_meteora(printing_amt: bool, amount_in: u64, route: Route, market: Market, tokens_infos: HashMap<String, TokenInfos>) -> Result<(String, String), Box<dyn std::error::Error>> {
@@ -418,4 +429,169 @@ pub struct RewardInfo {
     pub reward_rate: u128,
     pub last_update_time: u64,
     pub cumulative_seconds_with_empty_liquidity_reward: u64,
-}
\ No newline at end of file
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////////////////////         ORACLE            /////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Oracle account of a LbPair: discriminator, Oracle metadata, then `length` Observations (i128, i64, i64)
+#[derive(Debug, Clone)]
+pub struct OracleData {
+    pub metadata: Oracle,
+    // Initialized samples, from the earliest to the latest
+    pub observations: Vec<Observation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleDislocation {
+    pub pool: String,
+    pub active_id: i32,
+    pub twap_bin_id: f64,
+    // Price of X in Y, without decimals
+    pub active_price: f64,
+    pub twap_price: f64,
+    // active_price / twap_price - 1
+    pub dislocation: f64,
+    // Seconds really covered by the TWAP, shorter than the window on young oracles
+    pub twap_duration: i64,
+}
+
+pub fn decode_oracle(data: &[u8]) -> Result<OracleData> {
+    let metadata_len = Oracle::metadata_len();
+    let observation_len = std::mem::size_of::<Observation>();
+    if data.len() < metadata_len {
+        return Err(anyhow::format_err!("Oracle account too small: {} bytes", data.len()));
+    }
+    let read_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let read_i64 = |offset: usize| i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let metadata = Oracle { idx: read_u64(8), active_size: read_u64(16), length: read_u64(24) };
+
+    let mut samples: Vec<Observation> = Vec::new();
+    for i in 0..metadata.length as usize {
+        let offset = metadata_len + i * observation_len;
+        if offset + observation_len > data.len() {
+            break;
+        }
+        samples.push(Observation {
+            cumulative_active_bin_id: i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap()),
+            created_at: read_i64(offset + 16),
+            last_updated_at: read_i64(offset + 24),
+        });
+    }
+
+    // Samples are a ring buffer of active_size elements, the latest one at idx
+    let active_size = (metadata.active_size as usize).min(samples.len());
+    let mut observations: Vec<Observation> = Vec::new();
+    if active_size > 0 {
+        let latest_idx = metadata.idx as usize % active_size;
+        for i in 1..=active_size {
+            let observation = samples[(latest_idx + i) % active_size];
+            if observation.initialized() {
+                observations.push(observation);
+            }
+        }
+    }
+    Ok(OracleData { metadata, observations })
+}
+
+// Time weighted active bin over the last `window_secs`, the latest sample is accumulated up to `current_timestamp`
+// Return (TWAP bin id, seconds covered)
+pub fn get_twap_bin_id(oracle: &OracleData, active_id: i32, current_timestamp: i64, window_secs: i64) -> Option<(f64, i64)> {
+    let latest = oracle.observations.last()?;
+    let current_cumulative = latest.accumulate_active_bin_id(active_id, current_timestamp.max(latest.last_updated_at)).ok()?;
+    let start_timestamp = current_timestamp - window_secs;
+    // Latest sample before the start of the window, else the earliest one
+    let reference = oracle.observations.iter().rev()
+        .find(|observation| observation.last_updated_at <= start_timestamp)
+        .unwrap_or(&oracle.observations[0]);
+    let duration = current_timestamp - reference.last_updated_at;
+    if duration <= 0 {
+        return Some((active_id as f64, 0));
+    }
+    let twap_bin_id = (current_cumulative - reference.cumulative_active_bin_id) as f64 / duration as f64;
+    Some((twap_bin_id, duration))
+}
+
+pub fn get_oracle_dislocation(pool: String, lb_pair: &AccountData, oracle: &OracleData, current_timestamp: i64, window_secs: i64) -> Option<OracleDislocation> {
+    let (twap_bin_id, twap_duration) = get_twap_bin_id(oracle, lb_pair.active_id, current_timestamp, window_secs)?;
+    let active_price = get_price_from_id(lb_pair.active_id, lb_pair.bin_step).ok()? as f64 / 2f64.powi(64);
+    let twap_price = (1.0 + lb_pair.bin_step as f64 / 10000.0).powf(twap_bin_id);
+    Some(OracleDislocation {
+        pool,
+        active_id: lb_pair.active_id,
+        twap_bin_id,
+        active_price,
+        twap_price,
+        dislocation: active_price / twap_price - 1.0,
+        twap_duration,
+    })
+}
+
+// Fetch the oracles of the Meteora markets and sort the pools by dislocation, the most pushed away first
+pub async fn get_oracles_dislocations(rpc_client: &NonblockingRpcClient, markets: &Vec<Market>, window_secs: i64) -> Result<Vec<OracleDislocation>> {
+    let mut lb_pairs: Vec<(String, AccountData)> = Vec::new();
+    for market in markets.iter().filter(|market| market.dexLabel == DexLabel::METEORA) {
+        match market.account_data.as_ref().map(|data| AccountData::try_from_slice(data)) {
+            Some(Ok(lb_pair)) => lb_pairs.push((market.id.clone(), lb_pair)),
+            _ => error!("❌ Meteora pool {} without LbPair data", market.id),
+        }
+    }
+
+    let current_timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let mut dislocations: Vec<OracleDislocation> = Vec::new();
+    // Each LbPair is fetched with its oracle, the active bin and the TWAP are read at the same slot
+    for batch in lb_pairs.chunks(50) {
+        let keys: Vec<Pubkey> = batch.iter().flat_map(|(pool, lb_pair)| [from_str(pool).unwrap(), lb_pair.oracle]).collect();
+        let accounts = rpc_client.get_multiple_accounts(&keys).await?;
+        for ((pool, lb_pair), accounts) in batch.iter().zip(accounts.chunks(2)) {
+            let fresh_lb_pair = match accounts[0].as_ref().map(|account| AccountData::try_from_slice(&account.data)) {
+                Some(Ok(fresh_lb_pair)) => fresh_lb_pair,
+                _ => {
+                    error!("❌ LbPair of pool {} not decoded", pool);
+                    continue;
+                }
+            };
+            let oracle = match accounts[1].as_ref().map(|account| decode_oracle(&account.data)) {
+                Some(Ok(oracle)) => oracle,
+                _ => {
+                    error!("❌ Oracle {} of pool {} not decoded", lb_pair.oracle, pool);
+                    continue;
+                }
+            };
+            if let Some(dislocation) = get_oracle_dislocation(pool.clone(), &fresh_lb_pair, &oracle, current_timestamp, window_secs) {
+                dislocations.push(dislocation);
+            }
+        }
+    }
+    dislocations.sort_by(|a, b| b.dislocation.abs().partial_cmp(&a.dislocation.abs()).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(dislocations)
+}
+
+// Last dislocations computed by run_oracles_service(), the key is the address of the pool
+fn oracles_dislocations() -> &'static RwLock<HashMap<String, OracleDislocation>> {
+    static ORACLES_DISLOCATIONS: OnceLock<RwLock<HashMap<String, OracleDislocation>>> = OnceLock::new();
+    ORACLES_DISLOCATIONS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+pub fn get_oracle_dislocation_cached(pool: &String) -> Option<OracleDislocation> {
+    oracles_dislocations().read().unwrap().get(pool).cloned()
+}
+
+// The LbPairs of `markets` are refetched with their oracles at each refresh
+pub async fn run_oracles_service(rpc_url: String, markets: Vec<Market>, window_secs: i64, refresh_interval: Duration) {
+    info!("🔮 Launch Meteora oracles service...");
+    let rpc_client = NonblockingRpcClient::new(rpc_url);
+    loop {
+        match get_oracles_dislocations(&rpc_client, &markets, window_secs).await {
+            Ok(dislocations) => {
+                if let Some(most_dislocated) = dislocations.first() {
+                    info!("🔮 {} Meteora oracles read, most dislocated pool {}: {:.3}%", dislocations.len(), most_dislocated.pool, most_dislocated.dislocation * 100.0);
+                }
+                *oracles_dislocations().write().unwrap() = dislocations.into_iter().map(|dislocation| (dislocation.pool.clone(), dislocation)).collect();
+            }
+            Err(e) => error!("❌ Meteora oracles refresh error: {:?}", e),
+        }
+        tokio::time::sleep(refresh_interval).await;
+    }
+}
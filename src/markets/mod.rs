@@ -5,4 +5,6 @@ pub mod orca_whirpools;
 pub mod meteora;
 pub mod types;
 pub mod utils;
+pub mod swap_events;
 pub mod pools;
+pub mod snapshot;
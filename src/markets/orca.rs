@@ -80,6 +80,7 @@ impl OrcaDex {
                 id: from_Pubkey(pool.token_pool.clone()),
                 account_data: None,
                 liquidity: None,
+                slot: None,
             };
 
             let pair_string = toPairString(from_Pubkey(pool.mint_a), from_Pubkey(pool.mint_b));
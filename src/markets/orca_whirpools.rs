@@ -97,6 +97,7 @@ impl OrcaDexWhirpools {
                 //TODO: None here, be sure to refresh data after
                 account_data: None, 
                 liquidity: Some(pool.liquidity as u64),
+                slot: None,
             };
 
             let pair_string = toPairString(from_Pubkey(pool.token_mint_a), from_Pubkey(pool.token_mint_b));
@@ -147,24 +148,31 @@ pub async fn fetch_new_orca_whirpools(rpc_client: &RpcClient, token: String, on_
     ).unwrap();
 
     for account in accounts {
-        let whirpool_account = unpack_from_slice(account.1.data.as_slice()).unwrap();
-        let market: Market = Market {
-            tokenMintA: from_Pubkey(whirpool_account.token_mint_a.clone()),
-            tokenVaultA: from_Pubkey(whirpool_account.token_vault_a.clone()),
-            tokenMintB: from_Pubkey(whirpool_account.token_mint_b.clone()),
-            tokenVaultB: from_Pubkey(whirpool_account.token_vault_b.clone()),
-            fee: whirpool_account.fee_rate.clone() as u64,
-            dexLabel: DexLabel::ORCA_WHIRLPOOLS,
-            id: from_Pubkey(account.0.clone()),
-            account_data: Some(account.1.data),
-            liquidity: Some(whirpool_account.liquidity as u64),
-        };
+        let market = orca_whirpools_market_from_account(account.0, account.1.data).unwrap();
         new_markets.push((account.0, market));
     }
     // println!("Accounts: {:?}", accounts);
     return new_markets;
 }
 
+// Build a Market from raw Whirlpool account data (getProgramAccounts or programSubscribe)
+pub fn orca_whirpools_market_from_account(address: Pubkey, data: Vec<u8>) -> Result<Market> {
+    let whirpool_account = unpack_from_slice(data.as_slice())?;
+    let market: Market = Market {
+        tokenMintA: from_Pubkey(whirpool_account.token_mint_a.clone()),
+        tokenVaultA: from_Pubkey(whirpool_account.token_vault_a.clone()),
+        tokenMintB: from_Pubkey(whirpool_account.token_mint_b.clone()),
+        tokenVaultB: from_Pubkey(whirpool_account.token_vault_b.clone()),
+        fee: whirpool_account.fee_rate.clone() as u64,
+        dexLabel: DexLabel::ORCA_WHIRLPOOLS,
+        id: from_Pubkey(address),
+        account_data: Some(data),
+        liquidity: Some(whirpool_account.liquidity as u64),
+        slot: None,
+    };
+    Ok(market)
+}
+
 pub async fn fetch_data_orca_whirpools() -> Result<(), Box<dyn std::error::Error>> {
     let response = get("https://api.mainnet.orca.so/v1/whirlpool/list").await?;
     // info!("response: {:?}", response);
@@ -199,11 +207,11 @@ pub async fn stream_orca_whirpools(account: Pubkey) -> Result<()> {
     loop {
         match account_subscription_receiver.recv() {
             Ok(response) => {
-                let data = response.value.data;
+                let data = response.value.data.clone();
                 let bytes_slice = UiAccountData::decode(&data).unwrap();
                 // println!("account subscription data response: {:?}", data);
                 let account_data = unpack_from_slice(bytes_slice.as_slice());
-                println!("Orca Whirpools Pool updated: {:?}", account);
+                println!("Orca Whirpools Pool updated: {:?} at slot {}", account, response.context.slot);
                 println!("Data: {:?}", account_data.unwrap());
 
             }
@@ -5,6 +5,7 @@ use super::raydium_clmm::{RaydiumClmmDEX, fetch_data_raydium_clmm};
 use super::orca::{OrcaDex, fetch_data_orca};
 use super::orca_whirpools::{OrcaDexWhirpools, fetch_data_orca_whirpools};
 use super::raydium::{fetch_data_raydium, RaydiumDEX};
+use super::snapshot::{dexs_from_snapshot, load_pools_snapshot, refresh_snapshot_states, SNAPSHOT_PATH};
 
 use strum::IntoEnumIterator;
 use std::fs::File;
@@ -12,7 +13,7 @@ use std::io::Write;
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
 use reqwest::get;
-use log::info;
+use log::{error, info};
 use solana_client::rpc_client::RpcClient;
 
 
@@ -48,3 +49,20 @@ pub async fn load_all_pools(refecth_api: bool) -> Vec<Dex> {
 
 }
 
+
+// Warm restart: load the pools registry and the last known states from the snapshot,
+// then refresh only the states. Fallback on load_all_pools() if there is no snapshot
+pub async fn load_all_pools_with_snapshot(refecth_api: bool, use_snapshot: bool) -> Vec<Dex> {
+    if use_snapshot && !refecth_api {
+        match load_pools_snapshot(SNAPSHOT_PATH) {
+            Ok(snapshot) => {
+                let dexs = dexs_from_snapshot(&snapshot);
+                return refresh_snapshot_states(dexs, snapshot.slot).await;
+            }
+            Err(e) => {
+                error!("❌ No pools snapshot loaded, fallback on API cache files: {:?}", e);
+            }
+        }
+    }
+    load_all_pools(refecth_api).await
+}
@@ -8,6 +8,7 @@ use crate::common::constants::Env;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
 use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
 use std::fs::File;
 use std::fs;
 use serde::{Deserialize, Deserializer, de, Serialize};
@@ -24,6 +25,13 @@ use solana_client::rpc_client::RpcClient;
 use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
 
 use super::types::SimulationError;
+use anchor_spl::token::spl_token;
+use raydium_amm::math::{Calculator, CheckedCeilDiv, SwapDirection, U128};
+use raydium_amm::processor::{Processor, AUTHORITY_AMM};
+use raydium_amm::state::{AmmStatus, Loadable};
+use solana_program::account_info::AccountInfo;
+use solana_program::program_pack::Pack;
+use solana_sdk::account::Account;
 
 #[derive(Debug)]
 pub struct RaydiumDEX {
@@ -62,6 +70,7 @@ impl RaydiumDEX {
                 id: pool.amm_id.clone(),
                 account_data: Some(serialized_person),
                 liquidity: Some(pool.liquidity as u64),
+                slot: None,
             };
 
             let pair_string = toPairString(pool.base_mint, pool.quote_mint);
@@ -159,19 +168,7 @@ pub async fn fetch_new_raydium_pools(rpc_client: &RpcClient, token: String, on_t
     ).unwrap();
 
     for account in accounts.clone() {
-        let raydium_account = AmmInfo::try_from_slice(&account.1.data).unwrap();
-        let fees: u128 = (raydium_account.fees.trade_fee_numerator / raydium_account.fees.trade_fee_denominator) as u128;
-        let market: Market = Market {
-            tokenMintA: from_Pubkey(raydium_account.coin_vault_mint.clone()),
-            tokenVaultA: from_Pubkey(raydium_account.coin_vault.clone()),
-            tokenMintB: from_Pubkey(raydium_account.pc_vault_mint.clone()),
-            tokenVaultB: from_Pubkey(raydium_account.pc_vault.clone()),
-            fee: fees as u64,
-            dexLabel: DexLabel::RAYDIUM,
-            id: from_Pubkey(account.0.clone()),
-            account_data: Some(account.1.data),
-            liquidity: Some(666 as u64),
-        };
+        let market = raydium_market_from_account(account.0, account.1.data).unwrap();
         new_markets.push((account.0, market));
     }
     // println!("Accounts: {:?}", accounts);
@@ -179,6 +176,26 @@ pub async fn fetch_new_raydium_pools(rpc_client: &RpcClient, token: String, on_t
     return new_markets;
 }
 
+// Build a Market from raw AmmInfo account data (getProgramAccounts or programSubscribe)
+pub fn raydium_market_from_account(address: Pubkey, data: Vec<u8>) -> Result<Market> {
+    let raydium_account = AmmInfo::try_from_slice(&data)?;
+    let fees: u128 = (raydium_account.fees.trade_fee_numerator / raydium_account.fees.trade_fee_denominator) as u128;
+    let market: Market = Market {
+        tokenMintA: from_Pubkey(raydium_account.coin_vault_mint.clone()),
+        tokenVaultA: from_Pubkey(raydium_account.coin_vault.clone()),
+        tokenMintB: from_Pubkey(raydium_account.pc_vault_mint.clone()),
+        tokenVaultB: from_Pubkey(raydium_account.pc_vault.clone()),
+        fee: fees as u64,
+        dexLabel: DexLabel::RAYDIUM,
+        id: from_Pubkey(address),
+        account_data: Some(data),
+        // USD liquidity from the vaults balances, set by the caller
+        liquidity: None,
+        slot: None,
+    };
+    Ok(market)
+}
+
 pub async fn stream_raydium(account: Pubkey) -> Result<()> {
     let env = Env::new();
     let url = env.wss_rpc_url.as_str();
@@ -197,9 +214,9 @@ pub async fn stream_raydium(account: Pubkey) -> Result<()> {
     loop {
         match account_subscription_receiver.recv() {
             Ok(response) => {
-                let data = response.value.data;
+                let data = response.value.data.clone();
                 let bytes_slice = UiAccountData::decode(&data).unwrap();
-                println!("account subscription data response: {:?}", data);
+                println!("account subscription data response at slot {}: {:?}", response.context.slot, data);
                 // let account_data = unpack_from_slice(bytes_slice.as_slice());
                 // println!("Raydium CLMM Pool updated: {:?}", account);
                 // println!("Data: {:?}", account_data.unwrap());
@@ -252,8 +269,23 @@ pub async fn simulate_route_raydium(printing_amt: bool, amount_in: u64, route: R
             token0.symbol
         );
     }
-    // Simulate a swap
+    // Quote with the true reserves of the pool (vaults + OpenBook open orders - pending PnL)
     let env = Env::new();
+    match get_raydium_reserves(&market) {
+        Ok(reserves) => {
+            let amount_out = reserves.quote_swap_base_in(amount_in_uint, route.token_0to1);
+            if printing_amt {
+                println!("estimatedAmountIn: {:?} {:?}", amount_in_uint, if route.token_0to1 == true { token0.clone().symbol } else { token1.clone().symbol });
+                println!("estimatedAmountOut: {:?} {:?} (orderbook: {})", amount_out, if route.token_0to1 == true { token1.clone().symbol } else { token0.clone().symbol }, reserves.orderbook_enabled);
+            }
+            return Ok((amount_out.to_string(), amount_out.to_string()))
+        }
+        Err(e) => {
+            error!("❌ Raydium reserves not computed for {}, fallback on simulator: {:?}", market.id, e);
+        }
+    }
+
+    // Simulate a swap
     let domain = env.simulator_url;
 
     let req_url = format!("{}raydium_quote?{}", domain, params);
@@ -286,6 +318,174 @@ pub async fn simulate_route_raydium(printing_amt: bool, amount_in: u64, route: R
 
 }
 
+// Reserves used by the Raydium AMM v4 program to compute a swap
+#[derive(Debug, Clone)]
+pub struct RaydiumReserves {
+    pub pool: Pubkey,
+    pub total_coin_without_take_pnl: u64,
+    pub total_pc_without_take_pnl: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+    pub orderbook_enabled: bool,
+    pub slot: u64,
+}
+
+impl RaydiumReserves {
+    // Same computation as process_swap_base_in() in the Raydium program
+    // token_0to1 is coin -> pc (tokenMintA is the coin mint for Raydium markets)
+    pub fn quote_swap_base_in(&self, amount_in: u64, token_0to1: bool) -> u64 {
+        let swap_direction = if token_0to1 { SwapDirection::Coin2PC } else { SwapDirection::PC2Coin };
+        let swap_fee = U128::from(amount_in)
+            .checked_mul(self.swap_fee_numerator.into())
+            .unwrap()
+            .checked_ceil_div(self.swap_fee_denominator.into())
+            .unwrap()
+            .0;
+        let swap_in_after_deduct_fee = U128::from(amount_in).checked_sub(swap_fee).unwrap();
+        Calculator::swap_token_amount_base_in(
+            swap_in_after_deduct_fee,
+            self.total_pc_without_take_pnl.into(),
+            self.total_coin_without_take_pnl.into(),
+            swap_direction,
+        ).as_u64()
+    }
+}
+
+// Accounts read with the pools to quote them (vaults, open orders, market, event queue) and the slot of their batch
+fn raydium_reserves_accounts() -> &'static RwLock<HashMap<Pubkey, (Account, u64)>> {
+    static RAYDIUM_RESERVES_ACCOUNTS: OnceLock<RwLock<HashMap<Pubkey, (Account, u64)>>> = OnceLock::new();
+    RAYDIUM_RESERVES_ACCOUNTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// Event queue of the OpenBook markets, known after the first read of the market
+fn event_queues() -> &'static RwLock<HashMap<Pubkey, Pubkey>> {
+    static EVENT_QUEUES: OnceLock<RwLock<HashMap<Pubkey, Pubkey>>> = OnceLock::new();
+    EVENT_QUEUES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// AmmInfo of the market state, None when the data doesn't come from the chain (API cache)
+pub fn decode_amm_info(market: &Market) -> Option<raydium_amm::state::AmmInfo> {
+    let data = market.account_data.as_ref()?;
+    if data.len() != std::mem::size_of::<raydium_amm::state::AmmInfo>() {
+        return None;
+    }
+    Some(*raydium_amm::state::AmmInfo::load_from_bytes(data).ok()?)
+}
+
+// Accounts to fetch with the decoded state of the pool to quote it, read in the same batch
+// Vaults, then open orders, market and event queue if the orderbook is enabled
+pub fn get_raydium_reserves_keys(market: &Market) -> Vec<Pubkey> {
+    let amm = match decode_amm_info(market) {
+        Some(amm) => amm,
+        None => return Vec::new(),
+    };
+    let mut keys = vec![amm.coin_vault, amm.pc_vault];
+    if AmmStatus::from_u64(amm.status).orderbook_permission() {
+        keys.push(amm.open_orders);
+        keys.push(amm.market);
+        if let Some(event_queue) = event_queues().read().unwrap().get(&amm.market) {
+            keys.push(*event_queue);
+        }
+    }
+    keys
+}
+
+// Keep an account of get_raydium_reserves_keys() fetched at `slot`
+pub fn record_raydium_reserves_account(pubkey: Pubkey, account: Account, slot: u64) {
+    // Only the OpenBook market has this layout
+    if let Ok(market_layout) = MarketStateLayoutV3::try_from_slice(&account.data) {
+        event_queues().write().unwrap().insert(pubkey, market_layout.event_queue);
+    }
+    raydium_reserves_accounts().write().unwrap().insert(pubkey, (account, slot));
+}
+
+// Real swap reserves of a pool from its decoded state and the accounts fetched with it, no RPC call
+pub fn get_raydium_reserves(market: &Market) -> Result<RaydiumReserves> {
+    let pool = from_str(market.id.as_str())?;
+    let amm = decode_amm_info(market).ok_or(anyhow::format_err!("No AmmInfo state for Raydium pool {}", pool))?;
+    let keys = get_raydium_reserves_keys(market);
+    let enable_orderbook = AmmStatus::from_u64(amm.status).orderbook_permission();
+    if enable_orderbook && keys.len() < 5 {
+        return Err(anyhow::format_err!("Event queue not fetched yet for Raydium pool {}", pool));
+    }
+
+    let mut accounts: Vec<Account> = Vec::new();
+    let mut slot = market.slot.unwrap_or(u64::MAX);
+    {
+        let reserves_accounts = raydium_reserves_accounts().read().unwrap();
+        for key in keys.iter() {
+            let (account, account_slot) = reserves_accounts.get(key).ok_or(anyhow::format_err!("Account {} not fetched yet for Raydium pool {}", key, pool))?;
+            accounts.push(account.clone());
+            slot = slot.min(*account_slot);
+        }
+    }
+    let orderbook = if enable_orderbook {
+        Some((accounts[2].clone(), accounts[3].clone(), accounts[4].clone()))
+    } else {
+        None
+    };
+    compute_raydium_reserves(pool, &amm, &accounts[0], &accounts[1], orderbook, slot)
+}
+
+// Compute the real swap reserves of a Raydium AMM v4 pool:
+// vaults balances + tokens in the OpenBook open orders (if orderbook is enabled) - need_take_pnl
+// `orderbook` is (open orders, market, event queue)
+pub fn compute_raydium_reserves(pool: Pubkey, amm: &raydium_amm::state::AmmInfo, coin_vault_account: &Account, pc_vault_account: &Account, orderbook: Option<(Account, Account, Account)>, slot: u64) -> Result<RaydiumReserves> {
+    let amm_program = from_str("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8").unwrap();
+    let coin_vault = spl_token::state::Account::unpack(&coin_vault_account.data)?;
+    let pc_vault = spl_token::state::Account::unpack(&pc_vault_account.data)?;
+    let orderbook_enabled = orderbook.is_some();
+
+    let (total_pc_without_take_pnl, total_coin_without_take_pnl) = match orderbook {
+        Some((mut open_orders_account, mut market_account, mut event_queue_account)) => {
+            let market_layout = MarketStateLayoutV3::try_from_slice(&market_account.data)?;
+
+            let authority = Processor::authority_id(&amm_program, AUTHORITY_AMM, amm.nonce as u8)?;
+            let mut authority_lamports = 0;
+            let mut authority_data: Vec<u8> = Vec::new();
+            let system_program = solana_program::system_program::id();
+            let market_info = AccountInfo::new(&amm.market, false, false, &mut market_account.lamports, &mut market_account.data, &market_account.owner, false, 0);
+            let open_orders_info = AccountInfo::new(&amm.open_orders, false, false, &mut open_orders_account.lamports, &mut open_orders_account.data, &open_orders_account.owner, false, 0);
+            let event_queue_info = AccountInfo::new(&market_layout.event_queue, false, false, &mut event_queue_account.lamports, &mut event_queue_account.data, &event_queue_account.owner, false, 0);
+            let authority_info = AccountInfo::new(&authority, false, false, &mut authority_lamports, &mut authority_data, &system_program, false, 0);
+
+            let (market_state, open_orders) = Processor::load_serum_market_order(
+                &market_info,
+                &open_orders_info,
+                &authority_info,
+                amm,
+                false,
+            )?;
+            Calculator::calc_total_without_take_pnl(
+                pc_vault.amount,
+                coin_vault.amount,
+                &open_orders,
+                amm,
+                &market_state,
+                &event_queue_info,
+                &open_orders_info,
+            ).map_err(|e| anyhow::format_err!("Raydium calc_total_without_take_pnl error: {:?}", e))?
+        }
+        None => {
+            Calculator::calc_total_without_take_pnl_no_orderbook(
+                pc_vault.amount,
+                coin_vault.amount,
+                amm,
+            ).map_err(|e| anyhow::format_err!("Raydium calc_total_without_take_pnl_no_orderbook error: {:?}", e))?
+        }
+    };
+
+    Ok(RaydiumReserves {
+        pool,
+        total_coin_without_take_pnl,
+        total_pc_without_take_pnl,
+        swap_fee_numerator: amm.fees.swap_fee_numerator,
+        swap_fee_denominator: amm.fees.swap_fee_denominator,
+        orderbook_enabled,
+        slot,
+    })
+}
+
 fn de_rating<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
     Ok(match Value::deserialize(deserializer)? {
         Value::String(s) => s.parse().map_err(de::Error::custom)?,
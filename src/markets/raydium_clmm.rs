@@ -51,6 +51,7 @@ impl RaydiumClmmDEX {
                 id: pool.id.clone(),
                 account_data: None,
                 liquidity: None,
+                slot: None,
             };
 
             let pair_string = toPairString(pool.mint_a, pool.mint_b);
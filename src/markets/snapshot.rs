@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use chrono::Utc;
+use log::{error, info};
+use strum::IntoEnumIterator;
+
+use crate::arbitrage::streams::get_fresh_accounts_states;
+use crate::markets::types::{Dex, DexLabel, Market};
+use crate::markets::utils::toPairString;
+
+pub const SNAPSHOT_PATH: &str = "src/markets/cache/pools-snapshot.bin";
+
+// Pools registry with the last known account states, written in borsh
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct PoolsSnapshot {
+    // Newest slot of the account states in the snapshot
+    pub slot: u64,
+    // Unix timestamp of the snapshot
+    pub timestamp: i64,
+    pub markets: Vec<Market>,
+}
+
+// Last account states fetched by get_fresh_accounts_states(), the key is the address of the pool
+fn known_states() -> &'static RwLock<HashMap<String, Market>> {
+    static KNOWN_STATES: OnceLock<RwLock<HashMap<String, Market>>> = OnceLock::new();
+    KNOWN_STATES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+pub fn record_market_states(markets: &HashMap<String, Market>) {
+    let mut states = known_states().write().unwrap();
+    for (key, market) in markets.iter() {
+        if market.slot.is_some() {
+            states.insert(key.clone(), market.clone());
+        }
+    }
+}
+
+// Registry of `dexs` where each market is replaced by its last known state (pools discovered while running included)
+pub fn write_pools_snapshot(dexs: &Vec<Dex>, path: &str) -> Result<()> {
+    let states = known_states().read().unwrap().clone();
+
+    let mut markets: Vec<Market> = Vec::new();
+    for dex in dexs.iter() {
+        for market in dex.pairToMarkets.values().flatten() {
+            if !states.contains_key(&market.id) {
+                markets.push(market.clone());
+            }
+        }
+    }
+    markets.extend(states.values().cloned());
+
+    let snapshot = PoolsSnapshot {
+        slot: states.values().filter_map(|market| market.slot).max().unwrap_or(0),
+        timestamp: Utc::now().timestamp(),
+        markets,
+    };
+
+    // Write in a temporary file to never leave a truncated snapshot
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, borsh::to_vec(&snapshot)?)?;
+    fs::rename(&tmp_path, path)?;
+    info!("📸 Pools snapshot written in {}: {} markets, {} states, slot {}", path, snapshot.markets.len(), states.len(), snapshot.slot);
+    Ok(())
+}
+
+pub fn load_pools_snapshot(path: &str) -> Result<PoolsSnapshot> {
+    let data = fs::read(path)?;
+    let snapshot = PoolsSnapshot::try_from_slice(&data)?;
+    info!("📸 Pools snapshot loaded from {}: {} markets, slot {}, {}s old", path, snapshot.markets.len(), snapshot.slot, Utc::now().timestamp() - snapshot.timestamp);
+    Ok(snapshot)
+}
+
+pub fn dexs_from_snapshot(snapshot: &PoolsSnapshot) -> Vec<Dex> {
+    let mut dexs: Vec<Dex> = DexLabel::iter().map(Dex::new).collect();
+    for market in snapshot.markets.iter() {
+        let dex = dexs.iter_mut().find(|dex| dex.label == market.dexLabel).unwrap();
+        let pair_string = toPairString(market.tokenMintA.clone(), market.tokenMintB.clone());
+        dex.pairToMarkets.entry(pair_string).or_default().push(market.clone());
+    }
+    dexs
+}
+
+// Refetch only the markets with a state in the snapshot, never older than the snapshot slot
+pub async fn refresh_snapshot_states(mut dexs: Vec<Dex>, snapshot_slot: u64) -> Vec<Dex> {
+    let mut markets_with_state: HashMap<String, Market> = HashMap::new();
+    for dex in dexs.iter() {
+        for market in dex.pairToMarkets.values().flatten() {
+            if market.slot.is_some() {
+                markets_with_state.insert(market.id.clone(), market.clone());
+            }
+        }
+    }
+    let min_context_slot = if snapshot_slot > 0 { Some(snapshot_slot) } else { None };
+    let fresh_markets = get_fresh_accounts_states(markets_with_state, min_context_slot).await;
+
+    for dex in dexs.iter_mut() {
+        for markets in dex.pairToMarkets.values_mut() {
+            for market in markets.iter_mut() {
+                if let Some(fresh_market) = fresh_markets.get(&market.id) {
+                    *market = fresh_market.clone();
+                }
+            }
+        }
+    }
+    dexs
+}
+
+pub async fn run_snapshot_service(dexs: Vec<Dex>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Err(e) = write_pools_snapshot(&dexs, SNAPSHOT_PATH) {
+            error!("❌ Pools snapshot not written: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use futures::stream::{select_all, BoxStream, StreamExt};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_client::rpc_response::{Response, RpcLogsResponse};
+use solana_pubsub_client::nonblocking::pubsub_client::{PubsubClient, PubsubClientResult};
+use solana_sdk::bs58;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, EncodedTransactionWithStatusMeta, UiInnerInstructions, UiInstruction, UiLoadedAddresses, UiTransactionEncoding};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::arbitrage::subscriptions::{MAX_BACKOFF, MIN_BACKOFF};
+use crate::common::constants::Env;
+use crate::markets::meteora::AccountData;
+use crate::markets::raydium::decode_amm_info;
+use crate::markets::types::{DexLabel, Market};
+use borsh::BorshDeserialize;
+
+pub const RAYDIUM_AMM_PROGRAM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const METEORA_DLMM_PROGRAM: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+pub const WHIRLPOOL_PROGRAM: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS14EPFLC1HBkB2LfvuEtm6";
+
+// Instruction tags of swap_base_in / swap_base_out in the Raydium AMM program
+const RAYDIUM_SWAP_BASE_IN_TAG: u8 = 9;
+const RAYDIUM_SWAP_BASE_OUT_TAG: u8 = 11;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapEvent {
+    pub dex_label: DexLabel,
+    // Address of the pool, None when it can't be found in the logs (Raydium from logsSubscribe without pool)
+    pub pool: Option<String>,
+    pub signature: String,
+    pub slot: u64,
+    // Same convention as Route: tokenMintA -> tokenMintB
+    pub token_0to1: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    // 0 for Raydium and Whirlpools, the fee rate is in the pool account
+    pub fee: u64,
+    // Raydium: reserves (coin, pc) of the pool before the swap
+    pub reserves_before: Option<(u64, u64)>,
+    // Meteora: active bin (start, end) of the swap
+    pub bins: Option<(i32, i32)>,
+}
+
+// ray_log of a swap, bincode layout of SwapBaseInLog / SwapBaseOutLog in raydium_amm::log
+#[derive(Debug, Clone)]
+pub struct RaydiumSwapLog {
+    pub base_in: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    // 1: PC2Coin, 2: Coin2PC
+    pub direction: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+}
+
+pub fn decode_raydium_swap_log(ray_log: &str) -> Option<RaydiumSwapLog> {
+    let bytes = STANDARD.decode(ray_log.trim()).ok()?;
+    // log_type: u8 + 7 u64 (little endian)
+    if bytes.len() < 57 {
+        return None;
+    }
+    let read_u64 = |index: usize| -> u64 {
+        let start = 1 + index * 8;
+        u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap())
+    };
+    match bytes[0] {
+        // SwapBaseIn: amount_in, minimum_out, direction, user_source, pool_coin, pool_pc, out_amount
+        3 => Some(RaydiumSwapLog {
+            base_in: true,
+            amount_in: read_u64(0),
+            amount_out: read_u64(6),
+            direction: read_u64(2),
+            pool_coin: read_u64(4),
+            pool_pc: read_u64(5),
+        }),
+        // SwapBaseOut: max_in, amount_out, direction, user_source, pool_coin, pool_pc, deduct_in
+        4 => Some(RaydiumSwapLog {
+            base_in: false,
+            amount_in: read_u64(6),
+            amount_out: read_u64(1),
+            direction: read_u64(2),
+            pool_coin: read_u64(4),
+            pool_pc: read_u64(5),
+        }),
+        _ => None,
+    }
+}
+
+// Meteora emits the Swap event with event-cpi: EVENT_IX_TAG_LE + discriminator + borsh data
+// Also accept the data of a "Program data:" log (discriminator + borsh data)
+pub fn decode_meteora_swap_event(data: &[u8]) -> Option<lb_clmm::events::Swap> {
+    let data = data.strip_prefix(anchor_lang::event::EVENT_IX_TAG_LE).unwrap_or(data);
+    let event_data = data.strip_prefix(&lb_clmm::events::Swap::DISCRIMINATOR[..])?;
+    lb_clmm::events::Swap::deserialize(&mut &event_data[..]).ok()
+}
+
+// Fee of a Raydium swap with the swap fee of the pool state, rounded up like process_swap_base_in()
+pub fn get_raydium_swap_fee(amount_in: u64, amm: &raydium_amm::state::AmmInfo) -> u64 {
+    if amm.fees.swap_fee_denominator == 0 {
+        return 0;
+    }
+    (amount_in as u128 * amm.fees.swap_fee_numerator as u128)
+        .div_ceil(amm.fees.swap_fee_denominator as u128) as u64
+}
+
+fn raydium_swap_event(swap_log: RaydiumSwapLog, pool: Option<String>, signature: String, slot: u64) -> SwapEvent {
+    SwapEvent {
+        dex_label: DexLabel::RAYDIUM,
+        pool,
+        signature,
+        slot,
+        // Coin is tokenMintA
+        token_0to1: swap_log.direction == 2,
+        amount_in: swap_log.amount_in,
+        amount_out: swap_log.amount_out,
+        // ray_log don't log the fee, its rate is in the pool account (get_raydium_swap_fee())
+        fee: 0,
+        reserves_before: Some((swap_log.pool_coin, swap_log.pool_pc)),
+        bins: None,
+    }
+}
+
+fn meteora_swap_event(swap: lb_clmm::events::Swap, signature: String, slot: u64) -> SwapEvent {
+    SwapEvent {
+        dex_label: DexLabel::METEORA,
+        pool: Some(swap.lb_pair.to_string()),
+        signature,
+        slot,
+        // Token X is tokenMintA
+        token_0to1: swap.swap_for_y,
+        amount_in: swap.amount_in,
+        amount_out: swap.amount_out,
+        fee: swap.fee,
+        reserves_before: None,
+        bins: Some((swap.start_bin_id, swap.end_bin_id)),
+    }
+}
+
+// Decode the swap events of the logs of a transaction (logsSubscribe or getTransaction)
+// Raydium logs don't include the pool, `raydium_pool` is used when the caller knows it (subscription on one pool)
+// Meteora events are only in logs when emitted with emit!(), event-cpi events are in inner instructions
+pub fn decode_swap_events_from_logs(signature: String, slot: u64, logs: &Vec<String>, raydium_pool: Option<String>) -> Vec<SwapEvent> {
+    let mut swap_events: Vec<SwapEvent> = Vec::new();
+    // Stack of the invoked programs to know which program wrote the log
+    let mut program_stack: Vec<String> = Vec::new();
+
+    for log in logs {
+        let parts: Vec<&str> = log.split_whitespace().collect();
+        if parts.len() >= 3 && parts[0] == "Program" && parts[2] == "invoke" {
+            program_stack.push(parts[1].to_string());
+            continue;
+        }
+        if parts.len() >= 3 && parts[0] == "Program" && (parts[2] == "success" || parts[2] == "failed:") {
+            program_stack.pop();
+            continue;
+        }
+        let current_program = match program_stack.last() {
+            Some(program) => program.as_str(),
+            None => continue,
+        };
+
+        if current_program == RAYDIUM_AMM_PROGRAM {
+            if let Some(ray_log) = log.strip_prefix("Program log: ray_log: ") {
+                if let Some(swap_log) = decode_raydium_swap_log(ray_log) {
+                    swap_events.push(raydium_swap_event(swap_log, raydium_pool.clone(), signature.clone(), slot));
+                }
+            }
+        } else if current_program == METEORA_DLMM_PROGRAM {
+            if let Some(program_data) = log.strip_prefix("Program data: ") {
+                let data = match STANDARD.decode(program_data.trim()) {
+                    Ok(data) => data,
+                    Err(_) => continue,
+                };
+                if let Some(swap) = decode_meteora_swap_event(&data) {
+                    swap_events.push(meteora_swap_event(swap, signature.clone(), slot));
+                }
+            }
+        }
+    }
+    swap_events
+}
+
+// (program, accounts, data) of an instruction of a transaction
+pub type ExecutedInstruction = (String, Vec<String>, Vec<u8>);
+
+// Account keys (static keys then the keys loaded from the lookup tables) and all the instructions,
+// inner instructions included, in execution order
+pub fn get_executed_instructions(signature: &String, transaction: &EncodedTransactionWithStatusMeta) -> Result<(Vec<String>, Vec<ExecutedInstruction>)> {
+    let meta = transaction.meta.clone().ok_or(anyhow::format_err!("No meta for transaction {}", signature))?;
+    let versioned_transaction = transaction.transaction.decode().ok_or(anyhow::format_err!("Can't decode transaction {}", signature))?;
+
+    let mut account_keys: Vec<String> = versioned_transaction.message.static_account_keys().iter().map(|key| key.to_string()).collect();
+    let loaded_addresses: Option<UiLoadedAddresses> = meta.loaded_addresses.clone().into();
+    if let Some(loaded_addresses) = loaded_addresses {
+        account_keys.extend(loaded_addresses.writable);
+        account_keys.extend(loaded_addresses.readonly);
+    }
+
+    let mut instructions: Vec<ExecutedInstruction> = Vec::new();
+    let inner_instructions: Option<Vec<UiInnerInstructions>> = meta.inner_instructions.clone().into();
+    let inner_instructions = inner_instructions.unwrap_or_default();
+    for (index, instruction) in versioned_transaction.message.instructions().iter().enumerate() {
+        instructions.push((
+            account_keys[instruction.program_id_index as usize].clone(),
+            instruction.accounts.iter().map(|i| account_keys[*i as usize].clone()).collect(),
+            instruction.data.clone(),
+        ));
+        for inner in inner_instructions.iter().filter(|inner| inner.index as usize == index) {
+            for inner_instruction in inner.instructions.iter() {
+                if let UiInstruction::Compiled(compiled) = inner_instruction {
+                    instructions.push((
+                        account_keys[compiled.program_id_index as usize].clone(),
+                        compiled.accounts.iter().map(|i| account_keys[*i as usize].clone()).collect(),
+                        bs58::decode(&compiled.data).into_vec().unwrap_or_default(),
+                    ));
+                }
+            }
+        }
+    }
+    Ok((account_keys, instructions))
+}
+
+fn anchor_instruction_discriminator(name: &str) -> [u8; 8] {
+    let hash = solana_sdk::hash::hash(format!("global:{}", name).as_bytes()).to_bytes();
+    hash[..8].try_into().unwrap()
+}
+
+// Amount of a spl-token Transfer (3) or TransferChecked (12) instruction
+fn get_token_transfer_amount(instruction: &ExecutedInstruction) -> Option<u64> {
+    let (program, _, data) = instruction;
+    if program != TOKEN_PROGRAM && program != TOKEN_2022_PROGRAM {
+        return None;
+    }
+    match data.first() {
+        Some(3) | Some(12) if data.len() >= 9 => Some(u64::from_le_bytes(data[1..9].try_into().unwrap())),
+        _ => None,
+    }
+}
+
+// Whirlpools don't emit swap events: the direction comes from the swap instruction
+// and the amounts from the two token transfers made by the swap (in then out)
+pub fn decode_whirlpool_swaps(instructions: &Vec<ExecutedInstruction>, signature: &String, slot: u64) -> Vec<SwapEvent> {
+    let swap_discriminator = anchor_instruction_discriminator("swap");
+    let swap_v2_discriminator = anchor_instruction_discriminator("swap_v2");
+    let mut swap_events: Vec<SwapEvent> = Vec::new();
+
+    for (index, (program, accounts, data)) in instructions.iter().enumerate() {
+        // Args: amount u64, other_amount_threshold u64, sqrt_price_limit u128, amount_specified_is_input bool, a_to_b bool
+        if program != WHIRLPOOL_PROGRAM || data.len() < 42 {
+            continue;
+        }
+        let pool = if data[..8] == swap_discriminator && accounts.len() > 2 {
+            accounts[2].clone()
+        } else if data[..8] == swap_v2_discriminator && accounts.len() > 4 {
+            accounts[4].clone()
+        } else {
+            continue;
+        };
+        let a_to_b = data[41] == 1;
+        let transfers: Vec<u64> = instructions[index + 1..].iter().filter_map(get_token_transfer_amount).take(2).collect();
+        if transfers.len() < 2 {
+            continue;
+        }
+        swap_events.push(SwapEvent {
+            dex_label: DexLabel::ORCA_WHIRLPOOLS,
+            pool: Some(pool),
+            signature: signature.clone(),
+            slot,
+            token_0to1: a_to_b,
+            amount_in: transfers[0],
+            amount_out: transfers[1],
+            // Fee rate is in the pool account, not in the transaction
+            fee: 0,
+            reserves_before: None,
+            bins: None,
+        });
+    }
+    swap_events
+}
+
+// Decode the swap events of a fetched transaction, with the pool of each Raydium swap from the instruction accounts
+pub fn decode_swap_events_from_transaction(signature: String, transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Result<Vec<SwapEvent>> {
+    decode_swap_events_from_encoded_transaction(signature, transaction.slot, &transaction.transaction)
+}
+
+// Same for the transactions of a block (getBlock)
+pub fn decode_swap_events_from_encoded_transaction(signature: String, slot: u64, transaction: &EncodedTransactionWithStatusMeta) -> Result<Vec<SwapEvent>> {
+    let (_, instructions) = get_executed_instructions(&signature, transaction)?;
+    let meta = transaction.meta.clone().ok_or(anyhow::format_err!("No meta for transaction {}", signature))?;
+
+    // Raydium: the n-th swap instruction wrote the n-th ray_log, the amm is the 2nd account
+    let raydium_pools: Vec<String> = instructions.iter()
+        .filter(|(program, accounts, data)| {
+            program == RAYDIUM_AMM_PROGRAM
+            && accounts.len() > 1
+            && (data.first() == Some(&RAYDIUM_SWAP_BASE_IN_TAG) || data.first() == Some(&RAYDIUM_SWAP_BASE_OUT_TAG))
+        })
+        .map(|(_, accounts, _)| accounts[1].clone())
+        .collect();
+
+    let logs: Option<Vec<String>> = meta.log_messages.clone().into();
+    let logs = logs.unwrap_or_default();
+    let mut swap_events: Vec<SwapEvent> = Vec::new();
+    let mut raydium_index = 0;
+    for mut swap_event in decode_swap_events_from_logs(signature.clone(), slot, &logs, None) {
+        if swap_event.dex_label == DexLabel::RAYDIUM {
+            swap_event.pool = raydium_pools.get(raydium_index).cloned();
+            raydium_index += 1;
+        }
+        swap_events.push(swap_event);
+    }
+
+    // Meteora: event-cpi, self invoke of the program with the event as data
+    for (program, _, data) in instructions.iter() {
+        if program == METEORA_DLMM_PROGRAM {
+            if let Some(swap) = decode_meteora_swap_event(data) {
+                swap_events.push(meteora_swap_event(swap, signature.clone(), slot));
+            }
+        }
+    }
+
+    swap_events.extend(decode_whirlpool_swaps(&instructions, &signature, slot));
+
+    Ok(swap_events)
+}
+
+// Relative price move of the pool caused by the swap (0.01 = 1%)
+// Raydium: constant product, price_after / price_before = (x / (x + dx))^2 with x the reserve of the token in,
+// dx after the swap fee of the AmmInfo of `market`
+// Meteora: each crossed bin moves the price of bin_step bps
+pub fn get_price_move(swap_event: &SwapEvent, market: &Market) -> Option<f64> {
+    match swap_event.dex_label {
+        DexLabel::RAYDIUM => {
+            let (pool_coin, pool_pc) = swap_event.reserves_before?;
+            let reserve_in = if swap_event.token_0to1 { pool_coin } else { pool_pc } as f64;
+            if reserve_in == 0.0 {
+                return None;
+            }
+            let amm = decode_amm_info(market)?;
+            let amount_in = swap_event.amount_in.saturating_sub(get_raydium_swap_fee(swap_event.amount_in, &amm)) as f64;
+            Some(1.0 - (reserve_in / (reserve_in + amount_in)).powi(2))
+        }
+        DexLabel::METEORA => {
+            let (start_bin_id, end_bin_id) = swap_event.bins?;
+            let account_data = AccountData::try_from_slice(market.account_data.as_ref()?).ok()?;
+            let bin_step = account_data.bin_step as f64 / 10000.0;
+            Some((1.0 + bin_step).powi((end_bin_id - start_bin_id).abs()) - 1.0)
+        }
+        _ => None,
+    }
+}
+
+pub async fn get_swap_events_from_signature(rpc_client: &RpcClient, signature: String) -> Result<Vec<SwapEvent>> {
+    let transaction = rpc_client.get_transaction_with_config(
+        &Signature::from_str(signature.as_str())?,
+        RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        },
+    ).await?;
+    decode_swap_events_from_transaction(signature, &transaction)
+}
+
+async fn subscribe_logs<'a>(pubsub_client: &'a PubsubClient, dex_label: DexLabel, pool: String) -> PubsubClientResult<BoxStream<'a, (DexLabel, String, Response<RpcLogsResponse>)>> {
+    let (logs_stream, _logs_unsubscribe) = pubsub_client.logs_subscribe(
+        RpcTransactionLogsFilter::Mentions(vec![pool.clone()]),
+        RpcTransactionLogsConfig {
+            commitment: Some(CommitmentConfig::confirmed()),
+        },
+    ).await?;
+    Ok(logs_stream.map(move |response| (dex_label.clone(), pool.clone(), response)).boxed())
+}
+
+// logsSubscribe on each pool, multiplexed on one websocket connection, each decoded swap is sent in `sender` with its reception time
+// The pools received in `new_pools` are subscribed on the current connection
+// Meteora events are emitted with event-cpi so the transaction is fetched to read its inner instructions
+// Reconnects with a backoff until the receiver of `sender` is dropped
+pub async fn subscribe_swap_events(mut pools: Vec<(DexLabel, String)>, mut new_pools: UnboundedReceiver<(DexLabel, String)>, sender: UnboundedSender<(SwapEvent, Instant)>) {
+    let env = Env::new();
+    let rpc_client = Arc::new(RpcClient::new(env.rpc_url.clone()));
+    let mut backoff = MIN_BACKOFF;
+    let mut new_pools_open = true;
+    while !sender.is_closed() {
+        let pubsub_client = match PubsubClient::new(env.wss_rpc_url.as_str()).await {
+            Ok(pubsub_client) => pubsub_client,
+            Err(e) => {
+                error!("❌ Logs subscriptions connection failed, retry in {:?}: {:?}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                continue;
+            }
+        };
+        let mut logs_streams = Vec::new();
+        for (dex_label, pool) in pools.iter() {
+            match subscribe_logs(&pubsub_client, dex_label.clone(), pool.clone()).await {
+                Ok(logs_stream) => logs_streams.push(logs_stream),
+                Err(e) => {
+                    error!("❌ Logs subscription on {:?} pool {} failed: {:?}", dex_label, pool, e);
+                    break;
+                }
+            }
+        }
+        if logs_streams.len() < pools.len() {
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+            continue;
+        }
+        backoff = MIN_BACKOFF;
+        info!("👂 Listen swaps on {} pools", pools.len());
+
+        // The streams end when the connection drops
+        let mut logs_updates = select_all(logs_streams);
+        loop {
+            tokio::select! {
+                new_pool = new_pools.recv(), if new_pools_open => {
+                    let (dex_label, pool) = match new_pool {
+                        Some(new_pool) => new_pool,
+                        None => {
+                            new_pools_open = false;
+                            continue;
+                        }
+                    };
+                    pools.push((dex_label.clone(), pool.clone()));
+                    match subscribe_logs(&pubsub_client, dex_label.clone(), pool.clone()).await {
+                        Ok(logs_stream) => {
+                            info!("👂 Listen swaps on {:?} pool {}", dex_label, pool);
+                            logs_updates.push(logs_stream);
+                        }
+                        Err(e) => {
+                            error!("❌ Logs subscription on new {:?} pool {} failed: {:?}", dex_label, pool, e);
+                            break;
+                        }
+                    }
+                },
+                update = logs_updates.next() => {
+                    let (dex_label, pool, response) = match update {
+                        Some(update) => update,
+                        None => break,
+                    };
+                    let received_at = Instant::now();
+                    if response.value.err.is_some() {
+                        continue;
+                    }
+                    let signature = response.value.signature.clone();
+                    if dex_label == DexLabel::METEORA {
+                        // Not awaited here to keep reading the other pools
+                        let (rpc_client, sender) = (rpc_client.clone(), sender.clone());
+                        tokio::spawn(async move {
+                            match get_swap_events_from_signature(&rpc_client, signature.clone()).await {
+                                Ok(swap_events) => send_pool_swap_events(swap_events, &pool, received_at, &sender),
+                                Err(e) => error!("❌ Can't get swap events of {}: {:?}", signature, e),
+                            }
+                        });
+                    } else {
+                        let swap_events = decode_swap_events_from_logs(signature, response.context.slot, &response.value.logs, Some(pool.clone()));
+                        send_pool_swap_events(swap_events, &pool, received_at, &sender);
+                    }
+                    if sender.is_closed() {
+                        return;
+                    }
+                },
+            }
+        }
+        error!("❌ Logs subscriptions disconnected, reconnect in {:?}", backoff);
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+fn send_pool_swap_events(swap_events: Vec<SwapEvent>, pool: &String, received_at: Instant, sender: &UnboundedSender<(SwapEvent, Instant)>) {
+    for swap_event in swap_events {
+        if swap_event.pool.as_ref() == Some(pool) {
+            let _ = sender.send((swap_event, received_at));
+        }
+    }
+}
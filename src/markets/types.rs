@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 use crate::markets::utils::toPairString;
+use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
-#[derive(Debug, Clone, EnumIter, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, EnumIter, Serialize, Deserialize, BorshSerialize, BorshDeserialize, Eq, PartialEq, Hash)]
 pub enum DexLabel {
     ORCA,
     ORCA_WHIRLPOOLS,
@@ -33,7 +34,7 @@ impl DexLabel {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct Market {
     pub tokenMintA: String,
     pub tokenVaultA: String,
@@ -44,6 +45,9 @@ pub struct Market {
     pub id: String,
     pub account_data: Option<Vec<u8>>,
     pub liquidity: Option<u64>,
+    // Slot at which account_data was observed, None if the data comes from the API cache
+    #[serde(default)]
+    pub slot: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -1 +1,2 @@
-pub mod pools;
\ No newline at end of file
+pub mod pools;
+pub mod pool_discovery;
\ No newline at end of file
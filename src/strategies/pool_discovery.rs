@@ -0,0 +1,187 @@
+use std::{collections::{HashMap, HashSet}, sync::{Arc, OnceLock, RwLock}};
+use anyhow::Result;
+use log::{error, info};
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_client::{rpc_client::RpcClient, rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig}, rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType}};
+use solana_pubsub_client::pubsub_client::PubsubClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+
+use crate::{
+    arbitrage::{subscriptions::subscribe_with_reconnect, types::TokenInArb},
+    common::{constants::Env, prices::get_vaults_liquidity_usd, utils::from_str},
+    markets::{meteora::meteora_market_from_account, orca_whirpools::orca_whirpools_market_from_account, raydium::raydium_market_from_account, types::{DexLabel, Market}},
+};
+
+// Markets found while the bot runs, the key is the address of the pool
+pub type DiscoveredPools = Arc<RwLock<HashMap<String, Market>>>;
+
+// Each discovered pool is also sent to the running strategies
+fn discovered_pools_sender() -> &'static broadcast::Sender<Market> {
+    static DISCOVERED_POOLS_SENDER: OnceLock<broadcast::Sender<Market>> = OnceLock::new();
+    DISCOVERED_POOLS_SENDER.get_or_init(|| broadcast::channel(1024).0)
+}
+
+// Pools discovered after the call, the ones before are in DiscoveredPools
+pub fn subscribe_discovered_pools() -> broadcast::Receiver<Market> {
+    discovered_pools_sender().subscribe()
+}
+
+// Amount of a SPL Token (or Token-2022) account, same offset in both layouts
+fn token_account_amount(data: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(64..72)?.try_into().ok()?))
+}
+
+// USD liquidity of a new pool from the balances of its vaults
+fn fetch_liquidity_usd(rpc_client: &RpcClient, market: &Market) -> Result<Option<f64>> {
+    let vaults = vec![from_str(market.tokenVaultA.as_str())?, from_str(market.tokenVaultB.as_str())?];
+    let accounts = rpc_client.get_multiple_accounts(&vaults)?;
+    let amount_a = accounts[0].as_ref().and_then(|account| token_account_amount(&account.data)).ok_or(anyhow::format_err!("Vault {} not found", market.tokenVaultA))?;
+    let amount_b = accounts[1].as_ref().and_then(|account| token_account_amount(&account.data)).ok_or(anyhow::format_err!("Vault {} not found", market.tokenVaultB))?;
+    Ok(get_vaults_liquidity_usd(market, amount_a, amount_b))
+}
+
+// Program ids, data size and memcmp offsets of (token_a, token_b) in the pool account
+pub fn discovery_settings(dex_label: DexLabel) -> Option<(&'static str, u64, usize, usize)> {
+    match dex_label {
+        DexLabel::RAYDIUM => Some(("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", 752, 400, 432)),
+        DexLabel::ORCA_WHIRLPOOLS => Some(("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc", 653, 101, 181)),
+        DexLabel::METEORA => Some(("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo", 904, 88, 120)),
+        DexLabel::ORCA | DexLabel::RAYDIUM_CLMM => None,
+    }
+}
+
+pub fn decode_new_pool(dex_label: DexLabel, address: Pubkey, data: Vec<u8>) -> Result<Market> {
+    match dex_label {
+        DexLabel::RAYDIUM => raydium_market_from_account(address, data),
+        DexLabel::ORCA_WHIRLPOOLS => orca_whirpools_market_from_account(address, data),
+        DexLabel::METEORA => meteora_market_from_account(address, data),
+        _ => Err(anyhow::format_err!("Pool discovery not implemented for {:?}", dex_label)),
+    }
+}
+
+// Replace the getProgramAccounts polling of get_fresh_pools():
+// one programSubscribe per (program, token, side), each new pool touching our tokens is pushed in `discovered`
+pub async fn run_pool_discovery(tokens: Vec<TokenInArb>, known_pools: HashSet<String>, discovered: DiscoveredPools) -> Result<()> {
+    info!("🔭 Launch new pools discovery on RAYDIUM, ORCA_WHIRLPOOLS and METEORA...");
+    let token_addresses: HashSet<String> = tokens.iter().map(|token| token.address.clone()).collect();
+    let known_pools = Arc::new(RwLock::new(known_pools));
+    let mut set: JoinSet<()> = JoinSet::new();
+
+    for dex_label in [DexLabel::RAYDIUM, DexLabel::ORCA_WHIRLPOOLS, DexLabel::METEORA] {
+        let mut subscribed_tokens: HashSet<String> = HashSet::new();
+        for token in tokens.iter() {
+            // Avoid subscribe for the base token (often SOL), every pool of it is already filtered by the other tokens
+            if token.address == tokens[0].address || !subscribed_tokens.insert(token.address.clone()) {
+                continue;
+            }
+            for on_tokena in [true, false] {
+                let dex_label = dex_label.clone();
+                let token = token.address.clone();
+                let token_addresses = token_addresses.clone();
+                let known_pools = known_pools.clone();
+                let discovered = discovered.clone();
+                set.spawn_blocking(move || {
+                    let name = format!("{:?} pool discovery subscription on {}", dex_label, token);
+                    subscribe_with_reconnect(
+                        name.as_str(),
+                        || subscribe_new_pools(dex_label.clone(), token.clone(), on_tokena, token_addresses.clone(), known_pools.clone(), discovered.clone()),
+                        || false,
+                    );
+                });
+            }
+        }
+    }
+
+    while let Some(res) = set.join_next().await {
+        info!("🔭 Pool discovery task ended: {:?}", res);
+    }
+    Ok(())
+}
+
+fn subscribe_new_pools(dex_label: DexLabel, token: String, on_tokena: bool, token_addresses: HashSet<String>, known_pools: Arc<RwLock<HashSet<String>>>, discovered: DiscoveredPools) -> Result<()> {
+    let (program, data_size, offset_tokena, offset_tokenb) = discovery_settings(dex_label.clone()).unwrap();
+    let env = Env::new();
+    let rpc_client = RpcClient::new_with_commitment(env.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let filters = Some(vec![
+        RpcFilterType::Memcmp(Memcmp::new(
+            if on_tokena == true {
+                offset_tokena
+            } else {
+                offset_tokenb
+            },
+            MemcmpEncodedBytes::Base58(token.clone()),
+        )),
+        RpcFilterType::DataSize(data_size),
+    ]);
+
+    let (mut _program_subscription_client, program_subscription_receiver) =
+    PubsubClient::program_subscribe(
+        env.wss_rpc_url.as_str(),
+        &from_str(program).unwrap(),
+        Some(RpcProgramAccountsConfig {
+            filters,
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                data_slice: None,
+                commitment: Some(CommitmentConfig::confirmed()),
+                min_context_slot: None,
+            },
+            ..RpcProgramAccountsConfig::default()
+        }),
+    )?;
+
+    loop {
+        match program_subscription_receiver.recv() {
+            Ok(response) => {
+                let pool_address = response.value.pubkey;
+                // Every swap on a pool updates its account: a known pool (loaded, discovered or not decodable) is skipped
+                // before any decoding, only its first notification is handled
+                if !known_pools.write().unwrap().insert(pool_address.clone()) {
+                    continue;
+                }
+                let data = match UiAccountData::decode(&response.value.account.data) {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let mut market = match decode_new_pool(dex_label.clone(), from_str(pool_address.as_str()).unwrap(), data) {
+                    Ok(market) => market,
+                    Err(e) => {
+                        error!("❌ Can't decode new {:?} pool {}: {:?}", dex_label, pool_address, e);
+                        continue;
+                    }
+                };
+                if !token_addresses.contains(&market.tokenMintA) || !token_addresses.contains(&market.tokenMintB) {
+                    continue;
+                }
+                market.slot = Some(response.context.slot);
+                // Whirlpools liquidity comes from the pool state
+                if market.dexLabel != DexLabel::ORCA_WHIRLPOOLS {
+                    match fetch_liquidity_usd(&rpc_client, &market) {
+                        Ok(Some(liquidity_usd)) => market.liquidity = Some(liquidity_usd as u64),
+                        Ok(None) => error!("❌ No USD price for the tokens of the new {:?} pool {}, liquidity unknown", dex_label, pool_address),
+                        Err(e) => error!("❌ Liquidity of the new {:?} pool {} not fetched: {:?}", dex_label, pool_address, e),
+                    }
+                }
+                info!("🆕 New {:?} pool {} discovered at slot {}, liquidity {:?}$", dex_label, pool_address, response.context.slot, market.liquidity);
+                discovered.write().unwrap().insert(pool_address, market.clone());
+                // No receiver when no strategy runs yet
+                let _ = discovered_pools_sender().send(market);
+            }
+            Err(e) => {
+                error!("❌ Program subscription error: {:?}", e);
+                break;
+            }
+        }
+    }
+
+    // Disconnected, subscribe_with_reconnect() subscribes again
+    Ok(())
+}
+
+// Snapshot of the pools discovered since the start of the bot
+pub fn get_discovered_pools(discovered: &DiscoveredPools) -> HashMap<String, Market> {
+    discovered.read().unwrap().clone()
+}
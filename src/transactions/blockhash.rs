@@ -0,0 +1,99 @@
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{error, info};
+use solana_client::nonblocking::rpc_client::RpcClient as NonblockingRpcClient;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash};
+
+// A cached blockhash older than this is refetched by get_blockhash_for_tx()
+const MAX_BLOCKHASH_AGE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct BlockhashInfo {
+    pub blockhash: Hash,
+    // The blockhash can't be used after this block height
+    pub last_valid_block_height: u64,
+    pub block_height: u64,
+    pub slot: u64,
+    // RPC where the blockhash comes from, devnet and mainnet can't share it
+    pub rpc_url: String,
+    pub updated_at: Instant,
+}
+
+fn latest_blockhash() -> &'static RwLock<Option<BlockhashInfo>> {
+    static LATEST_BLOCKHASH: OnceLock<RwLock<Option<BlockhashInfo>>> = OnceLock::new();
+    LATEST_BLOCKHASH.get_or_init(|| RwLock::new(None))
+}
+
+// Non blocking read of the last blockhash refreshed by run_blockhash_service()
+pub fn get_latest_blockhash_info() -> Option<BlockhashInfo> {
+    latest_blockhash().read().unwrap().clone()
+}
+
+pub fn get_current_slot_cached() -> Option<u64> {
+    get_latest_blockhash_info().map(|blockhash_info| blockhash_info.slot)
+}
+
+// Poll the blockhash, the block height and the slot in background
+pub async fn run_blockhash_service(rpc_url: String, refresh_interval: Duration) {
+    info!("🧱 Launch blockhash service on {}...", rpc_url);
+    let rpc_client = NonblockingRpcClient::new(rpc_url.clone());
+    let commitment_config = CommitmentConfig::confirmed();
+    loop {
+        let blockhash = rpc_client.get_latest_blockhash_with_commitment(commitment_config).await;
+        let block_height = rpc_client.get_block_height_with_commitment(commitment_config).await;
+        let slot = rpc_client.get_slot_with_commitment(commitment_config).await;
+        match (blockhash, block_height, slot) {
+            (Ok((blockhash, last_valid_block_height)), Ok(block_height), Ok(slot)) => {
+                *latest_blockhash().write().unwrap() = Some(BlockhashInfo {
+                    blockhash,
+                    last_valid_block_height,
+                    block_height,
+                    slot,
+                    rpc_url: rpc_url.clone(),
+                    updated_at: Instant::now(),
+                });
+            }
+            (blockhash, block_height, slot) => {
+                error!("❌ Blockhash service refresh error: {:?} {:?} {:?}", blockhash.err(), block_height.err(), slot.err());
+            }
+        }
+        tokio::time::sleep(refresh_interval).await;
+    }
+}
+
+// Blockhash of the service when it is fresh and from the same RPC, else fetched with `rpc_client`
+pub fn get_blockhash_for_tx(rpc_client: &RpcClient) -> Result<BlockhashInfo> {
+    if let Some(blockhash_info) = get_latest_blockhash_info() {
+        if blockhash_info.rpc_url == rpc_client.url() && blockhash_info.updated_at.elapsed() <= MAX_BLOCKHASH_AGE {
+            return Ok(blockhash_info);
+        }
+    }
+    let commitment_config = CommitmentConfig::confirmed();
+    let (blockhash, last_valid_block_height) = rpc_client.get_latest_blockhash_with_commitment(commitment_config)?;
+    let block_height = rpc_client.get_block_height_with_commitment(commitment_config)?;
+    let slot = rpc_client.get_slot_with_commitment(commitment_config)?;
+    let blockhash_info = BlockhashInfo {
+        blockhash,
+        last_valid_block_height,
+        block_height,
+        slot,
+        rpc_url: rpc_client.url(),
+        updated_at: Instant::now(),
+    };
+    *latest_blockhash().write().unwrap() = Some(blockhash_info.clone());
+    Ok(blockhash_info)
+}
+
+// Check the expiry before sending: the current block height is the last one seen by the service
+// (or the one of the blockhash), plus the blocks produced since (~400ms per block)
+pub fn is_blockhash_expired(blockhash_info: &BlockhashInfo) -> bool {
+    let reference = match get_latest_blockhash_info() {
+        Some(latest) if latest.rpc_url == blockhash_info.rpc_url && latest.updated_at > blockhash_info.updated_at => latest,
+        _ => blockhash_info.clone(),
+    };
+    let estimated_block_height = reference.block_height + (reference.updated_at.elapsed().as_millis() / 400) as u64;
+    estimated_block_height > blockhash_info.last_valid_block_height
+}
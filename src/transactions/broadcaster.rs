@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::future::join_all;
+use log::{error, info};
+use serde_json::{json, Value};
+use solana_client::connection_cache::ConnectionCache;
+use solana_connection_cache::client_connection::ClientConnection;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+
+// An endpoint under this weight only receives the probing broadcasts
+pub const MIN_ENDPOINT_WEIGHT: f64 = 0.2;
+// Every PROBE_INTERVAL broadcasts all the endpoints are used, to measure again the skipped ones
+const PROBE_INTERVAL: u64 = 10;
+// Latency which halves the weight of an endpoint
+const REFERENCE_LATENCY_MS: f64 = 200.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BroadcastEndpoint {
+    Rpc(String),
+    Tpu(SocketAddr),
+}
+
+impl BroadcastEndpoint {
+    pub fn name(&self) -> String {
+        match self {
+            BroadcastEndpoint::Rpc(url) => url.clone(),
+            BroadcastEndpoint::Tpu(address) => format!("tpu://{}", address),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointStats {
+    pub sent: u64,
+    // Submission refused or not acknowledged
+    pub errors: u64,
+    // Submissions of transactions which never landed
+    pub dropped: u64,
+    // Transactions landed where this endpoint acknowledged the submission first, not necessarily the one which delivered it to the leader
+    pub fastest_ack: u64,
+    pub total_latency_ms: u128,
+    pub weight: f64,
+}
+
+impl Default for EndpointStats {
+    fn default() -> Self {
+        EndpointStats { sent: 0, errors: 0, dropped: 0, fastest_ack: 0, total_latency_ms: 0, weight: 1.0 }
+    }
+}
+
+impl EndpointStats {
+    pub fn average_latency_ms(&self) -> f64 {
+        let acknowledged = self.sent - self.errors;
+        if acknowledged == 0 {
+            return 0.0;
+        }
+        self.total_latency_ms as f64 / acknowledged as f64
+    }
+
+    pub fn drop_rate(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        (self.errors + self.dropped) as f64 / self.sent as f64
+    }
+
+    // Delivery rate, bonus for the fastest acknowledgements of landed transactions, divided by the latency
+    fn update_weight(&mut self) {
+        let fastest_ack_rate = if self.sent == 0 { 0.0 } else { self.fastest_ack as f64 / self.sent as f64 };
+        let latency_factor = 1.0 / (1.0 + self.average_latency_ms() / REFERENCE_LATENCY_MS);
+        self.weight = ((1.0 - self.drop_rate()) * (1.0 + fastest_ack_rate) * latency_factor).clamp(0.0, 1.0);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Submission {
+    pub endpoint: String,
+    // Time to the acknowledgement of the endpoint, None if refused
+    pub latency: Option<Duration>,
+}
+
+// Send the same signed transaction to a set of RPC and TPU endpoints at once
+#[derive(Clone)]
+pub struct Broadcaster {
+    endpoints: Vec<BroadcastEndpoint>,
+    stats: Arc<RwLock<HashMap<String, EndpointStats>>>,
+    // Submissions of the transactions waiting for their landing status
+    pending: Arc<RwLock<HashMap<Signature, Vec<Submission>>>>,
+    counter_broadcasts: Arc<RwLock<u64>>,
+    client: reqwest::Client,
+    connection_cache: Arc<ConnectionCache>,
+}
+
+impl Broadcaster {
+    pub fn new(endpoints: Vec<BroadcastEndpoint>) -> Self {
+        let stats = endpoints.iter().map(|endpoint| (endpoint.name(), EndpointStats::default())).collect();
+        Broadcaster {
+            endpoints,
+            stats: Arc::new(RwLock::new(stats)),
+            pending: Arc::new(RwLock::new(HashMap::new())),
+            counter_broadcasts: Arc::new(RwLock::new(0)),
+            client: reqwest::Client::new(),
+            connection_cache: Arc::new(ConnectionCache::new_quic("connection_cache_broadcaster_quic", 4)),
+        }
+    }
+
+    // RPC endpoints from a comma separated list of URLs, without the duplicates
+    pub fn from_rpc_urls(rpc_urls: &String) -> Self {
+        let mut endpoints: Vec<BroadcastEndpoint> = Vec::new();
+        for url in rpc_urls.split(',').map(|url| url.trim()).filter(|url| !url.is_empty()) {
+            let endpoint = BroadcastEndpoint::Rpc(url.to_string());
+            if !endpoints.contains(&endpoint) {
+                endpoints.push(endpoint);
+            }
+        }
+        Self::new(endpoints)
+    }
+
+    pub fn add_endpoint(&mut self, endpoint: BroadcastEndpoint) {
+        self.stats.write().unwrap().entry(endpoint.name()).or_default();
+        self.endpoints.push(endpoint);
+    }
+
+    pub fn get_stats(&self) -> HashMap<String, EndpointStats> {
+        self.stats.read().unwrap().clone()
+    }
+
+    // Endpoints of the next broadcast: weight over MIN_ENDPOINT_WEIGHT, at least the best one, all when probing
+    fn get_routes(&self) -> Vec<BroadcastEndpoint> {
+        let counter_broadcasts = {
+            let mut counter = self.counter_broadcasts.write().unwrap();
+            *counter += 1;
+            *counter
+        };
+        let stats = self.stats.read().unwrap();
+        let weight = |endpoint: &BroadcastEndpoint| stats.get(&endpoint.name()).map_or(1.0, |stats| stats.weight);
+        let mut routes: Vec<BroadcastEndpoint> = self.endpoints.clone();
+        routes.sort_by(|a, b| weight(b).partial_cmp(&weight(a)).unwrap());
+        if counter_broadcasts % PROBE_INTERVAL == 0 {
+            return routes;
+        }
+        let best = routes.first().cloned();
+        routes.retain(|endpoint| weight(endpoint) >= MIN_ENDPOINT_WEIGHT);
+        if routes.is_empty() {
+            routes.extend(best);
+        }
+        routes
+    }
+
+    async fn send_rpc(&self, url: &String, encoded_transaction: &String) -> Result<()> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendTransaction",
+            "params": [encoded_transaction, { "encoding": "base64", "skipPreflight": true, "maxRetries": 0 }],
+        });
+        let response: Value = self.client.post(url).json(&body).send().await?.json().await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::format_err!("sendTransaction error: {}", error));
+        }
+        Ok(())
+    }
+
+    async fn send_tpu(&self, address: SocketAddr, wire_transaction: Vec<u8>) -> Result<()> {
+        let connection_cache = self.connection_cache.clone();
+        tokio::task::spawn_blocking(move || connection_cache.get_connection(&address).send_data(&wire_transaction)).await??;
+        Ok(())
+    }
+
+    // Send to all the routed endpoints concurrently, Ok if at least one acknowledged the transaction
+    pub async fn broadcast(&self, transaction: &VersionedTransaction) -> Result<Signature> {
+        let signature = transaction.signatures[0];
+        let wire_transaction = bincode::serialize(transaction)?;
+        let encoded_transaction = STANDARD.encode(&wire_transaction);
+
+        let routes = self.get_routes();
+        let submissions: Vec<Submission> = join_all(routes.iter().map(|endpoint| {
+            let wire_transaction = wire_transaction.clone();
+            let encoded_transaction = &encoded_transaction;
+            async move {
+                let start = Instant::now();
+                let result = match endpoint {
+                    BroadcastEndpoint::Rpc(url) => self.send_rpc(url, encoded_transaction).await,
+                    BroadcastEndpoint::Tpu(address) => self.send_tpu(*address, wire_transaction).await,
+                };
+                let latency = match result {
+                    Ok(()) => Some(start.elapsed()),
+                    Err(e) => {
+                        error!("❌ Transaction {} not sent to {}: {:?}", signature, endpoint.name(), e);
+                        None
+                    }
+                };
+                Submission { endpoint: endpoint.name(), latency }
+            }
+        })).await;
+
+        {
+            let mut stats = self.stats.write().unwrap();
+            for submission in submissions.iter() {
+                let endpoint_stats = stats.entry(submission.endpoint.clone()).or_default();
+                endpoint_stats.sent += 1;
+                match submission.latency {
+                    Some(latency) => endpoint_stats.total_latency_ms += latency.as_millis(),
+                    None => endpoint_stats.errors += 1,
+                }
+                endpoint_stats.update_weight();
+            }
+        }
+        let acknowledged = submissions.iter().filter(|submission| submission.latency.is_some()).count();
+        info!("📡 Transaction {} broadcasted to {}/{} endpoints", signature, acknowledged, submissions.len());
+        self.pending.write().unwrap().insert(signature, submissions);
+        if acknowledged == 0 {
+            return Err(anyhow::format_err!("Transaction {} refused by all the endpoints", signature));
+        }
+        Ok(signature)
+    }
+
+    // Landing status of a broadcasted transaction: a landed transaction is credited to the fastest acknowledgement,
+    // the endpoint which delivered it is unknown. All the submissions count as dropped if it never landed
+    // Return the endpoint with the fastest acknowledgement of a landed transaction
+    pub fn record_landing(&self, signature: &Signature, landed: bool) -> Option<String> {
+        let submissions = self.pending.write().unwrap().remove(signature)?;
+        let fastest_ack = submissions.iter()
+            .filter_map(|submission| submission.latency.map(|latency| (latency, submission.endpoint.clone())))
+            .min()
+            .map(|(_, endpoint)| endpoint);
+
+        let mut stats = self.stats.write().unwrap();
+        for submission in submissions.iter().filter(|submission| submission.latency.is_some()) {
+            let endpoint_stats = stats.entry(submission.endpoint.clone()).or_default();
+            if !landed {
+                endpoint_stats.dropped += 1;
+            } else if Some(&submission.endpoint) == fastest_ack.as_ref() {
+                endpoint_stats.fastest_ack += 1;
+            }
+            endpoint_stats.update_weight();
+        }
+        if landed {
+            info!("📡 Transaction {} landed, fastest acknowledgement by {:?}", signature, fastest_ack);
+            fastest_ack
+        } else {
+            None
+        }
+    }
+}
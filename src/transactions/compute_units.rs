@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use log::info;
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::instruction::Instruction;
+
+use crate::markets::types::DexLabel;
+use crate::transactions::create_transaction::InstructionDetails;
+
+pub const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+// Margin added to the simulated consumption, the state can move between simulation and execution
+pub const COMPUTE_UNITS_MARGIN: f64 = 0.15;
+// Instructions without market: compute budget, profit guard, tip transfer...
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u64 = 10_000;
+
+// Compute units of one swap instruction when no simulation of the same path is known
+pub fn get_dex_compute_units(dex_label: &DexLabel) -> u64 {
+    match dex_label {
+        DexLabel::RAYDIUM => 60_000,
+        DexLabel::ORCA_WHIRLPOOLS => 120_000,
+        DexLabel::METEORA => 150_000,
+        DexLabel::RAYDIUM_CLMM => 150_000,
+        DexLabel::ORCA => 80_000,
+    }
+}
+
+// Max consumption observed in simulation for each sequence of DEX (one per hop)
+fn compute_units_profiles() -> &'static RwLock<HashMap<Vec<DexLabel>, u64>> {
+    static COMPUTE_UNITS_PROFILES: OnceLock<RwLock<HashMap<Vec<DexLabel>, u64>>> = OnceLock::new();
+    COMPUTE_UNITS_PROFILES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+pub fn get_path_dex_labels(instructions: &Vec<InstructionDetails>) -> Vec<DexLabel> {
+    instructions.iter().filter_map(|instruction| instruction.market.as_ref().map(|market| market.dex_label.clone())).collect()
+}
+
+pub fn record_compute_units(dex_labels: Vec<DexLabel>, units_consumed: u64) {
+    let mut profiles = compute_units_profiles().write().unwrap();
+    let profile = profiles.entry(dex_labels).or_insert(0);
+    *profile = (*profile).max(units_consumed);
+}
+
+// Observed profile of the path, else the sum of the DEX profiles of each hop
+pub fn estimate_compute_units(instructions: &Vec<InstructionDetails>) -> u64 {
+    let dex_labels = get_path_dex_labels(instructions);
+    if let Some(units) = compute_units_profiles().read().unwrap().get(&dex_labels) {
+        return *units;
+    }
+    instructions.iter()
+        .map(|instruction| match &instruction.market {
+            Some(market) => get_dex_compute_units(&market.dex_label),
+            None => DEFAULT_INSTRUCTION_COMPUTE_UNITS,
+        })
+        .sum()
+}
+
+// Compute unit limit of the transaction: simulated consumption (recorded in the profiles) or the profiles, plus the margin
+pub fn plan_compute_units(units_consumed: Option<u64>, instructions: &Vec<InstructionDetails>) -> u64 {
+    let units = match units_consumed {
+        Some(units_consumed) if units_consumed > 0 => {
+            record_compute_units(get_path_dex_labels(instructions), units_consumed);
+            units_consumed
+        }
+        _ => estimate_compute_units(instructions),
+    };
+    let compute_units = ((units as f64 * (1.0 + COMPUTE_UNITS_MARGIN)).ceil() as u64).min(MAX_COMPUTE_UNITS);
+    info!("🔢 Compute units limit: {} (simulated: {:?})", compute_units, units_consumed);
+    compute_units
+}
+
+// Replace the SetComputeUnitLimit and SetComputeUnitPrice instructions wherever they are, insert them first if missing
+pub fn set_compute_budget_instructions(instructions: &mut Vec<Instruction>, compute_unit_limit: u32, compute_unit_price: u64) {
+    let mut limit_set = false;
+    let mut price_set = false;
+    for instruction in instructions.iter_mut() {
+        if instruction.program_id != compute_budget::id() {
+            continue;
+        }
+        match instruction.data.first() {
+            Some(2) => {
+                *instruction = ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit);
+                limit_set = true;
+            }
+            Some(3) => {
+                *instruction = ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price);
+                price_set = true;
+            }
+            _ => {}
+        }
+    }
+    if !price_set {
+        instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price));
+    }
+    if !limit_set {
+        instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+use solana_transaction_status::{EncodedTransaction, UiLoadedAddresses, UiMessage, UiTransactionEncoding, UiTransactionStatusMeta, UiTransactionTokenBalance};
+
+use crate::common::database::insert_trade_result_collection;
+use crate::common::prices::get_usd_value;
+use crate::transactions::wsol::WSOL_MINT;
+
+// getSignatureStatuses accepts at most 256 signatures
+const MAX_SIGNATURES_PER_REQUEST: usize = 256;
+// Results not read by wait_for_trade_result() are dropped after this delay
+const TRADE_RESULT_RETENTION: Duration = Duration::from_secs(300);
+pub const TRADE_RESULTS_COLLECTION: &str = "trade_results";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FailureKind {
+    // Profit guard check_profit refused the cycle
+    NotProfitable,
+    // A swap got less than its minimum_amount_out
+    Slippage,
+    InsufficientFunds,
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TradeStatus {
+    Landed,
+    // Landed but reverted, the fees are paid
+    Failed(FailureKind),
+    // Not landed before the expiration of its blockhash
+    Dropped,
+}
+
+// Sent transaction followed until it lands or its blockhash expires
+#[derive(Debug, Clone)]
+pub struct PendingTrade {
+    pub strategy: String,
+    pub payer: Pubkey,
+    pub token_in: String,
+    pub expected_profit: f64,
+    pub last_valid_block_height: u64,
+    pub sent_at: Instant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeResult {
+    pub signature: String,
+    pub strategy: String,
+    pub status: TradeStatus,
+    pub slot: Option<u64>,
+    pub token_in: String,
+    pub expected_profit: f64,
+    // Balance delta of the payer in token_in, fees included for SOL when the payer pays them
+    pub realized_profit: i64,
+    pub realized_profit_usd: Option<f64>,
+    pub fee_lamports: u64,
+    pub confirmation_ms: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StrategyStats {
+    pub landed: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    pub failures: HashMap<String, u64>,
+    pub realized_profit_usd: f64,
+    pub fees_lamports: u64,
+}
+
+fn pending_trades() -> &'static RwLock<HashMap<Signature, PendingTrade>> {
+    static PENDING_TRADES: OnceLock<RwLock<HashMap<Signature, PendingTrade>>> = OnceLock::new();
+    PENDING_TRADES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn trade_results() -> &'static RwLock<HashMap<Signature, (TradeResult, Instant)>> {
+    static TRADE_RESULTS: OnceLock<RwLock<HashMap<Signature, (TradeResult, Instant)>>> = OnceLock::new();
+    TRADE_RESULTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn strategy_stats() -> &'static RwLock<HashMap<String, StrategyStats>> {
+    static STRATEGY_STATS: OnceLock<RwLock<HashMap<String, StrategyStats>>> = OnceLock::new();
+    STRATEGY_STATS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+pub fn track_transaction(signature: Signature, pending_trade: PendingTrade) {
+    pending_trades().write().unwrap().insert(signature, pending_trade);
+}
+
+pub fn get_strategy_stats() -> HashMap<String, StrategyStats> {
+    strategy_stats().read().unwrap().clone()
+}
+
+// Wait without blocking the thread for the result of a tracked transaction, None after `timeout`
+pub async fn wait_for_trade_result(signature: &Signature, timeout: Duration) -> Option<TradeResult> {
+    let start = Instant::now();
+    loop {
+        if let Some((trade_result, _)) = trade_results().write().unwrap().remove(signature) {
+            return Some(trade_result);
+        }
+        if start.elapsed() >= timeout {
+            return None;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+pub fn classify_failure(err: &TransactionError, logs: &Vec<String>) -> FailureKind {
+    let has_log = |patterns: &[&str]| logs.iter().any(|log| {
+        let log = log.to_lowercase();
+        patterns.iter().any(|pattern| log.contains(pattern))
+    });
+    match err {
+        TransactionError::InsufficientFundsForFee | TransactionError::InsufficientFundsForRent { .. } => FailureKind::InsufficientFunds,
+        _ if has_log(&["notprofitable"]) => FailureKind::NotProfitable,
+        _ if has_log(&["slippage", "amountoutbelowminimum", "minimum amount out"]) => FailureKind::Slippage,
+        _ if has_log(&["insufficient funds", "insufficient lamports"]) => FailureKind::InsufficientFunds,
+        err => FailureKind::Other(err.to_string()),
+    }
+}
+
+fn get_token_balance(balances: &Vec<UiTransactionTokenBalance>, owner: &String, mint: &String) -> i128 {
+    balances.iter()
+        .filter(|balance| &balance.mint == mint && Option::<String>::from(balance.owner.clone()).as_ref() == Some(owner))
+        .map(|balance| balance.ui_token_amount.amount.parse::<i128>().unwrap_or(0))
+        .sum()
+}
+
+// Account keys in the order of the balances: the static keys, then the writable and the readonly loaded addresses
+pub fn get_account_keys(transaction: &EncodedTransaction, meta: &UiTransactionStatusMeta) -> Vec<String> {
+    let mut account_keys: Vec<String> = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Raw(message) => message.account_keys.clone(),
+            // Already with the loaded addresses
+            UiMessage::Parsed(message) => return message.account_keys.iter().map(|account| account.pubkey.clone()).collect(),
+        },
+        transaction => transaction.decode()
+            .map(|transaction| transaction.message.static_account_keys().iter().map(|key| key.to_string()).collect())
+            .unwrap_or_default(),
+    };
+    if let Some(loaded_addresses) = Option::<UiLoadedAddresses>::from(meta.loaded_addresses.clone()) {
+        account_keys.extend(loaded_addresses.writable);
+        account_keys.extend(loaded_addresses.readonly);
+    }
+    account_keys
+}
+
+// Delta of the payer balance in `token_in`, for SOL the native balance of the payer (which is not the first
+// account with a separate fee payer) plus the wSOL accounts
+pub fn get_realized_profit(meta: &UiTransactionStatusMeta, account_keys: &Vec<String>, payer: &Pubkey, token_in: &String) -> i64 {
+    let pre_token_balances: Vec<UiTransactionTokenBalance> = Option::from(meta.pre_token_balances.clone()).unwrap_or_default();
+    let post_token_balances: Vec<UiTransactionTokenBalance> = Option::from(meta.post_token_balances.clone()).unwrap_or_default();
+    let owner = payer.to_string();
+    let mut delta = get_token_balance(&post_token_balances, &owner, token_in) - get_token_balance(&pre_token_balances, &owner, token_in);
+    if token_in == WSOL_MINT {
+        if let Some(index) = account_keys.iter().position(|account_key| account_key == &owner) {
+            let pre_balance = meta.pre_balances.get(index).cloned().unwrap_or(0) as i128;
+            let post_balance = meta.post_balances.get(index).cloned().unwrap_or(0) as i128;
+            delta += post_balance - pre_balance;
+        }
+    }
+    delta as i64
+}
+
+async fn get_trade_result(rpc_client: &RpcClient, signature: &Signature, pending_trade: &PendingTrade) -> Result<TradeResult> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let transaction = rpc_client.get_transaction_with_config(signature, config).await?;
+    let meta = transaction.transaction.meta.ok_or(anyhow::format_err!("Transaction {} without meta", signature))?;
+    let account_keys = get_account_keys(&transaction.transaction.transaction, &meta);
+    let logs: Vec<String> = Option::from(meta.log_messages.clone()).unwrap_or_default();
+    let status = match &meta.err {
+        None => TradeStatus::Landed,
+        Some(err) => TradeStatus::Failed(classify_failure(err, &logs)),
+    };
+    // A failed transaction only changed the balance of its fee payer
+    let realized_profit = get_realized_profit(&meta, &account_keys, &pending_trade.payer, &pending_trade.token_in);
+    Ok(TradeResult {
+        signature: signature.to_string(),
+        strategy: pending_trade.strategy.clone(),
+        status,
+        slot: Some(transaction.slot),
+        token_in: pending_trade.token_in.clone(),
+        expected_profit: pending_trade.expected_profit,
+        realized_profit,
+        realized_profit_usd: get_usd_value(&pending_trade.token_in, realized_profit as f64),
+        fee_lamports: meta.fee,
+        confirmation_ms: pending_trade.sent_at.elapsed().as_millis() as u64,
+    })
+}
+
+fn dropped_trade_result(signature: &Signature, pending_trade: &PendingTrade) -> TradeResult {
+    TradeResult {
+        signature: signature.to_string(),
+        strategy: pending_trade.strategy.clone(),
+        status: TradeStatus::Dropped,
+        slot: None,
+        token_in: pending_trade.token_in.clone(),
+        expected_profit: pending_trade.expected_profit,
+        realized_profit: 0,
+        realized_profit_usd: None,
+        fee_lamports: 0,
+        confirmation_ms: pending_trade.sent_at.elapsed().as_millis() as u64,
+    }
+}
+
+// Strategy statistics, storage and result for wait_for_trade_result()
+async fn record_trade_result(signature: Signature, trade_result: TradeResult) {
+    {
+        let mut stats = strategy_stats().write().unwrap();
+        let stats = stats.entry(trade_result.strategy.clone()).or_default();
+        match &trade_result.status {
+            TradeStatus::Landed => stats.landed += 1,
+            TradeStatus::Failed(failure_kind) => {
+                stats.failed += 1;
+                *stats.failures.entry(format!("{:?}", failure_kind)).or_insert(0) += 1;
+            }
+            TradeStatus::Dropped => stats.dropped += 1,
+        }
+        stats.realized_profit_usd += trade_result.realized_profit_usd.unwrap_or(0.0);
+        stats.fees_lamports += trade_result.fee_lamports;
+    }
+    match &trade_result.status {
+        TradeStatus::Landed => info!("💰 Transaction {} landed at slot {:?}, realized profit: {} ({:?} USD), expected: {}", signature, trade_result.slot, trade_result.realized_profit, trade_result.realized_profit_usd, trade_result.expected_profit),
+        status => error!("❌ Transaction {} not executed: {:?}", signature, status),
+    }
+    if let Err(e) = insert_trade_result_collection(TRADE_RESULTS_COLLECTION, trade_result.clone()).await {
+        error!("❌ Trade result {} not stored: {:?}", signature, e);
+    }
+
+    let mut results = trade_results().write().unwrap();
+    results.retain(|_, (_, recorded_at)| recorded_at.elapsed() <= TRADE_RESULT_RETENTION);
+    results.insert(signature, (trade_result, Instant::now()));
+}
+
+// One batched status request per round for all the tracked transactions
+pub async fn poll_pending_trades(rpc_client: &RpcClient) -> Result<usize> {
+    let pending: Vec<(Signature, PendingTrade)> = pending_trades().read().unwrap().iter().map(|(signature, pending_trade)| (*signature, pending_trade.clone())).collect();
+    if pending.is_empty() {
+        return Ok(0);
+    }
+    let block_height = rpc_client.get_block_height_with_commitment(CommitmentConfig::confirmed()).await?;
+
+    let mut counter_resolved = 0;
+    for chunk in pending.chunks(MAX_SIGNATURES_PER_REQUEST) {
+        let signatures: Vec<Signature> = chunk.iter().map(|(signature, _)| *signature).collect();
+        let statuses = rpc_client.get_signature_statuses(&signatures).await?.value;
+        for ((signature, pending_trade), status) in chunk.iter().zip(statuses) {
+            let trade_result = match status {
+                Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => {
+                    match get_trade_result(rpc_client, signature, pending_trade).await {
+                        Ok(trade_result) => trade_result,
+                        Err(e) => {
+                            // Not available yet on this RPC, next round
+                            error!("❌ Transaction {} not fetched: {:?}", signature, e);
+                            continue;
+                        }
+                    }
+                }
+                None if block_height > pending_trade.last_valid_block_height => dropped_trade_result(signature, pending_trade),
+                _ => continue,
+            };
+            pending_trades().write().unwrap().remove(signature);
+            record_trade_result(*signature, trade_result).await;
+            counter_resolved += 1;
+        }
+    }
+    Ok(counter_resolved)
+}
+
+pub async fn run_confirmation_service(rpc_url: String, poll_interval: Duration) {
+    info!("🔎 Launch confirmation tracker...");
+    let rpc_client = RpcClient::new(rpc_url);
+    loop {
+        if let Err(e) = poll_pending_trades(&rpc_client).await {
+            error!("❌ Confirmation tracker error: {:?}", e);
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
@@ -4,8 +4,8 @@ use log::info;
 use serde::{Deserialize, Serialize};
 use solana_client::{connection_cache::ConnectionCache, rpc_client::RpcClient, rpc_config::{RpcSendTransactionConfig, RpcSimulateTransactionConfig}, send_and_confirm_transactions_in_parallel::{send_and_confirm_transactions_in_parallel, SendAndConfirmConfig}, tpu_client::{TpuClient, TpuClientConfig}};
 use solana_sdk::{
-    address_lookup_table::{instruction::{create_lookup_table, extend_lookup_table},
-    state::AddressLookupTable, AddressLookupTableAccount}, commitment_config::{CommitmentConfig, CommitmentLevel}, compute_budget::ComputeBudgetInstruction, instruction::Instruction, message::{v0, VersionedMessage}, pubkey::Pubkey, signature::{read_keypair_file, Keypair, Signer}, sysvar::instructions, transaction::VersionedTransaction
+    address_lookup_table::{
+    state::AddressLookupTable, AddressLookupTableAccount}, commitment_config::{CommitmentConfig, CommitmentLevel}, compute_budget::ComputeBudgetInstruction, hash::Hash, instruction::Instruction, packet::PACKET_DATA_SIZE, message::{v0, VersionedMessage}, pubkey::Pubkey, signature::Signature, sysvar::instructions, transaction::VersionedTransaction
 };
 use solana_transaction_status::UiTransactionEncoding;
 use anchor_spl::token::spl_token;
@@ -17,28 +17,57 @@ use spl_associated_token_account::instruction::create_associated_token_account;
 use std::{fs::{File, OpenOptions}, io::{BufReader, Read}, path::Path, sync::Arc, thread::sleep, time::{Duration, Instant}};
 use std::io::{BufWriter, Write};
 
-use crate::{arbitrage::types::SwapPathResult, common::{constants::Env, utils::from_str}, markets::types::DexLabel, transactions::utils::{average, check_tx_status}};
-use super::{meteoradlmm_swap::{construct_meteora_instructions, SwapParametersMeteora}, orca_whirpools_swap::{construct_orca_whirpools_instructions, SwapParametersOrcaWhirpools}, raydium_swap::{construct_raydium_instructions, SwapParametersRaydium}};
+use crate::{arbitrage::types::SwapPathResult, common::{constants::Env, utils::from_str}, markets::types::DexLabel, transactions::utils::average};
+use super::{blockhash::{get_blockhash_for_tx, is_blockhash_expired, BlockhashInfo}, broadcaster::Broadcaster, confirmation::{track_transaction, wait_for_trade_result, PendingTrade, TradeStatus}, compute_units::{plan_compute_units, set_compute_budget_instructions, MAX_COMPUTE_UNITS}, jito::{create_tip_instruction, BundleSender, BundleStatus}, leader_sender::LeaderSender, nonce::{acquire_nonce, cancel_nonce, create_advance_nonce_instruction, release_nonce, ADVANCE_NONCE_COMPUTE_UNITS, NONCE_RESEND_WINDOW_BLOCKS}, lut_manager::{get_lut_cache_path, LutManager, LUT_MARKET_MAX_ACCOUNTS, LUT_MAX_ADDRESSES}, priority_fees::{get_compute_unit_price, get_expected_profit_lamports, PriorityFeePolicy}, meteoradlmm_swap::{construct_meteora_instructions, SwapParametersMeteora}, orca_whirpools_swap::{construct_orca_whirpools_instructions, SwapParametersOrcaWhirpools}, profit_guard::{construct_profit_guard_instructions, get_profit_guard_program}, signer::SignerService, slippage::{get_swap_amounts, SlippagePolicy}, wsol::{construct_unwrap_sol_instruction, construct_wrap_sol_instructions, get_max_wrap_amount, WSOL_MINT}, raydium_swap::{construct_raydium_instructions, SwapParametersRaydium}};
 
-pub async fn create_and_send_swap_transaction(simulate_or_send: SendOrSimulate, chain: ChainType, transaction_infos: SwapPathResult) -> Result<()> {
+// Max wait of the bundle status before giving up (the bundle can still land after)
+const BUNDLE_STATUS_TIMEOUT: Duration = Duration::from_secs(10);
+// Signings of the swap transaction sent by the RPC, a new one only once the previous one is dropped
+const SEND_ATTEMPTS: u32 = 2;
+// The same signed transaction is sent again at this interval until it is resolved or its blockhash expires
+const RESEND_INTERVAL: Duration = Duration::from_secs(2);
+// Max wait of the verdict of the confirmation tracker, the transaction stays tracked after
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub async fn create_and_send_swap_transaction(simulate_or_send: SendOrSimulate, chain: ChainType, signer: SignerService, transaction_infos: SwapPathResult, leader_sender: Option<LeaderSender>, bundle_sender: Option<BundleSender>, broadcaster: Option<Broadcaster>, priority_fee_policy: PriorityFeePolicy, slippage_policy: SlippagePolicy, wrap_sol: bool) -> Result<()> {
     info!("🔄 Create swap transaction.... ");
     
     let env = Env::new();
     let rpc_url = if chain.clone() == ChainType::Mainnet { env.rpc_url_tx.clone() } else { env.devnet_rpc_url };
     let rpc_client: RpcClient = RpcClient::new(rpc_url);
 
-    let payer: Keypair = read_keypair_file(env.payer_keypair_path.clone()).expect("Wallet keypair file not found");
-    info!("💳 Wallet {:#?}", payer.pubkey());
+    info!("💳 Wallet {:#?} (fee payer {:#?})", signer.payer(), signer.fee_payer());
 
     info!("🆔 Create/Send Swap instruction....");
     // Construct Swap instructions
-    let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+    let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS as u32);
     let compute_budget_instruction = vec![InstructionDetails{ instruction: compute_budget_ix, details: "Compute Budget Instruction".to_string(), market: None }];
     let priority_fees_ix = ComputeBudgetInstruction::set_compute_unit_price(0);
     let priority_fees_instruction = vec![InstructionDetails{ instruction: priority_fees_ix, details: "Set priority fees".to_string(), market: None }];
 
 
-    let swaps_construct_instructions: Vec<InstructionDetails> = construct_transaction(transaction_infos).await;
+    // The wrapped amount comes from the native SOL balance, not from a pre-funded wSOL account
+    if wrap_sol && transaction_infos.token_in == WSOL_MINT {
+        let max_wrap_amount = get_max_wrap_amount(&rpc_client, &signer.payer())?;
+        if transaction_infos.amount_in > max_wrap_amount {
+            error!("❌ Amount in {} over the native SOL available to wrap {}, transaction not sent", transaction_infos.amount_in, max_wrap_amount);
+            return Ok(());
+        }
+    }
+
+    let expected_profit_lamports = get_expected_profit_lamports(&transaction_infos.token_in, transaction_infos.result);
+    // Followed by the confirmation tracker once sent, the strategy statistics are per tokens path
+    let (strategy, token_in, expected_profit) = (transaction_infos.tokens_path.clone(), transaction_infos.token_in.clone(), transaction_infos.result);
+    let payer_pubkey = signer.payer();
+    let pending_trade = move |last_valid_block_height: u64| PendingTrade {
+        strategy: strategy.clone(),
+        payer: payer_pubkey,
+        token_in: token_in.clone(),
+        expected_profit,
+        last_valid_block_height,
+        sent_at: Instant::now(),
+    };
+    let swaps_construct_instructions: Vec<InstructionDetails> = construct_transaction(transaction_infos, signer.payer(), &slippage_policy, wrap_sol).await;
     let mut swap_instructions: Vec<InstructionDetails> = vec![compute_budget_instruction, priority_fees_instruction, swaps_construct_instructions].concat();
 
     if swap_instructions.len() == 0 {
@@ -119,15 +148,9 @@ pub async fn create_and_send_swap_transaction(simulate_or_send: SendOrSimulate,
     let mut instructions: Vec<Instruction> = swap_instructions.clone().into_iter().map(|instruc_details| instruc_details.instruction).collect();
 
     let commitment_config = CommitmentConfig::confirmed();
-    let tx = VersionedTransaction::try_new(
-        VersionedMessage::V0(v0::Message::try_compile(
-            &payer.pubkey(),
-            &instructions,
-            &vec_address_lut,
-            rpc_client.get_latest_blockhash_with_commitment(commitment_config).expect("❌ Error in get latest blockhash").0,
-        )?),
-        &[&payer],
-    )?;
+    // Blockhash of the simulation, read from the blockhash service
+    let blockhash_info = get_blockhash_for_tx(&rpc_client)?;
+    let tx = build_swap_transaction(&signer, &instructions, &vec_address_lut, blockhash_info.blockhash)?;
 
     //Simulate
     let config = RpcSimulateTransactionConfig {
@@ -147,115 +170,268 @@ pub async fn create_and_send_swap_transaction(simulate_or_send: SendOrSimulate,
     //     }
     // }
 
-    let result = rpc_client.simulate_transaction_with_config(&tx, config).unwrap().value;
-    let logs_simulation = result.clone().logs.unwrap();
-    let last_logs_simulation = &logs_simulation[logs_simulation.len() - 1];
-    println!("last_logs_simulation: {}", last_logs_simulation);
-    if logs_simulation.len() == 0 {
-        error!("❌ Get out! Simulate Error: {:#?}", result.err);
-        return Ok(())
-    } else {
-        info!("🧾 Simulate Tx Ata/Extend Logs: {:#?}", result.logs);
-    }
-
-    let result_cu: u64 = result.units_consumed.unwrap();
-    let result_cu: u64 = 150000;
-    info!("🔢 Computed Units: {}", result_cu);
-
-    let fees = rpc_client.get_recent_prioritization_fees(&vec![])?;
-    let average_fees = average(fees.iter().map(|iter| iter.prioritization_fee).collect());
-    info!("🔢 Average Prioritization fees price: {}", average_fees);
+    // Without simulation the compute units come from the profiles
+    let units_consumed = match rpc_client.simulate_transaction_with_config(&tx, config) {
+        Ok(response) => {
+            let result = response.value;
+            if result.err.is_some() || result.logs.as_ref().map_or(true, |logs| logs.len() == 0) {
+                error!("❌ Get out! Simulate Error: {:#?}", result.err);
+                info!("🧾 Simulate Tx Logs: {:#?}", result.logs);
+                return Ok(())
+            }
+            info!("🧾 Simulate Tx Logs: {:#?}", result.logs);
+            result.units_consumed
+        }
+        Err(e) => {
+            error!("❌ Swap transaction not simulated: {:?}", e);
+            None
+        }
+    };
+    let result_cu: u64 = plan_compute_units(units_consumed, &swap_instructions);
 
-    let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(result_cu as u32);
-    let priority_fees_ix = ComputeBudgetInstruction::set_compute_unit_price(100);
-    instructions[0] = priority_fees_ix;
-    instructions[1] = compute_budget_ix;
+    // Recent fees on the pools written by the swaps, capped by a share of the expected profit
+    let compute_unit_price = get_compute_unit_price(&rpc_client, &instructions, result_cu, expected_profit_lamports, &priority_fee_policy).unwrap_or_else(|e| {
+        error!("❌ Priority fees not estimated, min price used: {:?}", e);
+        priority_fee_policy.min_compute_unit_price
+    });
+    set_compute_budget_instructions(&mut instructions, result_cu as u32, compute_unit_price);
 
 
     //Send transaction
     if simulate_or_send == SendOrSimulate::Send {
-        let transaction_config: RpcSendTransactionConfig = RpcSendTransactionConfig {
-            skip_preflight: true,
-            //Confirmed give more accurate result: https://www.helius.dev/blog/how-to-land-transactions-on-solana#blockhash
-            preflight_commitment: Some(CommitmentLevel::Confirmed),
-            encoding: Some(UiTransactionEncoding::Base58),
-            max_retries: Some(0),
-            min_context_slot: None,
-
+        // The simulated message with the final compute budget, signed again with a fresh blockhash or a durable nonce
+        let blockhash_info = get_blockhash_for_tx(&rpc_client)?;
+        // With a free nonce account the signed transaction stays valid until its nonce is advanced,
+        // it is sent during NONCE_RESEND_WINDOW_BLOCKS only
+        let nonce = acquire_nonce(&rpc_client, &signer);
+        let (recent_blockhash, blockhash_info) = match nonce {
+            Some((nonce_address, nonce_hash)) => {
+                instructions.insert(0, create_advance_nonce_instruction(&nonce_address, &signer.payer()));
+                set_compute_budget_instructions(&mut instructions, (result_cu + ADVANCE_NONCE_COMPUTE_UNITS) as u32, compute_unit_price);
+                info!("🔐 Swap transaction with the durable nonce {} of {}", nonce_hash, nonce_address);
+                let last_valid_block_height = blockhash_info.block_height + NONCE_RESEND_WINDOW_BLOCKS;
+                (nonce_hash, BlockhashInfo { last_valid_block_height, ..blockhash_info })
+            }
+            None => {
+                if is_blockhash_expired(&blockhash_info) {
+                    error!("❌ Blockhash {} expired (last valid block height {}), swap transaction not sent", blockhash_info.blockhash, blockhash_info.last_valid_block_height);
+                    return Ok(())
+                }
+                (blockhash_info.blockhash, blockhash_info)
+            }
         };
- 
-        let new_payer: Keypair = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
-        let txn: Transaction = Transaction::new_signed_with_payer(
-            &instructions,
-            Some(&new_payer.pubkey()),
-            &vec![&new_payer],
-            rpc_client.get_latest_blockhash_with_commitment(commitment_config).expect("❌ Error in get latest blockhash").0,
-        );
-        println!("Rpc http address: {}", rpc_client.url());
-        // let signature = rpc_client.send_transaction_with_config(
-        //     &txn,
-        //     transaction_config
-        // ).unwrap();
-        
-        let non_blocking_rpc_client = solana_client::nonblocking::rpc_client::RpcClient::new(env.rpc_url_tx.clone());
-        let arc_rpc_client = Arc::new(non_blocking_rpc_client);
-        let connection_cache = ConnectionCache::new_quic("connection_cache_cli_program_quic", 1);
-        let signer: [Arc<dyn Signer>; 1] = [Arc::new(new_payer) as Arc<dyn Signer>];
-
-        let iteration_number = 2;
-        let mut iteration_counter = 0;
-        let transaction_errors = if let ConnectionCache::Quic(cache) = connection_cache {
-            let tpu_client = solana_client::nonblocking::tpu_client::TpuClient::new_with_connection_cache(
-                arc_rpc_client.clone(),
-                &env.wss_rpc_url,
-                TpuClientConfig::default(),
-                cache,
-            )
-            .await?;
-            let error_tx = send_and_confirm_transactions_in_parallel(
-                arc_rpc_client,
-                Some(tpu_client),
-                &[txn.message],
-                &signer,
-                SendAndConfirmConfig {
-                    resign_txs_count: Some(iteration_number),
-                    with_spinner: true,
-                },
-            )
-            .await
-            .map_err(|err| format!("Data writes to account failed: {err}")).unwrap_or_default()
-            .into_iter()
-            .map(|err| format!("Data writes to account failed: {:?}", err))
-            // .flatten()
-            .collect::<String>();
-            info!("❌ Swap transaction is not executed: {:?}", error_tx);
-            iteration_counter += 1;
+        let last_valid_block_height = blockhash_info.last_valid_block_height;
+        let txn = match build_swap_transaction(&signer, &instructions, &vec_address_lut, recent_blockhash) {
+            Ok(txn) => txn,
+            Err(e) => {
+                if let Some((nonce_address, _)) = nonce {
+                    cancel_nonce(&nonce_address);
+                }
+                return Err(e)
+            }
         };
-        // if iteration_counter >= iteration_number {
-        //     error!("❌ Swap transactions sended {} times, and all fails", iteration_counter);
-        // } else {
-        //     info!("✅ Swap transaction is well executed!");
-        // }
+
+        // Bundle with a tip: a failed arbitrage doesn't land and doesn't pay fees
+        if let Some(bundle_sender) = bundle_sender {
+            let mut bundle_instructions = instructions.clone();
+            bundle_instructions.push(create_tip_instruction(&signer.fee_payer(), bundle_sender.tip_lamports));
+            let (bundle_signature, status) = match build_swap_transaction(&signer, &bundle_instructions, &vec_address_lut, recent_blockhash) {
+                Ok(bundle_tx) => {
+                    let bundle_signature = bundle_tx.signatures[0];
+                    let status = match bundle_sender.send_bundle(&[bundle_tx]).await {
+                        Ok(bundle_id) => bundle_sender.wait_for_bundle(&bundle_id, BUNDLE_STATUS_TIMEOUT).await.unwrap_or_else(|e| {
+                            error!("❌ Bundle {} status unknown: {:?}", bundle_id, e);
+                            BundleStatus::Pending
+                        }),
+                        Err(e) => {
+                            error!("❌ Bundle not sent: {:?}", e);
+                            BundleStatus::Invalid
+                        }
+                    };
+                    (Some(bundle_signature), status)
+                }
+                Err(e) => {
+                    error!("❌ Bundle transaction not built: {:?}", e);
+                    (None, BundleStatus::Invalid)
+                }
+            };
+            // A pending bundle can still land, only a rejected one is sent again without tip
+            let fallback = bundle_sender.fallback_to_rpc && matches!(status, BundleStatus::Failed | BundleStatus::Invalid);
+            if !fallback || (nonce.is_none() && is_blockhash_expired(&blockhash_info)) {
+                match bundle_signature {
+                    Some(bundle_signature) => {
+                        track_transaction(bundle_signature, pending_trade(last_valid_block_height));
+                        spawn_nonce_release(&signer, nonce, bundle_signature);
+                    }
+                    None => if let Some((nonce_address, _)) = nonce {
+                        cancel_nonce(&nonce_address);
+                    },
+                }
+                return Ok(())
+            }
+            info!("📦 Bundle not landed, fallback to the normal sending");
+        }
+        // Send directly to the TPU of the current and next leaders (in the window of a preferred leader if any)
+        if let Some(leader_sender) = leader_sender {
+            match leader_sender.send_in_leader_window(&txn).await {
+                Ok(sent) => {
+                    info!("👑 Swap transaction {} sent to {} leader(s)", txn.signatures[0], sent);
+                    track_transaction(txn.signatures[0], pending_trade(last_valid_block_height));
+                }
+                Err(e) => error!("❌ Swap transaction not sent to leaders: {:?}", e),
+            }
+            spawn_nonce_release(&signer, nonce, txn.signatures[0]);
+            return Ok(())
+        }
+        info!("📡 Send swap transaction {} by {}", txn.signatures[0], rpc_client.url());
+        // Resolved in background, the strategy goes on with the next opportunities
+        tokio::spawn(send_until_resolved(chain, rpc_client.url(), signer, txn, instructions, vec_address_lut, blockhash_info, nonce, broadcaster, pending_trade));
     }
     Ok(())
 }
 
-pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send: SendOrSimulate, transaction_infos: SwapPathResult, lut_address: Pubkey, tokens: Vec<Pubkey>) -> Result<()> {
+// Send the same signed transaction again until the confirmation tracker resolves it. A new transaction is signed
+// only once the previous one is reported dropped, so two signed versions of the arbitrage can't both land
+async fn send_until_resolved(chain: ChainType, rpc_url: String, signer: SignerService, txn: VersionedTransaction, instructions: Vec<Instruction>, address_lookup_tables: Vec<AddressLookupTableAccount>, blockhash_info: BlockhashInfo, nonce: Option<(Pubkey, Hash)>, broadcaster: Option<Broadcaster>, pending_trade: impl Fn(u64) -> PendingTrade + Send) {
+    let rpc_client: RpcClient = RpcClient::new(rpc_url);
+    let transaction_config: RpcSendTransactionConfig = RpcSendTransactionConfig {
+        skip_preflight: true,
+        //Confirmed give more accurate result: https://www.helius.dev/blog/how-to-land-transactions-on-solana#blockhash
+        preflight_commitment: Some(CommitmentLevel::Confirmed),
+        encoding: Some(UiTransactionEncoding::Base64),
+        max_retries: Some(0),
+        min_context_slot: None,
+    };
+    // A durable nonce transaction is never signed again, its nonce is advanced once dropped
+    let signings = if nonce.is_some() { 1 } else { SEND_ATTEMPTS };
+    let (mut txn, mut blockhash_info) = (txn, blockhash_info);
+    let mut tx_landed = false;
+    for signing in 1..=signings {
+        if signing > 1 {
+            let new_txn = get_blockhash_for_tx(&rpc_client).and_then(|new_blockhash_info| {
+                let new_txn = build_swap_transaction(&signer, &instructions, &address_lookup_tables, new_blockhash_info.blockhash)?;
+                Ok((new_txn, new_blockhash_info))
+            });
+            match new_txn {
+                Ok((new_txn, new_blockhash_info)) => (txn, blockhash_info) = (new_txn, new_blockhash_info),
+                Err(e) => {
+                    error!("❌ Swap transaction not signed again: {:?}", e);
+                    break;
+                }
+            }
+        }
+        let signature = txn.signatures[0];
+        let mut tracked = false;
+        let trade_result = loop {
+            let expired = is_blockhash_expired(&blockhash_info);
+            if !expired {
+                // Same signed transaction to all the routed endpoints, else to RPC_URL_TX only
+                let sent = match &broadcaster {
+                    Some(broadcaster) => broadcaster.broadcast(&txn).await,
+                    None => rpc_client.send_transaction_with_config(&txn, transaction_config).map_err(|e| e.into()),
+                };
+                match sent {
+                    Ok(_) if !tracked => {
+                        if chain == ChainType::Devnet {
+                            info!("https://explorer.solana.com/tx/{}?cluster=devnet", signature);
+                        } else {
+                            info!("https://explorer.solana.com/tx/{}", signature);
+                        }
+                        track_transaction(signature, pending_trade(blockhash_info.last_valid_block_height));
+                        tracked = true;
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("❌ Swap transaction {} not sent (signing {}/{}): {:?}", signature, signing, signings, e),
+                }
+            } else if !tracked {
+                // Never acknowledged and expired, it can't land
+                break None;
+            }
+            // Once the blockhash is expired, only the verdict of the tracker is awaited
+            let timeout = if expired { CONFIRMATION_TIMEOUT } else { RESEND_INTERVAL };
+            match wait_for_trade_result(&signature, timeout).await {
+                Some(trade_result) => break Some(trade_result),
+                None if expired => break None,
+                None => {}
+            }
+        };
+        match trade_result {
+            // Landed, even reverted, the transaction is not signed again
+            Some(trade_result) if trade_result.status != TradeStatus::Dropped => {
+                tx_landed = true;
+                if let Some(broadcaster) = &broadcaster {
+                    broadcaster.record_landing(&signature, true);
+                }
+                info!("✅ Swap transaction landed: {:?}", trade_result.status);
+                break;
+            }
+            Some(_) => {
+                if let Some(broadcaster) = &broadcaster {
+                    broadcaster.record_landing(&signature, false);
+                }
+                info!("❌ Swap transaction {} dropped (signing {}/{})", signature, signing, signings);
+            }
+            // Unknown status, it could still land: a second signed transaction could execute the arbitrage twice
+            None if tracked => {
+                error!("❌ Swap transaction {} not resolved by the confirmation tracker, not signed again", signature);
+                break;
+            }
+            None => info!("❌ Swap transaction {} expired without being sent (signing {}/{})", signature, signing, signings),
+        }
+    }
+    if let Some((nonce_address, nonce_hash)) = nonce {
+        release_nonce(&rpc_client, &signer, &nonce_address, &nonce_hash, tx_landed);
+    }
+}
+
+// Give back the nonce account once the transaction sent by a bundle or to the leaders is resolved
+fn spawn_nonce_release(signer: &SignerService, nonce: Option<(Pubkey, Hash)>, signature: Signature) {
+    let (nonce_address, nonce_hash) = match nonce {
+        Some(nonce) => nonce,
+        None => return,
+    };
+    let signer = signer.clone();
+    tokio::spawn(async move {
+        let landed = wait_for_trade_result(&signature, CONFIRMATION_TIMEOUT).await
+            .map_or(false, |trade_result| trade_result.status != TradeStatus::Dropped);
+        release_nonce(&RpcClient::new(Env::new().rpc_url_tx), &signer, &nonce_address, &nonce_hash, landed);
+    });
+}
+
+// v0 transaction of the swaps with the lookup tables, the same builder for the simulation, the bundles and the sending
+pub fn build_swap_transaction(signer: &SignerService, instructions: &[Instruction], address_lookup_tables: &[AddressLookupTableAccount], blockhash: Hash) -> Result<VersionedTransaction> {
+    let transaction = VersionedTransaction::try_new(
+        VersionedMessage::V0(v0::Message::try_compile(
+            &signer.fee_payer(),
+            instructions,
+            address_lookup_tables,
+            blockhash,
+        )?),
+        &signer.signers(),
+    )?;
+    let transaction_size = bincode::serialized_size(&transaction)? as usize;
+    if transaction_size > PACKET_DATA_SIZE {
+        return Err(anyhow::format_err!("Swap transaction too large: {} bytes (max {}), are the markets in the LUTs?", transaction_size, PACKET_DATA_SIZE));
+    }
+    Ok(transaction)
+}
+
+pub async fn create_ata_extendlut_transaction(chain: ChainType, signer: SignerService, simulate_or_send: SendOrSimulate, transaction_infos: SwapPathResult, lut_address: Pubkey, tokens: Vec<Pubkey>) -> Result<()> {
     info!("🔄 Create ATA/Extend LUT transaction.... ");
     
     let env = Env::new();
     let rpc_url = if chain.clone() == ChainType::Mainnet { &env.rpc_url_tx } else { &env.devnet_rpc_url };
     let rpc_client: RpcClient = RpcClient::new(rpc_url);
 
-    let payer: Keypair = read_keypair_file(env.payer_keypair_path.clone()).expect("Wallet keypair file not found");
-    info!("💳 Wallet {:#?}", payer.pubkey());
+    let payer = signer.payer();
+    info!("💳 Wallet {:#?} (fee payer {:#?})", payer, signer.fee_payer());
 
     let mut vec_pda_instructions: Vec<Instruction> = Vec::new();
 
     //Create Pda/Ata accounts
     for token in tokens {
         let pda_user_token = get_associated_token_address(
-            &payer.pubkey(),
+            &payer,
             &token,
         );
         match rpc_client.get_account(&pda_user_token) {
@@ -265,8 +441,8 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
             Err(error) => {
                 info!("👷‍♂️ PDA creation...");
                 let create_pda_instruction = create_associated_token_account(
-                    &payer.pubkey(),
-                    &payer.pubkey(),
+                    &payer,
+                    &payer,
                     &token,
                     &spl_token::id()
                 );
@@ -275,72 +451,39 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
         }
     }
 
-    // Create the extend LUT instructions
-    let mut swap_instructions: Vec<InstructionDetails> = construct_transaction(transaction_infos).await;
-    // println!("swap_instructions len: {:?}", swap_instructions.len());
-    // println!("swap_instructions: {:?}", swap_instructions);
-    //Check if lookup table is already exist
-    for (i, instruction) in swap_instructions.clone().iter().enumerate() {
-        let market_addr = instruction.market.as_ref().unwrap().address;
-        let (lut_exist, lut_address) = get_lut_address_for_market(market_addr, false).unwrap();
-        if lut_exist == true {
-            info!("🟢 Lookup already exist for {} !", market_addr);
-            // println!("i: {}", i);
-            // println!("swap_instructions[{}]: {:?}", i, swap_instructions[i]);
-            swap_instructions.remove(0);
-            continue;
-        } else {  
-            info!("👷‍♂️ Extend lookup added for: {}", market_addr);
-            // println!("i: {}", i);
-            // println!("swap_instructions[{}]: {:?}", i, swap_instructions[i]);
+    // Only the swaps have accounts to put in the LUT, not the profit guard instructions
+    let swap_instructions: Vec<InstructionDetails> = construct_transaction(transaction_infos, payer, &SlippagePolicy::default(), false).await.into_iter().filter(|instruction| instruction.market.is_some()).collect();
+    if simulate_or_send == SendOrSimulate::Send {
+        // The manager extends `lut_address` while it has room, else rotates to another table, one confirmed transaction per extend
+        let mut lut_manager = LutManager::new(chain.clone(), signer.clone(), false)?;
+        lut_manager.verify()?;
+        lut_manager.add_instructions_markets(&swap_instructions, Some(lut_address))?;
+    } else {
+        for instruction in swap_instructions.iter() {
+            let market_addr = instruction.market.as_ref().unwrap().address;
+            if !get_lut_address_for_market(market_addr, false)?.0 {
+                info!("👷‍♂️ Extend lookup needed for: {}", market_addr);
+            }
         }
     }
-    if swap_instructions.len() == 0 && vec_pda_instructions.len() == 0 {
-        info!("➡️ No ATA/Extend lookup transaction needed");
+    if vec_pda_instructions.len() == 0 {
+        info!("➡️ No ATA transaction needed");
         return Ok(())   
     }
-    let mut vec_details_extend_instructions: Vec<InstructionDetails> = Vec::new();
-    // println!("AFTER");
-    // println!("swap_instructions: {:?}", swap_instructions);
-    
-    for instr in swap_instructions.clone() {
-        if instr.instruction.accounts.len() == 0 {
-            continue;
-        }
-        if instr.clone().market.is_none() {
-            error!("❌ Instruction market is None");
-            return Ok(());
-        }
-        let accounts: Vec<Pubkey> = instr.instruction.accounts.iter().map(|account| account.pubkey).collect();
-        // println!("Accounts: {:?}", accounts);
-
-        let extend_instruction = extend_lookup_table(
-            lut_address, 
-            payer.pubkey(), 
-            Some(payer.pubkey()), 
-            accounts
-        );
-        vec_details_extend_instructions.push(InstructionDetails{instruction: extend_instruction, details: "Extend Instruction".to_string(), market: instr.clone().market});
-        
-        println!("Extend LUT instruction for market: {:?}", instr.clone().market);
-        //Break because 2 extends lookup instruction not fit in 1 tx
-        break;
-    }
 
     let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
     let priority_fees_ix = ComputeBudgetInstruction::set_compute_unit_price(0);
     let compute_budget_instruction = vec![priority_fees_ix, compute_budget_ix];
 
-    let vec_extend_instructions: Vec<Instruction> = vec_details_extend_instructions.clone().into_iter().map(|instruc_details| instruc_details.instruction).collect();
-
-    let mut vec_all_instructions: Vec<Instruction> = vec![compute_budget_instruction.clone(), vec_pda_instructions.clone(), vec_extend_instructions].concat();
+    let mut vec_all_instructions: Vec<Instruction> = vec![compute_budget_instruction.clone(), vec_pda_instructions.clone()].concat();
 
     let commitment_config = CommitmentConfig::confirmed();
+    let blockhash_info = get_blockhash_for_tx(&rpc_client)?;
     let txn_simulate: Transaction = Transaction::new_signed_with_payer(
         &vec_all_instructions,
-        Some(&payer.pubkey()),
-        &vec![&payer],
-        rpc_client.get_latest_blockhash_with_commitment(commitment_config).expect("❌ Error in get latest blockhash").0,
+        Some(&signer.fee_payer()),
+        &signer.signers(),
+        blockhash_info.blockhash,
     );
     // println!("Tx size: {:?}", txn);
 
@@ -373,6 +516,10 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
 
     //Send transaction
     if simulate_or_send == SendOrSimulate::Send {
+        if is_blockhash_expired(&blockhash_info) {
+            error!("❌ Blockhash {} expired (last valid block height {}), ATA/Extend LUT transaction not sent", blockhash_info.blockhash, blockhash_info.last_valid_block_height);
+            return Ok(())
+        }
         let transaction_config: RpcSendTransactionConfig = RpcSendTransactionConfig {
             skip_preflight: false,
             //Confirmed give more accurate result: https://www.helius.dev/blog/how-to-land-transactions-on-solana#blockhash
@@ -383,12 +530,11 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
 
         };
  
-        let new_payer: Keypair = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
         let txn: Transaction = Transaction::new_signed_with_payer(
             &vec_all_instructions,
-            Some(&new_payer.pubkey()),
-            &vec![&new_payer],
-            rpc_client.get_latest_blockhash_with_commitment(commitment_config).expect("❌ Error in get latest blockhash").0,
+            Some(&signer.fee_payer()),
+            &signer.signers(),
+            blockhash_info.blockhash,
         );
         println!("Rpc http address: {}", rpc_client.url());
         // let signature = rpc_client.send_transaction_with_config(
@@ -399,7 +545,6 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
         let non_blocking_rpc_client = solana_client::nonblocking::rpc_client::RpcClient::new(env.rpc_url_tx.clone());
         let arc_rpc_client = Arc::new(non_blocking_rpc_client);
         let connection_cache = ConnectionCache::new_quic("connection_cache_cli_program_quic", 1);
-        let signer: [Arc<dyn Signer>; 1] = [Arc::new(new_payer) as Arc<dyn Signer>];
 
         let iteration_number = 5;
         let mut iteration_counter = 0;
@@ -415,7 +560,7 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
                 arc_rpc_client,
                 Some(tpu_client),
                 &[txn.message],
-                &signer,
+                &signer.signers(),
                 SendAndConfirmConfig {
                     resign_txs_count: Some(iteration_number),
                     with_spinner: true,
@@ -433,9 +578,6 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
         if iteration_counter >= iteration_number {
             error!("❌ Ata/Extend transaction sended {} times, and all fails", iteration_counter);
         } else {
-            for details_instruction in vec_details_extend_instructions {
-                let _ = write_lut_for_market(details_instruction.market.unwrap().address, lut_address, false);
-            }
             info!("✅ Ata/Extend transaction is well executed");
         }
 
@@ -459,22 +601,25 @@ pub async fn create_ata_extendlut_transaction(chain: ChainType, simulate_or_send
     
     Ok(())
 }
-pub async fn construct_transaction(transaction_infos: SwapPathResult) -> Vec<InstructionDetails> {
+// With `wrap_sol` and a cycle in SOL, the amount in is wrapped before the first hop and the wSOL account closed after the last one
+pub async fn construct_transaction(transaction_infos: SwapPathResult, payer: Pubkey, slippage_policy: &SlippagePolicy, wrap_sol: bool) -> Vec<InstructionDetails> {
     
     let mut swap_instructions: Vec<InstructionDetails> = Vec::new();
+    let swap_amounts = get_swap_amounts(&transaction_infos, slippage_policy);
     
     for (i, route_sim) in transaction_infos.route_simulations.clone().iter().enumerate() {
         match route_sim.dex_label {
             DexLabel::METEORA => {
                 let swap_params: SwapParametersMeteora = SwapParametersMeteora{
                     lb_pair: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
-                    amount_in: transaction_infos.route_simulations[i].amount_in,
+                    amount_in: swap_amounts[i].0,
                     swap_for_y: transaction_infos.route_simulations[i].token_0to1,
                     input_token: from_str(transaction_infos.route_simulations[i].token_in.as_str()).unwrap(),
                     output_token: from_str(transaction_infos.route_simulations[i].token_out.as_str()).unwrap(),
-                    minimum_amount_out: transaction_infos.route_simulations[i].estimated_amount_out.parse().unwrap()
+                    minimum_amount_out: swap_amounts[i].1,
+                    min_context_slot: transaction_infos.slot
                 };
-                let result = construct_meteora_instructions(swap_params.clone()).await;
+                let result = construct_meteora_instructions(swap_params.clone(), payer).await;
                 if result.len() == 0 {
                     let empty_array: Vec<InstructionDetails> = Vec::new();
                     error!("Error in Meteora Instruction");
@@ -489,11 +634,12 @@ pub async fn construct_transaction(transaction_infos: SwapPathResult) -> Vec<Ins
                     pool: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
                     input_token_mint: from_str(route_sim.token_in.as_str()).unwrap(),
                     output_token_mint: from_str(route_sim.token_out.as_str()).unwrap(),
-                    amount_in: transaction_infos.route_simulations[i].amount_in,
+                    amount_in: swap_amounts[i].0,
                     swap_for_y: transaction_infos.route_simulations[i].token_0to1,
-                    min_amount_out: transaction_infos.route_simulations[i].estimated_amount_out.parse().unwrap()
+                    min_amount_out: swap_amounts[i].1,
+                    min_context_slot: transaction_infos.slot
                 };
-                let result = construct_raydium_instructions(swap_params);
+                let result = construct_raydium_instructions(swap_params, payer);
                 if result.len() == 0 {
                     let empty_array: Vec<InstructionDetails> = Vec::new();
                     error!("Error in Raydium Instruction");
@@ -511,10 +657,11 @@ pub async fn construct_transaction(transaction_infos: SwapPathResult) -> Vec<Ins
                     whirpools: from_str(transaction_infos.route_simulations[i].pool_address.as_str()).unwrap(),
                     input_token: from_str(route_sim.token_in.as_str()).unwrap(),
                     output_token: from_str(route_sim.token_out.as_str()).unwrap(),
-                    amount_in: transaction_infos.route_simulations[i].amount_in,
-                    minimum_amount_out: transaction_infos.route_simulations[i].estimated_amount_out.parse().unwrap()
+                    amount_in: swap_amounts[i].0,
+                    minimum_amount_out: swap_amounts[i].1,
+                    min_context_slot: transaction_infos.slot
                 };
-                let result = construct_orca_whirpools_instructions(swap_params).await;
+                let result = construct_orca_whirpools_instructions(swap_params, payer).await;
                 // Return len 0 to handle error case in swap
                 if result.len() == 0 {
                     let empty_array: Vec<InstructionDetails> = Vec::new();
@@ -530,56 +677,41 @@ pub async fn construct_transaction(transaction_infos: SwapPathResult) -> Vec<Ins
             }
         }
     }
-    return swap_instructions;
-}
-
-pub async fn create_lut(chain: ChainType) -> Result<()> {
-    info!("🆔 Create/Send LUT transaction....");
-    let env = Env::new();
-    let rpc_url = if chain == ChainType::Mainnet { env.rpc_url } else { env.devnet_rpc_url };
-    let rpc_client: RpcClient = RpcClient::new(rpc_url);
-    let payer: Keypair = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
-
-    //Create Address Lookup Table (LUT acronym)
-    let slot = rpc_client.get_slot_with_commitment(CommitmentConfig::finalized()).expect("Error in get slot");
-    let (create_lut_instruction, lut_address) = create_lookup_table(
-        payer.pubkey(),
-        payer.pubkey(),
-        slot - 200
-    );
-
-    let txn_lut = Transaction::new_signed_with_payer(
-        &vec![create_lut_instruction.clone()],
-        Some(&payer.pubkey()),
-        &vec![&payer],
-        rpc_client.get_latest_blockhash().expect("Error in get latest blockhash"),
-    );
-    let transaction_config: RpcSendTransactionConfig = RpcSendTransactionConfig {
-        skip_preflight: false,
-        .. RpcSendTransactionConfig::default()
-    };
+    if swap_instructions.len() == 0 {
+        return swap_instructions;
+    }
 
-    let signature = rpc_client.send_transaction_with_config(
-        &txn_lut,
-        transaction_config
-    ).unwrap();
+    let wrap_amount = if wrap_sol && transaction_infos.token_in == WSOL_MINT { Some(transaction_infos.amount_in) } else { None };
+    let guard = get_profit_guard_program().map(|program_id| (program_id, slippage_policy.guard_min_profit()));
+    return wrap_swap_instructions(swap_instructions, payer, from_str(transaction_infos.token_in.as_str()).unwrap(), guard, wrap_amount);
+}
 
-    if chain == ChainType::Devnet {
-        info!("https://explorer.solana.com/tx/{}?cluster=devnet", signature);
-    } else {
-        info!("https://explorer.solana.com/tx/{}", signature);
+// Whole cycle profitability checked on-chain when the profit guard `(program, min_profit)` is deployed, else the swaps
+// only check their own minimum_amount_out. With `wrap_amount` the native SOL is wrapped before the start balance
+// is recorded and unwrapped after the profit is checked
+pub fn wrap_swap_instructions(swap_instructions: Vec<InstructionDetails>, payer: Pubkey, token_in: Pubkey, guard: Option<(Pubkey, u64)>, wrap_amount: Option<u64>) -> Vec<InstructionDetails> {
+    let mut start_instructions: Vec<InstructionDetails> = Vec::new();
+    let mut end_instructions: Vec<InstructionDetails> = Vec::new();
+    if let Some(wrap_amount) = wrap_amount {
+        start_instructions.extend(construct_wrap_sol_instructions(payer, wrap_amount));
     }
-    let commitment_config = CommitmentConfig::confirmed();
-    let tx_confirmed = check_tx_status(commitment_config, chain, signature).await?;
-    if tx_confirmed {
-        info!("✅ Address LUT is well created");
-        info!("🧾 Address LUT {:#?}", lut_address);
-    } else {
-        info!("❌ Address LUT is not created");
-        info!("🧾 Address LUT {:#?}", lut_address);
+    if let Some((program_id, min_profit)) = guard {
+        let (record_start_balance, check_profit) = construct_profit_guard_instructions(program_id, payer, token_in, min_profit);
+        start_instructions.push(record_start_balance);
+        end_instructions.push(check_profit);
+    }
+    if wrap_amount.is_some() {
+        end_instructions.push(construct_unwrap_sol_instruction(payer));
     }
+    vec![start_instructions, swap_instructions, end_instructions].concat()
+}
 
-    Ok(())
+pub async fn create_lut(chain: ChainType, signer: SignerService) -> Result<Pubkey> {
+    info!("🆔 Create/Send LUT transaction....");
+    let mut lut_manager = LutManager::new(chain, signer, false)?;
+    let lut_address = lut_manager.create_table()?;
+    info!("🧾 Address LUT {:#?}", lut_address);
+    Ok(lut_address)
 }
 
 pub async fn is_available_lut(chain: ChainType, lut_address: Pubkey) -> Result<bool> {
@@ -587,26 +719,16 @@ pub async fn is_available_lut(chain: ChainType, lut_address: Pubkey) -> Result<b
     let env = Env::new();
     let rpc_url = if chain == ChainType::Mainnet { env.rpc_url } else { env.devnet_rpc_url };
     let rpc_client: RpcClient = RpcClient::new(rpc_url);
-    let payer: Keypair = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
 
     let raw_lut_account = rpc_client.get_account(&lut_address)?;
     let address_lookup_table = AddressLookupTable::deserialize(&raw_lut_account.data)?;
     
     let lut_length = address_lookup_table.addresses.len();
-    if lut_length < 210 {
-        return Ok(true)
-    } else {
-        return Ok(false)
-    }
+    Ok(lut_length + LUT_MARKET_MAX_ACCOUNTS <= LUT_MAX_ADDRESSES)
 }
 
 pub fn get_lut_address_for_market(market: Pubkey, is_test: bool) -> Result<(bool, Option<Pubkey>)> {
-    let mut path = "";
-    if is_test {
-        path = "src/transactions/cache/lut_addresses_test.json";
-    } else {
-        path = "src/transactions/cache/lut_addresses.json";
-    }
+    let path = get_lut_cache_path(is_test);
     let file_read = OpenOptions::new().read(true).open(path)?;
     let mut lut_file: VecLUTFile = serde_json::from_reader(&file_read).unwrap();
     let lut_address = lut_file.value.iter().find(|iteration| &from_str(iteration.market.as_str()).unwrap() == &market);
@@ -621,12 +743,7 @@ pub fn get_lut_address_for_market(market: Pubkey, is_test: bool) -> Result<(bool
 }
 
 pub fn write_lut_for_market(market:Pubkey, lut_address: Pubkey, is_test: bool) -> Result<()> {
-    let mut path = "";
-    if is_test {
-        path = "src/transactions/cache/lut_addresses_test.json";
-    } else {
-        path = "src/transactions/cache/lut_addresses.json";
-    }
+    let path = get_lut_cache_path(is_test);
     let file_exist = Path::new(path).is_file();
     if !file_exist {
         File::create(path)?;
@@ -0,0 +1,120 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use log::{error, info};
+use serde_json::{json, Value};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::VersionedTransaction;
+
+// Jito mainnet tip accounts, one is picked for each bundle
+pub const JITO_TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKc5wPdSSdeBnizKZ6jT",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BundleStatus {
+    Pending,
+    Landed(u64),
+    // Simulation failed or not accepted by the leader, nothing paid
+    Failed,
+    // Unknown by the block engine (expired or never received)
+    Invalid,
+}
+
+pub fn get_tip_account() -> Pubkey {
+    let index = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.subsec_nanos() as usize) % JITO_TIP_ACCOUNTS.len();
+    Pubkey::from_str(JITO_TIP_ACCOUNTS[index]).unwrap()
+}
+
+// Last instruction of the arbitrage transaction: the tip is paid only if all the swaps succeed
+pub fn create_tip_instruction(payer: &Pubkey, tip_lamports: u64) -> Instruction {
+    system_instruction::transfer(payer, &get_tip_account(), tip_lamports)
+}
+
+// Submit bundles to a block engine with the JSON-RPC API
+#[derive(Debug, Clone)]
+pub struct BundleSender {
+    pub block_engine_url: String,
+    pub tip_lamports: u64,
+    // Send the transaction without tip by the normal path when the bundle fails
+    pub fallback_to_rpc: bool,
+    client: reqwest::Client,
+}
+
+impl BundleSender {
+    pub fn new(block_engine_url: String, tip_lamports: u64, fallback_to_rpc: bool) -> Self {
+        BundleSender {
+            block_engine_url: block_engine_url.trim_end_matches('/').to_string(),
+            tip_lamports,
+            fallback_to_rpc,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn request(&self, endpoint: &str, method: &str, params: Value) -> Result<Value> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response: Value = self.client
+            .post(format!("{}/api/v1/{}", self.block_engine_url, endpoint))
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::format_err!("Block engine {} error: {}", method, error));
+        }
+        response.get("result").cloned().ok_or(anyhow::format_err!("Block engine {} without result", method))
+    }
+
+    // Return the bundle id
+    pub async fn send_bundle(&self, transactions: &[VersionedTransaction]) -> Result<String> {
+        let encoded_transactions = transactions.iter()
+            .map(|transaction| Ok(STANDARD.encode(bincode::serialize(transaction)?)))
+            .collect::<Result<Vec<String>>>()?;
+        let result = self.request("bundles", "sendBundle", json!([encoded_transactions, { "encoding": "base64" }])).await?;
+        let bundle_id = result.as_str().ok_or(anyhow::format_err!("Bad bundle id: {}", result))?.to_string();
+        info!("📦 Bundle {} sent to {}", bundle_id, self.block_engine_url);
+        Ok(bundle_id)
+    }
+
+    pub async fn get_bundle_status(&self, bundle_id: &String) -> Result<BundleStatus> {
+        let result = self.request("getInflightBundleStatuses", "getInflightBundleStatuses", json!([[bundle_id]])).await?;
+        let status = match result["value"].as_array().and_then(|statuses| statuses.first()) {
+            Some(status) => status,
+            None => return Ok(BundleStatus::Invalid),
+        };
+        match status["status"].as_str() {
+            Some("Pending") => Ok(BundleStatus::Pending),
+            Some("Landed") => Ok(BundleStatus::Landed(status["landed_slot"].as_u64().unwrap_or_default())),
+            Some("Failed") => Ok(BundleStatus::Failed),
+            _ => Ok(BundleStatus::Invalid),
+        }
+    }
+
+    // Poll the status until the bundle is not pending anymore, Pending if `timeout` is reached
+    pub async fn wait_for_bundle(&self, bundle_id: &String, timeout: Duration) -> Result<BundleStatus> {
+        let start = Instant::now();
+        loop {
+            let status = self.get_bundle_status(bundle_id).await?;
+            if status != BundleStatus::Pending || start.elapsed() >= timeout {
+                match &status {
+                    BundleStatus::Landed(slot) => info!("📦 Bundle {} landed at slot {}", bundle_id, slot),
+                    status => error!("❌ Bundle {} not landed: {:?}", bundle_id, status),
+                }
+                return Ok(status);
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{error, info};
+use solana_client::connection_cache::ConnectionCache;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_connection_cache::client_connection::ClientConnection;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+
+use super::blockhash::get_latest_blockhash_info;
+
+// Number of upcoming slots of the schedule kept in memory
+const LEADERS_LOOKAHEAD_SLOTS: u64 = 100;
+const SLOT_DURATION_MS: u128 = 400;
+
+#[derive(Debug, Clone)]
+pub struct LeaderTpu {
+    pub leader: Pubkey,
+    pub tpu: Option<SocketAddr>,
+    pub tpu_quic: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LeaderState {
+    pub slot: u64,
+    pub updated_at: Option<Instant>,
+    // Leader of each upcoming slot
+    pub leaders_by_slot: BTreeMap<u64, Pubkey>,
+    pub tpu_by_leader: HashMap<Pubkey, LeaderTpu>,
+}
+
+// Send transactions directly to the TPU of the current and next leaders
+#[derive(Clone)]
+pub struct LeaderSender {
+    rpc_url: Option<String>,
+    connection_cache: Arc<ConnectionCache>,
+    use_quic: bool,
+    state: Arc<RwLock<LeaderState>>,
+    // Slots after the current one whose leaders also receive the transaction
+    pub fanout_slots: u64,
+    // Hold the transactions until one of these validators is the leader (empty: send at once)
+    pub preferred_leaders: HashSet<Pubkey>,
+    // Slots to wait for a preferred leader before sending anyway
+    pub max_wait_slots: u64,
+}
+
+impl LeaderSender {
+    pub fn new(rpc_url: String, fanout_slots: u64, use_quic: bool) -> Self {
+        LeaderSender {
+            rpc_url: Some(rpc_url),
+            connection_cache: Arc::new(Self::connection_cache(use_quic)),
+            use_quic,
+            state: Arc::new(RwLock::new(LeaderState::default())),
+            fanout_slots,
+            preferred_leaders: HashSet::new(),
+            max_wait_slots: 0,
+        }
+    }
+
+    // Sender on a known schedule without RPC, for local clusters and tests
+    pub fn with_schedule(state: LeaderState, fanout_slots: u64, use_quic: bool) -> Self {
+        LeaderSender {
+            rpc_url: None,
+            connection_cache: Arc::new(Self::connection_cache(use_quic)),
+            use_quic,
+            state: Arc::new(RwLock::new(state)),
+            fanout_slots,
+            preferred_leaders: HashSet::new(),
+            max_wait_slots: 0,
+        }
+    }
+
+    pub fn with_preferred_leaders(mut self, preferred_leaders: HashSet<Pubkey>, max_wait_slots: u64) -> Self {
+        self.preferred_leaders = preferred_leaders;
+        self.max_wait_slots = max_wait_slots;
+        self
+    }
+
+    fn connection_cache(use_quic: bool) -> ConnectionCache {
+        if use_quic {
+            ConnectionCache::new_quic("connection_cache_leader_sender_quic", 4)
+        } else {
+            ConnectionCache::with_udp("connection_cache_leader_sender_udp", 4)
+        }
+    }
+
+    // Fetch the current slot, the next leaders and their TPU addresses
+    pub async fn refresh(&self) -> Result<()> {
+        let rpc_url = match &self.rpc_url {
+            Some(rpc_url) => rpc_url.clone(),
+            None => return Ok(()),
+        };
+        let rpc_client = RpcClient::new(rpc_url);
+        let slot = rpc_client.get_slot_with_commitment(CommitmentConfig::processed()).await?;
+        let leaders = rpc_client.get_slot_leaders(slot, LEADERS_LOOKAHEAD_SLOTS).await?;
+        let leaders_by_slot: BTreeMap<u64, Pubkey> = leaders.into_iter().enumerate().map(|(i, leader)| (slot + i as u64, leader)).collect();
+
+        let mut tpu_by_leader: HashMap<Pubkey, LeaderTpu> = HashMap::new();
+        let upcoming_leaders: HashSet<Pubkey> = leaders_by_slot.values().cloned().collect();
+        for node in rpc_client.get_cluster_nodes().await? {
+            let leader = match Pubkey::from_str(node.pubkey.as_str()) {
+                Ok(leader) => leader,
+                Err(_) => continue,
+            };
+            if upcoming_leaders.contains(&leader) {
+                tpu_by_leader.insert(leader, LeaderTpu { leader, tpu: node.tpu, tpu_quic: node.tpu_quic });
+            }
+        }
+
+        let mut state = self.state.write().unwrap();
+        state.slot = slot;
+        state.updated_at = Some(Instant::now());
+        state.leaders_by_slot = leaders_by_slot;
+        state.tpu_by_leader = tpu_by_leader;
+        Ok(())
+    }
+
+    pub async fn run_refresh_service(self, refresh_interval: Duration) {
+        info!("👑 Launch leader schedule service...");
+        loop {
+            if let Err(e) = self.refresh().await {
+                error!("❌ Leader schedule refresh error: {:?}", e);
+            }
+            tokio::time::sleep(refresh_interval).await;
+        }
+    }
+
+    // Slot of the blockhash service if fresher, else the slot of the last refresh plus the elapsed slots
+    pub fn current_slot(&self) -> u64 {
+        let state = self.state.read().unwrap();
+        let estimated_slot = match state.updated_at {
+            Some(updated_at) => state.slot + (updated_at.elapsed().as_millis() / SLOT_DURATION_MS) as u64,
+            None => state.slot,
+        };
+        match get_latest_blockhash_info() {
+            Some(blockhash_info) if blockhash_info.slot > estimated_slot => blockhash_info.slot,
+            _ => estimated_slot,
+        }
+    }
+
+    pub fn current_leader(&self) -> Option<Pubkey> {
+        let slot = self.current_slot();
+        self.state.read().unwrap().leaders_by_slot.get(&slot).cloned()
+    }
+
+    // TPU addresses of the leaders from the current slot to current slot + fanout_slots, without duplicates
+    pub fn get_leaders_tpu(&self) -> Vec<(u64, LeaderTpu)> {
+        let slot = self.current_slot();
+        let state = self.state.read().unwrap();
+        let mut seen_leaders: HashSet<Pubkey> = HashSet::new();
+        let mut leaders_tpu: Vec<(u64, LeaderTpu)> = Vec::new();
+        for (leader_slot, leader) in state.leaders_by_slot.range(slot..=slot + self.fanout_slots) {
+            if !seen_leaders.insert(*leader) {
+                continue;
+            }
+            if let Some(leader_tpu) = state.tpu_by_leader.get(leader) {
+                leaders_tpu.push((*leader_slot, leader_tpu.clone()));
+            }
+        }
+        leaders_tpu
+    }
+
+    // Return the number of leaders which received the transaction
+    pub fn send_transaction(&self, transaction: &VersionedTransaction) -> Result<usize> {
+        let wire_transaction = bincode::serialize(transaction)?;
+        self.send_wire_transaction(&wire_transaction)
+    }
+
+    pub fn send_wire_transaction(&self, wire_transaction: &[u8]) -> Result<usize> {
+        let mut sent = 0;
+        for (leader_slot, leader_tpu) in self.get_leaders_tpu() {
+            let address = if self.use_quic { leader_tpu.tpu_quic } else { leader_tpu.tpu };
+            let address = match address {
+                Some(address) => address,
+                None => continue,
+            };
+            let connection = self.connection_cache.get_connection(&address);
+            match connection.send_data(wire_transaction) {
+                Ok(_) => {
+                    info!("👑 Transaction sent to leader {} of slot {} ({})", leader_tpu.leader, leader_slot, address);
+                    sent += 1;
+                }
+                Err(e) => error!("❌ Transaction not sent to leader {} ({}): {:?}", leader_tpu.leader, address, e),
+            }
+        }
+        if sent == 0 {
+            return Err(anyhow::format_err!("No leader TPU available at slot {}", self.current_slot()));
+        }
+        Ok(sent)
+    }
+
+    // Hold the transaction until one of `preferred_leaders` is the current leader, send anyway after `max_wait_slots`
+    pub async fn send_in_leader_window(&self, transaction: &VersionedTransaction) -> Result<usize> {
+        if self.preferred_leaders.is_empty() {
+            return self.send_transaction(transaction);
+        }
+        let start_slot = self.current_slot();
+        loop {
+            let slot = self.current_slot();
+            let in_window = self.current_leader().map_or(false, |leader| self.preferred_leaders.contains(&leader));
+            if in_window || slot >= start_slot + self.max_wait_slots {
+                if !in_window {
+                    info!("👑 No preferred leader after {} slots, send to the current leaders", self.max_wait_slots);
+                }
+                return self.send_transaction(transaction);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use log::{error, info};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_sdk::address_lookup_table::instruction::{close_lookup_table, create_lookup_table, deactivate_lookup_table, extend_lookup_table};
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::address_lookup_table::{self, AddressLookupTableAccount};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+
+use crate::common::constants::Env;
+use crate::common::utils::from_str;
+
+use super::blockhash::get_blockhash_for_tx;
+use super::create_transaction::{ChainType, InstructionDetails, LUTFile, VecLUTFile};
+use super::signer::SignerService;
+
+pub const LUT_MAX_ADDRESSES: usize = 256;
+// Room kept in a table to add the accounts of one more swap instruction
+pub const LUT_MARKET_MAX_ACCOUNTS: usize = 46;
+// Addresses per extend transaction, kept under the 1232 bytes of a transaction
+pub const EXTEND_BATCH_SIZE: usize = 20;
+// A deactivated table can be closed once its deactivation slot is out of the slot hashes
+const DEACTIVATION_COOLDOWN_SLOTS: u64 = 513;
+// Offset of the authority of the table (after the type, the deactivation slot, the last extended slot and its start index, the option tag)
+pub const LUT_AUTHORITY_OFFSET: usize = 22;
+
+pub fn get_lut_cache_path(is_test: bool) -> &'static str {
+    if is_test {
+        "src/transactions/cache/lut_addresses_test.json"
+    } else {
+        "src/transactions/cache/lut_addresses.json"
+    }
+}
+
+// One extend instruction per EXTEND_BATCH_SIZE addresses, the authority pays the extension
+pub fn get_extend_instructions(lut_address: Pubkey, authority: Pubkey, addresses: &[Pubkey]) -> Vec<Instruction> {
+    addresses.chunks(EXTEND_BATCH_SIZE)
+        .map(|batch| extend_lookup_table(lut_address, authority, Some(authority), batch.to_vec()))
+        .collect()
+}
+
+// Lookup tables of the payer: creation on demand, extension across transactions, rotation when full,
+// cache verified against the chain, deactivation and closing of the unused tables
+pub struct LutManager {
+    rpc_client: RpcClient,
+    // The payer is the authority of the tables
+    signer: SignerService,
+    cache_path: String,
+    // Active tables of the payer with their on-chain addresses
+    tables: HashMap<Pubkey, Vec<Pubkey>>,
+    // Market -> table with the accounts of its swap instruction
+    markets: HashMap<Pubkey, Pubkey>,
+}
+
+impl LutManager {
+    pub fn new(chain: ChainType, signer: SignerService, is_test: bool) -> Result<Self> {
+        let env = Env::new();
+        let rpc_url = if chain == ChainType::Mainnet { env.rpc_url_tx } else { env.devnet_rpc_url };
+        let cache_path = get_lut_cache_path(is_test).to_string();
+
+        let mut markets: HashMap<Pubkey, Pubkey> = HashMap::new();
+        if Path::new(&cache_path).is_file() {
+            let lut_file: VecLUTFile = serde_json::from_str(&fs::read_to_string(&cache_path)?)?;
+            for field in lut_file.value {
+                if let (Ok(market), Ok(lut_address)) = (from_str(field.market.as_str()), from_str(field.lut_address.as_str())) {
+                    markets.insert(market, lut_address);
+                }
+            }
+        }
+        Ok(LutManager { rpc_client: RpcClient::new(rpc_url), signer, cache_path, tables: HashMap::new(), markets })
+    }
+
+    fn send_and_confirm(&self, instructions: &[Instruction]) -> Result<Signature> {
+        let blockhash_info = get_blockhash_for_tx(&self.rpc_client)?;
+        let transaction = Transaction::new_signed_with_payer(instructions, Some(&self.signer.fee_payer()), &self.signer.signers(), blockhash_info.blockhash);
+        let signature = self.rpc_client.send_and_confirm_transaction_with_spinner_and_commitment(&transaction, CommitmentConfig::confirmed())?;
+        Ok(signature)
+    }
+
+    fn write_cache(&self) -> Result<()> {
+        let mut lut_file = VecLUTFile{ value: Vec::new() };
+        for (market, lut_address) in self.markets.iter() {
+            lut_file.value.push(LUTFile{ market: market.to_string(), lut_address: lut_address.to_string() });
+        }
+        let file = File::create(&self.cache_path)?;
+        let mut writer = BufWriter::new(&file);
+        writer.write_all(serde_json::to_string(&lut_file)?.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // All the lookup tables of the payer (active or not) from the chain
+    fn get_payer_tables(&self) -> Result<Vec<(Pubkey, AddressLookupTable<'static>)>> {
+        let accounts = self.rpc_client.get_program_accounts_with_config(
+            &address_lookup_table::program::id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new(LUT_AUTHORITY_OFFSET, MemcmpEncodedBytes::Base58(self.signer.payer().to_string())))]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )?;
+        let mut tables = Vec::new();
+        for (lut_address, account) in accounts {
+            match AddressLookupTable::deserialize(&account.data) {
+                Ok(table) => tables.push((lut_address, AddressLookupTable { meta: table.meta, addresses: table.addresses.into_owned().into() })),
+                Err(e) => error!("❌ LUT {} not decoded: {:?}", lut_address, e),
+            }
+        }
+        Ok(tables)
+    }
+
+    // Load the active tables of the payer and drop the cached markets whose table is missing, deactivated
+    // or doesn't contain the market. Return the number of markets removed from the cache
+    pub fn verify(&mut self) -> Result<usize> {
+        self.tables = self.get_payer_tables()?.into_iter()
+            .filter(|(_, table)| table.meta.deactivation_slot == u64::MAX)
+            .map(|(lut_address, table)| (lut_address, table.addresses.to_vec()))
+            .collect();
+
+        let counter_markets = self.markets.len();
+        let tables = &self.tables;
+        self.markets.retain(|market, lut_address| {
+            let valid = tables.get(lut_address).map_or(false, |addresses| addresses.contains(market));
+            if !valid {
+                error!("❌ Market {} not found in the LUT {}, removed from the cache", market, lut_address);
+            }
+            valid
+        });
+        let counter_removed = counter_markets - self.markets.len();
+        if counter_removed > 0 {
+            self.write_cache()?;
+        }
+        info!("🗂️ {} active LUTs, {} markets verified, {} removed", self.tables.len(), self.markets.len(), counter_removed);
+        Ok(counter_removed)
+    }
+
+    pub fn create_table(&mut self) -> Result<Pubkey> {
+        let slot = self.rpc_client.get_slot_with_commitment(CommitmentConfig::finalized())?;
+        let (create_lut_instruction, lut_address) = create_lookup_table(self.signer.payer(), self.signer.payer(), slot - 1);
+        let signature = self.send_and_confirm(&[create_lut_instruction])?;
+        info!("✅ LUT {} created ({})", lut_address, signature);
+        self.tables.insert(lut_address, Vec::new());
+        Ok(lut_address)
+    }
+
+    // Preferred table if it has room for `needed` addresses, else any active table, else a new one
+    fn get_table_with_space(&mut self, needed: usize, preferred: Option<Pubkey>) -> Result<Pubkey> {
+        let has_space = |addresses: &Vec<Pubkey>| addresses.len() + needed <= LUT_MAX_ADDRESSES;
+        if let Some(lut_address) = preferred {
+            if self.tables.get(&lut_address).map_or(false, has_space) {
+                return Ok(lut_address);
+            }
+        }
+        if let Some((lut_address, _)) = self.tables.iter().find(|(_, addresses)| has_space(addresses)) {
+            return Ok(*lut_address);
+        }
+        info!("🗂️ All LUTs are full, rotate to a new one");
+        self.create_table()
+    }
+
+    // Put the accounts of the market in one table, without the addresses already there
+    pub fn add_market(&mut self, market: Pubkey, accounts: Vec<Pubkey>, preferred: Option<Pubkey>) -> Result<Pubkey> {
+        let mut seen: HashSet<Pubkey> = HashSet::new();
+        let accounts: Vec<Pubkey> = accounts.into_iter().filter(|account| seen.insert(*account)).collect();
+        if accounts.len() > LUT_MAX_ADDRESSES {
+            return Err(anyhow::format_err!("{} accounts for the market {}, more than a LUT", accounts.len(), market));
+        }
+
+        // Tables already holding part of the accounts need less room
+        let missing_in = |addresses: &Vec<Pubkey>| accounts.iter().filter(|account| !addresses.contains(account)).count();
+        let best_table = self.tables.iter()
+            .filter(|(_, addresses)| addresses.len() + missing_in(addresses) <= LUT_MAX_ADDRESSES)
+            .min_by_key(|(_, addresses)| missing_in(addresses))
+            .map(|(lut_address, _)| *lut_address);
+        let lut_address = match best_table {
+            Some(lut_address) if preferred.is_none() || Some(lut_address) == preferred => lut_address,
+            _ => self.get_table_with_space(accounts.len(), preferred)?,
+        };
+
+        let table_addresses = self.tables.get(&lut_address).cloned().unwrap_or_default();
+        let new_addresses: Vec<Pubkey> = accounts.into_iter().filter(|account| !table_addresses.contains(account)).collect();
+        for (batch, extend_instruction) in new_addresses.chunks(EXTEND_BATCH_SIZE).zip(get_extend_instructions(lut_address, self.signer.payer(), &new_addresses)) {
+            let signature = self.send_and_confirm(&[extend_instruction])?;
+            info!("👷‍♂️ LUT {} extended with {} addresses ({})", lut_address, batch.len(), signature);
+            self.tables.entry(lut_address).or_default().extend_from_slice(batch);
+        }
+
+        self.markets.insert(market, lut_address);
+        self.write_cache()?;
+        Ok(lut_address)
+    }
+
+    // Make sure every swap of the instructions has its accounts in a table
+    pub fn add_instructions_markets(&mut self, instructions: &Vec<InstructionDetails>, preferred: Option<Pubkey>) -> Result<()> {
+        for instruction in instructions.iter() {
+            let market = match &instruction.market {
+                Some(market) => market.address,
+                None => continue,
+            };
+            if self.markets.contains_key(&market) {
+                info!("🟢 Lookup already exist for {} !", market);
+                continue;
+            }
+            let accounts: Vec<Pubkey> = instruction.instruction.accounts.iter().map(|account| account.pubkey).collect();
+            self.add_market(market, accounts, preferred)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_lut_address_for_market(&self, market: &Pubkey) -> Option<Pubkey> {
+        self.markets.get(market).cloned()
+    }
+
+    pub fn get_lookup_table_accounts(&self, markets: &Vec<Pubkey>) -> Vec<AddressLookupTableAccount> {
+        let lut_addresses: HashSet<Pubkey> = markets.iter().filter_map(|market| self.markets.get(market).cloned()).collect();
+        lut_addresses.into_iter()
+            .filter_map(|lut_address| self.tables.get(&lut_address).map(|addresses| AddressLookupTableAccount { key: lut_address, addresses: addresses.clone() }))
+            .collect()
+    }
+
+    // Deactivate the tables which contain none of `used_markets`, return the number of deactivated tables
+    pub fn deactivate_unused(&mut self, used_markets: &HashSet<Pubkey>) -> Result<usize> {
+        let used_tables: HashSet<Pubkey> = self.markets.iter()
+            .filter(|(market, _)| used_markets.contains(market))
+            .map(|(_, lut_address)| *lut_address)
+            .collect();
+        let unused_tables: Vec<Pubkey> = self.tables.keys().filter(|lut_address| !used_tables.contains(lut_address)).cloned().collect();
+        for lut_address in unused_tables.iter() {
+            let signature = self.send_and_confirm(&[deactivate_lookup_table(*lut_address, self.signer.payer())])?;
+            info!("🗂️ LUT {} deactivated ({})", lut_address, signature);
+            self.tables.remove(lut_address);
+            self.markets.retain(|_, market_lut| market_lut != lut_address);
+        }
+        if !unused_tables.is_empty() {
+            self.write_cache()?;
+        }
+        Ok(unused_tables.len())
+    }
+
+    // Close the deactivated tables of the payer after the cooldown and get back their rent
+    pub fn close_deactivated(&mut self) -> Result<usize> {
+        let current_slot = self.rpc_client.get_slot_with_commitment(CommitmentConfig::confirmed())?;
+        let mut counter_closed = 0;
+        for (lut_address, table) in self.get_payer_tables()? {
+            let deactivation_slot = table.meta.deactivation_slot;
+            if deactivation_slot == u64::MAX || current_slot <= deactivation_slot + DEACTIVATION_COOLDOWN_SLOTS {
+                continue;
+            }
+            match self.send_and_confirm(&[close_lookup_table(lut_address, self.signer.payer(), self.signer.payer())]) {
+                Ok(signature) => {
+                    info!("🗂️ LUT {} closed, rent reclaimed ({})", lut_address, signature);
+                    counter_closed += 1;
+                }
+                Err(e) => error!("❌ LUT {} not closed: {:?}", lut_address, e),
+            }
+        }
+        Ok(counter_closed)
+    }
+}
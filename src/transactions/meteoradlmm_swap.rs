@@ -20,7 +20,6 @@ use std::mem::size_of;
 
 use log::{info, error};
 use solana_sdk::instruction::Instruction;
-use solana_sdk::signature::read_keypair_file;
 use spl_associated_token_account::instruction::create_associated_token_account;
 
 use crate::common::constants::Env;
@@ -28,6 +27,7 @@ use crate::common::utils::from_str;
 use crate::markets::meteora::AccountData;
 use crate::markets::types::DexLabel;
 use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+use crate::transactions::utils::get_account_with_min_slot;
 
 
 #[derive(Debug, Clone)]
@@ -38,9 +38,10 @@ pub struct SwapParametersMeteora {
     pub input_token: Pubkey,
     pub output_token: Pubkey,
     pub minimum_amount_out: u64,
+    pub min_context_slot: Option<u64>,
 }
 // 
-pub async fn construct_meteora_instructions(params: SwapParametersMeteora) -> Vec<InstructionDetails> {
+pub async fn construct_meteora_instructions(params: SwapParametersMeteora, payer: Pubkey) -> Vec<InstructionDetails> {
     let SwapParametersMeteora {
         amount_in,
         lb_pair,
@@ -48,17 +49,23 @@ pub async fn construct_meteora_instructions(params: SwapParametersMeteora) -> Ve
         input_token,
         output_token,
         minimum_amount_out,
+        min_context_slot,
     } = params;
     // info!("METEORA CRAFT SWAP INSTRUCTION !");
 
     let mut swap_instructions: Vec<InstructionDetails> = Vec::new();
     let env = Env::new();
-    let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
     
     let amm_program = from_str("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo").unwrap();
     
     let rpc_client: RpcClient = RpcClient::new(env.rpc_url);
-    let pool_account: solana_sdk::account::Account = rpc_client.get_account(&lb_pair).unwrap();
+    let pool_account: solana_sdk::account::Account = match get_account_with_min_slot(&rpc_client, &lb_pair, min_context_slot) {
+        Ok(account) => account,
+        Err(e) => {
+            error!("❌ Meteora pool account not fetched: {:?}", e);
+            return swap_instructions
+        }
+    };
     let pool_state = AccountData::try_from_slice(&pool_account.data).unwrap();
     
     // println!("Pool State: {:#?}", pool_state);
@@ -68,7 +75,7 @@ pub async fn construct_meteora_instructions(params: SwapParametersMeteora) -> Ve
 
     //Get PDA
     let pda_user_source = get_associated_token_address(
-        &payer.pubkey(),
+        &payer,
         &input_token,
     );
     match rpc_client.get_account(&pda_user_source) {
@@ -79,7 +86,7 @@ pub async fn construct_meteora_instructions(params: SwapParametersMeteora) -> Ve
     }
 
     let pda_user_destination = get_associated_token_address(
-        &payer.pubkey(),
+        &payer,
         &output_token,
     );
 
@@ -114,7 +121,7 @@ pub async fn construct_meteora_instructions(params: SwapParametersMeteora) -> Ve
         AccountMeta::new(pool_state.oracle, false),
         AccountMeta::new(amm_program, false),
         //user
-        AccountMeta::new_readonly(payer.pubkey(), true),
+        AccountMeta::new_readonly(payer, true),
         //token program
         AccountMeta::new_readonly(spl_token::id(), false),
         AccountMeta::new_readonly(spl_token::id(), false),
@@ -1,5 +1,18 @@
+pub mod blockhash;
+pub mod broadcaster;
+pub mod compute_units;
+pub mod confirmation;
 pub mod create_transaction;
+pub mod jito;
+pub mod leader_sender;
+pub mod lut_manager;
 pub mod meteoradlmm_swap;
+pub mod nonce;
 pub mod orca_whirpools_swap;
+pub mod priority_fees;
+pub mod profit_guard;
 pub mod raydium_swap;
-pub mod utils;
\ No newline at end of file
+pub mod signer;
+pub mod slippage;
+pub mod utils;
+pub mod wsol;
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use anyhow::Result;
+use log::{error, info};
+use solana_client::nonce_utils::{data_from_account, get_account_with_commitment};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::nonce::State;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+
+use super::blockhash::get_blockhash_for_tx;
+use super::signer::SignerService;
+
+// Compute units of advance_nonce_account, added to the planned limit of the swaps
+pub const ADVANCE_NONCE_COMPUTE_UNITS: u64 = 1_000;
+// Blocks during which a nonce transaction is sent again before its nonce is advanced to cancel it (~30s),
+// the opportunity is gone long before
+pub const NONCE_RESEND_WINDOW_BLOCKS: u64 = 75;
+
+#[derive(Debug, Clone)]
+pub struct NonceAccount {
+    pub address: Pubkey,
+    // Durable blockhash of the next transaction
+    pub nonce: Hash,
+    // Taken by an opportunity in flight
+    pub in_use: bool,
+    pub uses: u64,
+    // The nonce of an unlanded transaction couldn't be advanced, retried by the next acquire
+    pub pending_advance: bool,
+}
+
+fn nonce_accounts() -> &'static RwLock<HashMap<Pubkey, NonceAccount>> {
+    static NONCE_ACCOUNTS: OnceLock<RwLock<HashMap<Pubkey, NonceAccount>>> = OnceLock::new();
+    NONCE_ACCOUNTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// Nonce accounts are derived from the payer, no keypair to store
+pub fn get_nonce_address(payer: &Pubkey, index: usize) -> Pubkey {
+    Pubkey::create_with_seed(payer, &format!("mev-nonce-{}", index), &system_program::id()).unwrap()
+}
+
+pub fn fetch_nonce(rpc_client: &RpcClient, address: &Pubkey) -> Result<Hash> {
+    let account = get_account_with_commitment(rpc_client, address, CommitmentConfig::confirmed())?;
+    Ok(data_from_account(&account)?.blockhash())
+}
+
+fn send_and_confirm(rpc_client: &RpcClient, signer: &SignerService, instructions: &[Instruction]) -> Result<()> {
+    let blockhash_info = get_blockhash_for_tx(rpc_client)?;
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&signer.fee_payer()), &signer.signers(), blockhash_info.blockhash);
+    rpc_client.send_and_confirm_transaction_with_spinner_and_commitment(&transaction, CommitmentConfig::confirmed())?;
+    Ok(())
+}
+
+pub fn load_nonce_account(address: Pubkey, nonce: Hash) {
+    nonce_accounts().write().unwrap().insert(address, NonceAccount { address, nonce, in_use: false, uses: 0, pending_advance: false });
+}
+
+pub fn get_nonce_account(address: &Pubkey) -> Option<NonceAccount> {
+    nonce_accounts().read().unwrap().get(address).cloned()
+}
+
+// Load the `count` nonce accounts of the payer, created if missing, with the payer as authority
+pub fn init_nonce_accounts(rpc_client: &RpcClient, signer: &SignerService, count: usize) -> Result<usize> {
+    let payer = signer.payer();
+    let rent = rpc_client.get_minimum_balance_for_rent_exemption(State::size())?;
+    for index in 0..count {
+        let address = get_nonce_address(&payer, index);
+        if rpc_client.get_account_with_commitment(&address, CommitmentConfig::confirmed())?.value.is_none() {
+            let instructions = system_instruction::create_nonce_account_with_seed(
+                &payer,
+                &address,
+                &payer,
+                &format!("mev-nonce-{}", index),
+                &payer,
+                rent,
+            );
+            send_and_confirm(rpc_client, signer, &instructions)?;
+            info!("🔐 Nonce account {} created", address);
+        }
+        load_nonce_account(address, fetch_nonce(rpc_client, &address)?);
+    }
+    info!("🔐 {} nonce accounts loaded", count);
+    Ok(count)
+}
+
+// Free nonce account for one opportunity, None when all are in flight (or none loaded).
+// An account whose advance failed is advanced first, skipped if it fails again
+pub fn acquire_nonce(rpc_client: &RpcClient, signer: &SignerService) -> Option<(Pubkey, Hash)> {
+    let mut skipped: Vec<Pubkey> = Vec::new();
+    loop {
+        let account = {
+            let mut accounts = nonce_accounts().write().unwrap();
+            let account = accounts.values_mut().filter(|account| !account.in_use && !skipped.contains(&account.address)).min_by_key(|account| account.uses)?;
+            account.in_use = true;
+            account.clone()
+        };
+        let nonce = if account.pending_advance {
+            advance_used_nonce(rpc_client, signer, &account.address, &account.nonce)
+        } else {
+            Ok(account.nonce)
+        };
+        let mut accounts = nonce_accounts().write().unwrap();
+        let stored_account = accounts.get_mut(&account.address)?;
+        match nonce {
+            Ok(nonce) => {
+                stored_account.nonce = nonce;
+                stored_account.pending_advance = false;
+                stored_account.uses += 1;
+                return Some((account.address, nonce));
+            }
+            Err(e) => {
+                error!("❌ Nonce account {} still not advanced, skipped: {:?}", account.address, e);
+                stored_account.in_use = false;
+                skipped.push(account.address);
+            }
+        }
+    }
+}
+
+// First instruction of a nonce transaction, the authority (payer) signs it
+pub fn create_advance_nonce_instruction(address: &Pubkey, authority: &Pubkey) -> Instruction {
+    system_instruction::advance_nonce_account(address, authority)
+}
+
+// Current nonce of the account, advanced first if it is still `used_nonce` so the unlanded transaction can't land later
+fn advance_used_nonce(rpc_client: &RpcClient, signer: &SignerService, address: &Pubkey, used_nonce: &Hash) -> Result<Hash> {
+    let nonce = fetch_nonce(rpc_client, address)?;
+    if nonce != *used_nonce {
+        return Ok(nonce);
+    }
+    send_and_confirm(rpc_client, signer, &[create_advance_nonce_instruction(address, &signer.payer())])?;
+    info!("🔐 Nonce {} advanced, the unlanded transaction is cancelled", address);
+    let nonce = fetch_nonce(rpc_client, address)?;
+    if nonce == *used_nonce {
+        return Err(anyhow::format_err!("Nonce {} unchanged after its advance", address));
+    }
+    Ok(nonce)
+}
+
+// Give back the nonce account once its transaction is resolved. If it didn't land the nonce is advanced so the
+// transaction can't land later, on failure the next acquire advances it
+pub fn release_nonce(rpc_client: &RpcClient, signer: &SignerService, address: &Pubkey, used_nonce: &Hash, landed: bool) {
+    let nonce = if landed { fetch_nonce(rpc_client, address) } else { advance_used_nonce(rpc_client, signer, address, used_nonce) };
+    let mut accounts = nonce_accounts().write().unwrap();
+    if let Some(account) = accounts.get_mut(address) {
+        match nonce {
+            Ok(nonce) if nonce != *used_nonce => {
+                account.nonce = nonce;
+                account.pending_advance = false;
+            }
+            Ok(_) => {
+                error!("❌ Nonce account {} not refreshed yet, advanced at its next use", address);
+                account.pending_advance = true;
+            }
+            Err(e) => {
+                error!("❌ Nonce account {} not advanced, retried at its next use: {:?}", address, e);
+                account.pending_advance = true;
+            }
+        }
+        account.in_use = false;
+    }
+}
+
+// Give back a nonce account whose transaction was never sent, its nonce is unchanged
+pub fn cancel_nonce(address: &Pubkey) {
+    if let Some(account) = nonce_accounts().write().unwrap().get_mut(address) {
+        account.in_use = false;
+    }
+}
@@ -24,7 +24,6 @@ use std::str::FromStr;
 
 use log::{error, info};
 use solana_sdk::instruction::Instruction;
-use solana_sdk::signature::read_keypair_file;
 use spl_associated_token_account::instruction::create_associated_token_account;
 
 use crate::common::constants::Env;
@@ -33,6 +32,7 @@ use crate::markets::meteora::AccountData;
 use crate::markets::orca_whirpools::WhirlpoolAccountState;
 use crate::markets::types::DexLabel;
 use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
+use crate::transactions::utils::get_account_with_min_slot;
 
 
 #[derive(Debug, Clone)]
@@ -42,27 +42,34 @@ pub struct SwapParametersOrcaWhirpools {
     pub input_token: Pubkey,
     pub output_token: Pubkey,
     pub minimum_amount_out: u64,
+    pub min_context_slot: Option<u64>,
 }
 // 
-pub async fn construct_orca_whirpools_instructions(params: SwapParametersOrcaWhirpools) -> Vec<InstructionDetails> {
+pub async fn construct_orca_whirpools_instructions(params: SwapParametersOrcaWhirpools, payer: Pubkey) -> Vec<InstructionDetails> {
     let SwapParametersOrcaWhirpools {
         whirpools,
         amount_in,
         input_token,
         output_token,
         minimum_amount_out,
+        min_context_slot,
     } = params;
     // info!("ORCA WHIRPOOLS CRAFT SWAP INSTRUCTION !");
     // println!("Params: {:?}", params);
 
     let mut swap_instructions: Vec<InstructionDetails> = Vec::new();
     let env = Env::new();
-    let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
     
     let amm_program = from_str("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc").unwrap();
     
     let rpc_client: RpcClient = RpcClient::new(env.rpc_url);
-    let pool_account: solana_sdk::account::Account = rpc_client.get_account(&whirpools).unwrap();
+    let pool_account: solana_sdk::account::Account = match get_account_with_min_slot(&rpc_client, &whirpools, min_context_slot) {
+        Ok(account) => account,
+        Err(e) => {
+            error!("❌ Orca Whirpools pool account not fetched: {:?}", e);
+            return swap_instructions
+        }
+    };
     // println!("Params: {:?}", pool_account);
     // println!("Params data length: {:?}", pool_account.data.len());
 
@@ -83,7 +90,7 @@ pub async fn construct_orca_whirpools_instructions(params: SwapParametersOrcaWhi
 
     //Get PDA
     let pda_user_source = get_associated_token_address(
-        &payer.pubkey(),
+        &payer,
         &input_token,
     );
     match rpc_client.get_account(&pda_user_source) {
@@ -94,7 +101,7 @@ pub async fn construct_orca_whirpools_instructions(params: SwapParametersOrcaWhi
     }
 
     let pda_user_destination = get_associated_token_address(
-        &payer.pubkey(),
+        &payer,
         &output_token,
     );
 
@@ -131,7 +138,7 @@ pub async fn construct_orca_whirpools_instructions(params: SwapParametersOrcaWhi
         // TokenProgram
         AccountMeta::new_readonly(spl_token::id(), false),
         //Token Authority / User ? 
-        AccountMeta::new(payer.pubkey(), true),
+        AccountMeta::new(payer, true),
         AccountMeta::new(whirpools, false),
         AccountMeta::new(
             if a_to_b {
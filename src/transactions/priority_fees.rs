@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use log::info;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::common::prices::{get_usd_price, get_usd_value};
+
+const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+// getRecentPrioritizationFees accepts at most 128 accounts
+const MAX_FEE_ACCOUNTS: usize = 128;
+
+// Compute unit price (micro-lamports) of the swap transactions
+#[derive(Debug, Clone)]
+pub struct PriorityFeePolicy {
+    // Percentile (0-100) of the recent fees paid on the writable accounts of the transaction
+    pub percentile: f64,
+    // Max share of the expected profit spent in priority fees
+    pub max_profit_share: f64,
+    pub min_compute_unit_price: u64,
+    pub max_compute_unit_price: u64,
+}
+
+impl Default for PriorityFeePolicy {
+    fn default() -> Self {
+        PriorityFeePolicy {
+            percentile: 75.0,
+            max_profit_share: 0.3,
+            min_compute_unit_price: 100,
+            max_compute_unit_price: 5_000_000,
+        }
+    }
+}
+
+// Writable accounts of the instructions without the signers, the fee markets are per written account
+pub fn get_writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut seen: HashSet<Pubkey> = HashSet::new();
+    instructions.iter()
+        .flat_map(|instruction| instruction.accounts.iter())
+        .filter(|account| account.is_writable && !account.is_signer && seen.insert(account.pubkey))
+        .map(|account| account.pubkey)
+        .take(MAX_FEE_ACCOUNTS)
+        .collect()
+}
+
+pub fn get_percentile(values: &Vec<u64>, percentile: f64) -> u64 {
+    if values.is_empty() {
+        return 0;
+    }
+    let mut sorted_values = values.clone();
+    sorted_values.sort_unstable();
+    let index = ((percentile.clamp(0.0, 100.0) / 100.0) * (sorted_values.len() - 1) as f64).round() as usize;
+    sorted_values[index]
+}
+
+// Percentile of the recent compute unit prices of the slots where the accounts were written
+pub fn estimate_compute_unit_price(rpc_client: &RpcClient, writable_accounts: &Vec<Pubkey>, percentile: f64) -> Result<u64> {
+    let fees = rpc_client.get_recent_prioritization_fees(writable_accounts)?;
+    let fees: Vec<u64> = fees.iter().map(|fee| fee.prioritization_fee).collect();
+    Ok(get_percentile(&fees, percentile))
+}
+
+// Expected profit in lamports, with the USD prices when the base token is not SOL
+pub fn get_expected_profit_lamports(token_in: &String, result: f64) -> Option<u64> {
+    if result <= 0.0 {
+        return Some(0);
+    }
+    if token_in == SOL_MINT {
+        return Some(result as u64);
+    }
+    let profit_usd = get_usd_value(token_in, result)?;
+    let sol_usd = get_usd_price(&SOL_MINT.to_string())?;
+    Some((profit_usd / sol_usd * 1e9) as u64)
+}
+
+// Max compute unit price which keeps the priority fee under max_profit_share of the profit
+pub fn cap_compute_unit_price(compute_unit_price: u64, compute_units: u64, expected_profit_lamports: u64, max_profit_share: f64) -> u64 {
+    if compute_units == 0 {
+        return compute_unit_price;
+    }
+    let max_fee_lamports = expected_profit_lamports as f64 * max_profit_share;
+    let max_compute_unit_price = (max_fee_lamports * 1_000_000.0 / compute_units as f64) as u64;
+    compute_unit_price.min(max_compute_unit_price)
+}
+
+pub fn get_compute_unit_price(rpc_client: &RpcClient, instructions: &[Instruction], compute_units: u64, expected_profit_lamports: Option<u64>, policy: &PriorityFeePolicy) -> Result<u64> {
+    let writable_accounts = get_writable_accounts(instructions);
+    let estimated_price = estimate_compute_unit_price(rpc_client, &writable_accounts, policy.percentile)?;
+    let mut compute_unit_price = estimated_price.clamp(policy.min_compute_unit_price, policy.max_compute_unit_price);
+    // Unknown profit: no cap, the min/max of the policy only
+    if let Some(expected_profit_lamports) = expected_profit_lamports {
+        compute_unit_price = cap_compute_unit_price(compute_unit_price, compute_units, expected_profit_lamports, policy.max_profit_share);
+    }
+    info!("🔢 Compute unit price: {} (p{} of {} writable accounts: {}, expected profit: {:?} lamports)", compute_unit_price, policy.percentile, writable_accounts.len(), estimated_price, expected_profit_lamports);
+    Ok(compute_unit_price)
+}
@@ -0,0 +1,70 @@
+use anchor_spl::associated_token::get_associated_token_address;
+use log::error;
+use solana_program::hash;
+use solana_program::instruction::AccountMeta;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+
+use crate::common::constants::Env;
+use crate::common::utils::from_str;
+use crate::transactions::create_transaction::InstructionDetails;
+
+// Min. gain of the base token required by check_profit, 0 refuses only the losing arbitrages
+pub const PROFIT_GUARD_MIN_PROFIT: u64 = 0;
+
+// Address of the deployed programs/profit_guard (PROFIT_GUARD_PROGRAM), None when it is not set
+pub fn get_profit_guard_program() -> Option<Pubkey> {
+    let program = Env::new().profit_guard_program;
+    if program.is_empty() {
+        return None;
+    }
+    match from_str(program.as_str()) {
+        Ok(program_id) => Some(program_id),
+        Err(e) => {
+            error!("❌ Invalid PROFIT_GUARD_PROGRAM {}, arbitrages without profit guard: {:?}", program, e);
+            None
+        }
+    }
+}
+
+pub fn get_guard_address(program_id: &Pubkey, payer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"profit_guard", payer.as_ref()], program_id).0
+}
+
+fn sighash(name: &str) -> [u8; 8] {
+    let mut sighash = [0u8; 8];
+    sighash.copy_from_slice(&hash::hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    sighash
+}
+
+// (first, last) instructions of the arbitrage: record the base token balance of the payer, then check
+// it increased of at least `min_profit`, else the transaction fails
+pub fn construct_profit_guard_instructions(program_id: Pubkey, payer: Pubkey, base_token: Pubkey, min_profit: u64) -> (InstructionDetails, InstructionDetails) {
+    let guard = get_guard_address(&program_id, &payer);
+    let token_account = get_associated_token_address(&payer, &base_token);
+
+    let record_start_balance = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(guard, false),
+            AccountMeta::new_readonly(token_account, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: sighash("record_start_balance").to_vec(),
+    };
+    let check_profit = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(guard, false),
+            AccountMeta::new_readonly(token_account, false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        data: [sighash("check_profit").to_vec(), min_profit.to_le_bytes().to_vec()].concat(),
+    };
+    (
+        InstructionDetails{ instruction: record_start_balance, details: "Profit guard: record start balance".to_string(), market: None },
+        InstructionDetails{ instruction: check_profit, details: "Profit guard: check profit".to_string(), market: None },
+    )
+}
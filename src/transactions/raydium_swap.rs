@@ -12,7 +12,6 @@ use log::{info, error};
 use raydium_amm::instruction::swap_base_in;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::instruction::Instruction;
-use solana_sdk::signature::read_keypair_file;
 use spl_associated_token_account::instruction::create_associated_token_account;
 
 use crate::common::constants::Env;
@@ -21,7 +20,7 @@ use crate::markets::raydium::AmmInfo;
 use crate::markets::types::DexLabel;
 use crate::transactions::create_transaction::{InstructionDetails, MarketInfos};
 
-use super::utils::get_keys_for_market;
+use super::utils::{get_account_with_min_slot, get_keys_for_market};
 
 #[derive(Debug, Clone)]
 pub struct SwapParametersRaydium {
@@ -30,31 +29,38 @@ pub struct SwapParametersRaydium {
     pub output_token_mint: Pubkey,
     pub amount_in: u64,
     pub swap_for_y: bool,
-    pub min_amount_out: u64
+    pub min_amount_out: u64,
+    pub min_context_slot: Option<u64>
 }
 // Function are imported from Raydium library, you can see here: 
 // https://github.com/raydium-io/raydium-library
-pub fn construct_raydium_instructions(params: SwapParametersRaydium) -> Vec<InstructionDetails> {
+pub fn construct_raydium_instructions(params: SwapParametersRaydium, payer: Pubkey) -> Vec<InstructionDetails> {
     let SwapParametersRaydium {
         pool,
         input_token_mint,
         output_token_mint,
         amount_in,
         swap_for_y,
-        min_amount_out
+        min_amount_out,
+        min_context_slot
     } = params;
     // info!("RAYDIUM CRAFT SWAP INSTRUCTION !");
 
     let mut swap_instructions: Vec<InstructionDetails> = Vec::new();
     let env = Env::new();
-    let payer = read_keypair_file(env.payer_keypair_path).expect("Wallet keypair file not found");
     
     let amm_program = from_str("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8").unwrap();
     //Devnet : HWy1jotHpo6UqeQxx49dpYYdQB8wj9Qk9MdxwjLvDHB8
     //Mainnet : 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8
     
     let rpc_client: RpcClient = RpcClient::new(env.rpc_url);
-    let pool_account: solana_sdk::account::Account = rpc_client.get_account(&pool).unwrap();
+    let pool_account: solana_sdk::account::Account = match get_account_with_min_slot(&rpc_client, &pool, min_context_slot) {
+        Ok(account) => account,
+        Err(e) => {
+            error!("❌ Raydium pool account not fetched: {:?}", e);
+            return swap_instructions
+        }
+    };
     // println!("Params data length: {:?}", pool_account.data.len());
     let pool_state = AmmInfo::try_from_slice(&pool_account.data).unwrap();
     // println!("min_amount_out: {:?}", min_amount_out);
@@ -75,7 +81,7 @@ pub fn construct_raydium_instructions(params: SwapParametersRaydium) -> Vec<Inst
     ).unwrap();
 
     let pda_user_source = get_associated_token_address(
-        &payer.pubkey(),
+        &payer,
         &input_token_mint,
     );
     match rpc_client.get_account(&pda_user_source) {
@@ -86,7 +92,7 @@ pub fn construct_raydium_instructions(params: SwapParametersRaydium) -> Vec<Inst
     }
 
     let pda_user_destination = get_associated_token_address(
-        &payer.pubkey(),
+        &payer,
         &output_token_mint,
     );
 
@@ -117,7 +123,7 @@ pub fn construct_raydium_instructions(params: SwapParametersRaydium) -> Vec<Inst
         &market_keys.vault_signer_key,
         &pda_user_source,
         &pda_user_destination,
-        &payer.pubkey(),
+        &payer,
         amount_in,
         min_amount_out,
     ).expect("Error in Raydium swap instruction construction");
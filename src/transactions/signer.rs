@@ -0,0 +1,199 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use aes_gcm_siv::aead::{Aead, NewAead};
+use aes_gcm_siv::{Aes256GcmSiv, Key, Nonce};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hmac::Hmac;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
+use solana_sdk::signer::SignerError;
+
+use crate::common::constants::{get_env, Env};
+
+const KEYSTORE_ITERATIONS: u32 = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignerSource {
+    File(String),
+    // Keypair encrypted with the KEYSTORE_PASSWORD environment variable
+    Keystore(String),
+    // Signer process listening on a local Unix socket, the private key never enters the bot
+    Remote(String),
+}
+
+impl SignerSource {
+    // PAYER_SIGNER: file (default), keystore or remote
+    pub fn payer_from_env(env: &Env) -> Result<Self> {
+        match env.payer_signer.as_str() {
+            "" | "file" => Ok(SignerSource::File(env.payer_keypair_path.clone())),
+            "keystore" => Ok(SignerSource::Keystore(env.payer_keystore_path.clone())),
+            "remote" => Ok(SignerSource::Remote(env.payer_remote_signer_socket.clone())),
+            signer => Err(anyhow::format_err!("Unknown PAYER_SIGNER: {}", signer)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub pubkey: String,
+    pub salt: String,
+    pub nonce: String,
+    pub iterations: u32,
+    pub ciphertext: String,
+}
+
+fn derive_keystore_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+pub fn encrypt_keystore(keypair: &Keypair, password: &str) -> Result<Keystore> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let key = derive_keystore_key(password, &salt, KEYSTORE_ITERATIONS);
+    let ciphertext = Aes256GcmSiv::new(Key::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), keypair.to_bytes().as_ref())
+        .map_err(|_| anyhow::format_err!("Keystore encryption failed"))?;
+    Ok(Keystore {
+        pubkey: keypair.pubkey().to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        iterations: KEYSTORE_ITERATIONS,
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+pub fn decrypt_keystore(keystore: &Keystore, password: &str) -> Result<Keypair> {
+    let key = derive_keystore_key(password, &STANDARD.decode(&keystore.salt)?, keystore.iterations);
+    let secret = Aes256GcmSiv::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(&STANDARD.decode(&keystore.nonce)?), STANDARD.decode(&keystore.ciphertext)?.as_ref())
+        .map_err(|_| anyhow::format_err!("Keystore decryption failed, wrong password?"))?;
+    let keypair = Keypair::from_bytes(&secret)?;
+    if keypair.pubkey().to_string() != keystore.pubkey {
+        return Err(anyhow::format_err!("Keystore pubkey {} doesn't match its keypair", keystore.pubkey));
+    }
+    Ok(keypair)
+}
+
+pub fn write_keystore(path: &str, keypair: &Keypair, password: &str) -> Result<()> {
+    fs::write(path, serde_json::to_string(&encrypt_keystore(keypair, password)?)?)?;
+    Ok(())
+}
+
+pub fn read_keystore(path: &str, password: &str) -> Result<Keypair> {
+    let keystore: Keystore = serde_json::from_str(&fs::read_to_string(path)?)?;
+    decrypt_keystore(&keystore, password)
+}
+
+// One JSON request per line on the socket:
+// {"method": "getPubkey"} -> {"pubkey": base58}, {"method": "signMessage", "message": base64} -> {"signature": base58}
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    pub socket_path: String,
+    pubkey: Pubkey,
+}
+
+impl RemoteSigner {
+    pub fn connect(socket_path: String) -> Result<Self> {
+        let response = Self::request(&socket_path, json!({ "method": "getPubkey" }))?;
+        let pubkey = Pubkey::from_str(response["pubkey"].as_str().unwrap_or_default())?;
+        Ok(RemoteSigner { socket_path, pubkey })
+    }
+
+    fn request(socket_path: &String, request: Value) -> Result<Value> {
+        let mut stream = UnixStream::connect(socket_path)?;
+        stream.write_all(format!("{}\n", request).as_bytes())?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let response: Value = serde_json::from_str(&line)?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::format_err!("Remote signer error: {}", error));
+        }
+        Ok(response)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let response = Self::request(&self.socket_path, json!({ "method": "signMessage", "message": STANDARD.encode(message) }))
+            .map_err(|e| SignerError::Connection(e.to_string()))?;
+        let signature = Signature::from_str(response["signature"].as_str().unwrap_or_default())
+            .map_err(|e| SignerError::Custom(format!("Bad remote signature: {:?}", e)))?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Custom(format!("Remote signature not made by {}", self.pubkey)));
+        }
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+pub fn load_signer(source: &SignerSource) -> Result<Arc<dyn Signer + Send + Sync>> {
+    match source {
+        SignerSource::File(path) => Ok(Arc::new(read_keypair_file(path).map_err(|e| anyhow::format_err!("Keypair file {} not read: {:?}", path, e))?)),
+        SignerSource::Keystore(path) => Ok(Arc::new(read_keystore(path, &get_env("KEYSTORE_PASSWORD"))?)),
+        SignerSource::Remote(socket_path) => Ok(Arc::new(RemoteSigner::connect(socket_path.clone())?)),
+    }
+}
+
+// Signers of the transaction layer: the payer owns the token accounts and signs the swaps,
+// the fee payer pays the transaction fees (the payer when not set)
+#[derive(Clone)]
+pub struct SignerService {
+    payer: Arc<dyn Signer + Send + Sync>,
+    fee_payer: Arc<dyn Signer + Send + Sync>,
+}
+
+impl SignerService {
+    pub fn new(payer: Arc<dyn Signer + Send + Sync>, fee_payer: Option<Arc<dyn Signer + Send + Sync>>) -> Self {
+        let fee_payer = fee_payer.unwrap_or(payer.clone());
+        SignerService { payer, fee_payer }
+    }
+
+    // PAYER_SIGNER for the payer, FEE_PAYER_KEYPAIR_PATH for a separate fee payer
+    pub fn from_env() -> Result<Self> {
+        let env = Env::new();
+        let payer = load_signer(&SignerSource::payer_from_env(&env)?)?;
+        let fee_payer = if env.fee_payer_keypair_path.is_empty() {
+            None
+        } else {
+            Some(load_signer(&SignerSource::File(env.fee_payer_keypair_path.clone()))?)
+        };
+        Ok(Self::new(payer, fee_payer))
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    pub fn fee_payer(&self) -> Pubkey {
+        self.fee_payer.pubkey()
+    }
+
+    // Fee payer first, the payer only if it is another account
+    pub fn signers(&self) -> Vec<&dyn Signer> {
+        let mut signers: Vec<&dyn Signer> = vec![self.fee_payer.as_ref()];
+        if self.payer() != self.fee_payer() {
+            signers.push(self.payer.as_ref());
+        }
+        signers
+    }
+}
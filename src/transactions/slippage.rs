@@ -0,0 +1,67 @@
+use crate::arbitrage::types::SwapPathResult;
+use crate::transactions::profit_guard::PROFIT_GUARD_MIN_PROFIT;
+
+// minimum_amount_out of the swaps of a cycle
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlippagePolicy {
+    // Every hop at most `bps` under its estimated amount out
+    PerHop { bps: u64 },
+    // Intermediate hops at the estimated min amount out of the simulation (else `bps`), the final leg at most `bps` under its estimation
+    FinalLeg { bps: u64 },
+    // Intermediate hops at the estimated min amount out, the final leg must give back amount in + `min_profit`
+    RequiredProfit { min_profit: u64 },
+}
+
+impl Default for SlippagePolicy {
+    fn default() -> Self {
+        SlippagePolicy::FinalLeg { bps: 50 }
+    }
+}
+
+impl SlippagePolicy {
+    // Min. profit checked by the profit guard at the end of the transaction
+    pub fn guard_min_profit(&self) -> u64 {
+        match self {
+            SlippagePolicy::RequiredProfit { min_profit } => *min_profit,
+            _ => PROFIT_GUARD_MIN_PROFIT,
+        }
+    }
+}
+
+fn apply_bps(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * 10000u128.saturating_sub(bps as u128) / 10000) as u64
+}
+
+// Slippage of the intermediate hops when the simulator has no min for the DEX and the policy no bps
+const DEFAULT_INTERMEDIATE_BPS: u64 = 50;
+
+// (amount_in, minimum_amount_out) of each route simulation of the path
+// A hop after the first spends the minimum_amount_out of the previous one, so a small shortfall on an intermediate hop
+// never leaves the next one without funds. Its estimated amount out is scaled down to this amount in (conservative on a
+// curve), and the tokens received over the minimum stay in the wallet
+pub fn get_swap_amounts(transaction_infos: &SwapPathResult, policy: &SlippagePolicy) -> Vec<(u64, u64)> {
+    let hops = transaction_infos.route_simulations.len();
+    let mut swap_amounts: Vec<(u64, u64)> = Vec::new();
+    let mut amount_in = match transaction_infos.route_simulations.first() {
+        Some(route_sim) => route_sim.amount_in,
+        None => return swap_amounts,
+    };
+    for (i, route_sim) in transaction_infos.route_simulations.iter().enumerate() {
+        let scale = |amount: u64| (amount as u128 * amount_in as u128 / route_sim.amount_in.max(1) as u128) as u64;
+        let estimated_amount_out = scale(route_sim.estimated_amount_out.parse().unwrap_or(0));
+        // The simulator min, or 0 when it has no min for this DEX
+        let estimated_min_amount_out = scale(route_sim.estimated_min_amount_out.parse().unwrap_or(0)).min(estimated_amount_out);
+        let intermediate_min = |bps: u64| if estimated_min_amount_out > 0 { estimated_min_amount_out } else { apply_bps(estimated_amount_out, bps) };
+        let is_final_leg = i + 1 == hops;
+        let minimum_amount_out = match policy {
+            SlippagePolicy::PerHop { bps } => apply_bps(estimated_amount_out, *bps),
+            SlippagePolicy::FinalLeg { bps } if is_final_leg => apply_bps(estimated_amount_out, *bps),
+            SlippagePolicy::FinalLeg { bps } => intermediate_min(*bps),
+            SlippagePolicy::RequiredProfit { min_profit } if is_final_leg => transaction_infos.amount_in.saturating_add(*min_profit),
+            SlippagePolicy::RequiredProfit { .. } => intermediate_min(DEFAULT_INTERMEDIATE_BPS),
+        };
+        swap_amounts.push((amount_in, minimum_amount_out));
+        amount_in = minimum_amount_out;
+    }
+    swap_amounts
+}
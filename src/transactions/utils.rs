@@ -1,6 +1,7 @@
 use log::{error, info};
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
 use anyhow::{format_err, Result};
 use std::{
     borrow::Cow,
@@ -49,7 +50,7 @@ pub async fn check_tx_status(commitment_config: CommitmentConfig, chain: ChainTy
         }
         let ten_secs = Duration::from_secs(10);
         info!("⏳ {} seconds...", 10 * counter);
-        sleep(ten_secs);
+        tokio::time::sleep(ten_secs).await;
         counter += 1;
     }
 }
@@ -166,4 +167,15 @@ pub fn average(numbers: Vec<u64>) -> u64 {
     let sum: u64 = numbers.iter().sum();
     let count = numbers.len() as u64;
     sum / count
-}
\ No newline at end of file
+}
+
+// Get an account not older than min_context_slot (the slot of the simulation) when the RPC support it
+pub fn get_account_with_min_slot(rpc_client: &RpcClient, pubkey: &Pubkey, min_context_slot: Option<u64>) -> Result<Account> {
+    let response = rpc_client.get_account_with_config(pubkey, RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice: None,
+        commitment: Some(CommitmentConfig::confirmed()),
+        min_context_slot: min_context_slot,
+    })?;
+    response.value.ok_or(format_err!("Account {} not found", pubkey))
+}
@@ -0,0 +1,41 @@
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::spl_token;
+use anyhow::Result;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+
+use crate::transactions::create_transaction::InstructionDetails;
+
+pub const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+// Native SOL kept out of the wrap: rent of the wSOL account and the fees of the transaction
+pub const WRAP_SOL_RESERVE_LAMPORTS: u64 = 10_000_000;
+
+// Create the wSOL account if missing, move `lamports` in it and sync its token amount
+pub fn construct_wrap_sol_instructions(payer: Pubkey, lamports: u64) -> Vec<InstructionDetails> {
+    let wsol_mint = spl_token::native_mint::id();
+    let wsol_account = get_associated_token_address(&payer, &wsol_mint);
+
+    let create_account = create_associated_token_account_idempotent(&payer, &payer, &wsol_mint, &spl_token::id());
+    let transfer = system_instruction::transfer(&payer, &wsol_account, lamports);
+    let sync_native = spl_token::instruction::sync_native(&spl_token::id(), &wsol_account).unwrap();
+    vec![
+        InstructionDetails{ instruction: create_account, details: "Wrap SOL: create wSOL account".to_string(), market: None },
+        InstructionDetails{ instruction: transfer, details: "Wrap SOL: transfer".to_string(), market: None },
+        InstructionDetails{ instruction: sync_native, details: "Wrap SOL: sync native".to_string(), market: None },
+    ]
+}
+
+// Close the wSOL account, all its lamports (wSOL and rent) go back to the payer
+pub fn construct_unwrap_sol_instruction(payer: Pubkey) -> InstructionDetails {
+    let wsol_account = get_associated_token_address(&payer, &spl_token::native_mint::id());
+    let close_account = spl_token::instruction::close_account(&spl_token::id(), &wsol_account, &payer, &payer, &[]).unwrap();
+    InstructionDetails{ instruction: close_account, details: "Unwrap SOL: close wSOL account".to_string(), market: None }
+}
+
+// Max amount in of a cycle starting in SOL: the native balance without the reserve
+pub fn get_max_wrap_amount(rpc_client: &RpcClient, payer: &Pubkey) -> Result<u64> {
+    let balance = rpc_client.get_balance(payer)?;
+    Ok(balance.saturating_sub(WRAP_SOL_RESERVE_LAMPORTS))
+}